@@ -0,0 +1,162 @@
+//! Benchmarks for mixing the outputs of many child sources.
+//!
+//! Each pair of benchmarks mixes the same samples with a scalar fold and with the SIMD mixing
+//! functions, at several numbers of children, to find where SIMD starts paying off.
+
+#![feature(test)]
+
+extern crate test;
+
+use rodio_scheduler::simd;
+use test::{Bencher, black_box};
+
+/// The number of output samples mixed by every benchmark iteration.
+const SAMPLES: usize = 1024;
+
+/// Creates the outputs of `children` child sources for one sample.
+fn child_samples(children: usize) -> Vec<f32> {
+    (0..children).map(|child| child as f32 / 100.0).collect()
+}
+
+fn bench_fold(b: &mut Bencher, children: usize) {
+    let samples = child_samples(children);
+
+    b.iter(|| {
+        (0..SAMPLES)
+            .map(|_| {
+                black_box(&samples)
+                    .iter()
+                    .fold(0.0, |mix, sample| mix + sample)
+            })
+            .sum::<f32>()
+    });
+}
+
+fn bench_sum_samples(b: &mut Bencher, children: usize) {
+    let samples = child_samples(children);
+
+    b.iter(|| {
+        (0..SAMPLES)
+            .map(|_| simd::sum_samples(black_box(&samples)))
+            .sum::<f32>()
+    });
+}
+
+#[bench]
+fn fold_2_children(b: &mut Bencher) {
+    bench_fold(b, 2);
+}
+
+#[bench]
+fn sum_samples_2_children(b: &mut Bencher) {
+    bench_sum_samples(b, 2);
+}
+
+#[bench]
+fn fold_4_children(b: &mut Bencher) {
+    bench_fold(b, 4);
+}
+
+#[bench]
+fn sum_samples_4_children(b: &mut Bencher) {
+    bench_sum_samples(b, 4);
+}
+
+#[bench]
+fn fold_8_children(b: &mut Bencher) {
+    bench_fold(b, 8);
+}
+
+#[bench]
+fn sum_samples_8_children(b: &mut Bencher) {
+    bench_sum_samples(b, 8);
+}
+
+#[bench]
+fn fold_20_children(b: &mut Bencher) {
+    bench_fold(b, 20);
+}
+
+#[bench]
+fn sum_samples_20_children(b: &mut Bencher) {
+    bench_sum_samples(b, 20);
+}
+
+#[bench]
+fn fold_60_children(b: &mut Bencher) {
+    bench_fold(b, 60);
+}
+
+#[bench]
+fn sum_samples_60_children(b: &mut Bencher) {
+    bench_sum_samples(b, 60);
+}
+
+/// Mixes a block of every child into the output, with a different gain for each channel, one
+/// sample at a time.
+fn bench_block_scalar(b: &mut Bencher, children: usize) {
+    let child = vec![0.5; SAMPLES];
+    let gains = [0.25, 0.75];
+    let mut output = vec![0.0; SAMPLES];
+
+    b.iter(|| {
+        output.fill(0.0);
+
+        for _ in 0..children {
+            let pattern = gains.iter().cycle();
+            for ((output, sample), gain) in output.iter_mut().zip(black_box(&child)).zip(pattern) {
+                *output += sample * gain;
+            }
+        }
+
+        output[0]
+    });
+}
+
+/// Mixes a block of every child into the output, with a different gain for each channel, with
+/// `simd::mix_samples_with_gains`.
+fn bench_block_simd(b: &mut Bencher, children: usize) {
+    let child = vec![0.5; SAMPLES];
+    let gains = [0.25, 0.75, 0.25, 0.75, 0.25, 0.75, 0.25, 0.75];
+    let mut output = vec![0.0; SAMPLES];
+
+    b.iter(|| {
+        output.fill(0.0);
+
+        for _ in 0..children {
+            simd::mix_samples_with_gains(&mut output, black_box(&child), gains);
+        }
+
+        output[0]
+    });
+}
+
+#[bench]
+fn block_scalar_4_children(b: &mut Bencher) {
+    bench_block_scalar(b, 4);
+}
+
+#[bench]
+fn block_simd_4_children(b: &mut Bencher) {
+    bench_block_simd(b, 4);
+}
+
+#[bench]
+fn block_scalar_20_children(b: &mut Bencher) {
+    bench_block_scalar(b, 20);
+}
+
+#[bench]
+fn block_simd_20_children(b: &mut Bencher) {
+    bench_block_simd(b, 20);
+}
+
+#[bench]
+fn block_scalar_60_children(b: &mut Bencher) {
+    bench_block_scalar(b, 60);
+}
+
+#[bench]
+fn block_simd_60_children(b: &mut Bencher) {
+    bench_block_simd(b, 60);
+}
@@ -0,0 +1,246 @@
+//! Benchmarks for rendering a scheduler with many playing voices.
+//!
+//! Each benchmark renders the same second of audio, either one sample at a time through the
+//! iterator or in blocks through `fill_buffer`.
+//!
+//! The `scheduler_next_*` benchmarks render a `Scheduler` with a growing number of children.
+//! Sources are rendered into a block ahead of the output, each adding its events as contiguous
+//! slices, while streams are summed with the block one sample at a time. Run the `_streams`
+//! benchmarks with and without the `simd` feature to find where summing the children with SIMD
+//! breaks even against the scalar fold, and compare them with the `_sources` ones:
+//!
+//! ```text
+//! cargo bench --bench rendering scheduler_next
+//! cargo bench --bench rendering --no-default-features scheduler_next
+//! ```
+
+#![feature(test)]
+
+extern crate test;
+
+use rodio::buffer::SamplesBuffer;
+use rodio_scheduler::{PlaybackEvent, SampleBuffer, Scheduler, SingleSourceScheduler};
+use test::Bencher;
+
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+const BLOCK_SIZE: usize = 1024;
+
+/// Creates a long sound buffer, so every event keeps playing until the end of the benchmark.
+fn pad() -> SampleBuffer {
+    let samples = vec![0.5; SAMPLE_RATE as usize * CHANNELS as usize];
+
+    SampleBuffer::new(
+        SamplesBuffer::new(CHANNELS, SAMPLE_RATE, samples),
+        SAMPLE_RATE,
+        CHANNELS,
+    )
+}
+
+/// Creates a `SingleSourceScheduler` with `voices` events that start in the first 100 frames and
+/// play until the end of the benchmark.
+fn busy_scheduler(voices: u64) -> SingleSourceScheduler {
+    let mut scheduler = SingleSourceScheduler::from_buffer(pad());
+
+    let events = (0..voices).map(|voice| PlaybackEvent {
+        gain: Some(1.0 / voices as f32),
+        ..PlaybackEvent::at_frame(0, voice * 100 / voices)
+    });
+    scheduler.schedule_events(events).unwrap();
+
+    scheduler
+}
+
+/// Creates a `Scheduler` with 4 sources, each playing `voices` events.
+fn busy_mixer(voices: u64) -> Scheduler<rodio::source::Zero> {
+    let mut scheduler = Scheduler::new(
+        rodio::source::Zero::new(CHANNELS, SAMPLE_RATE),
+        SAMPLE_RATE,
+        CHANNELS,
+    );
+    let buffer = pad();
+
+    for source_id in 0..4 {
+        scheduler.add_shared_source(buffer.clone());
+
+        let events = (0..voices).map(|voice| PlaybackEvent::at_frame(source_id, voice));
+        scheduler.schedule_many(events).unwrap();
+    }
+
+    scheduler
+}
+
+fn bench_next(b: &mut Bencher, voices: u64) {
+    b.iter(|| {
+        let mut scheduler = busy_scheduler(voices);
+
+        (0..SAMPLE_RATE as usize * CHANNELS as usize)
+            .map(|_| scheduler.next().unwrap_or_default())
+            .sum::<f32>()
+    });
+}
+
+fn bench_fill_buffer(b: &mut Bencher, voices: u64) {
+    let mut block = vec![0.0; BLOCK_SIZE];
+
+    b.iter(|| {
+        let mut scheduler = busy_scheduler(voices);
+
+        (0..SAMPLE_RATE as usize * CHANNELS as usize / BLOCK_SIZE)
+            .map(|_| {
+                scheduler.fill_buffer(&mut block);
+
+                block.iter().sum::<f32>()
+            })
+            .sum::<f32>()
+    });
+}
+
+#[bench]
+fn single_source_next_16_voices(b: &mut Bencher) {
+    bench_next(b, 16);
+}
+
+#[bench]
+fn single_source_fill_buffer_16_voices(b: &mut Bencher) {
+    bench_fill_buffer(b, 16);
+}
+
+#[bench]
+fn single_source_next_64_voices(b: &mut Bencher) {
+    bench_next(b, 64);
+}
+
+#[bench]
+fn single_source_fill_buffer_64_voices(b: &mut Bencher) {
+    bench_fill_buffer(b, 64);
+}
+
+/// Renders the mixer through its iterator, which renders the sources a block ahead.
+#[bench]
+fn scheduler_next_4x16_voices(b: &mut Bencher) {
+    b.iter(|| {
+        let mut scheduler = busy_mixer(16);
+
+        (0..SAMPLE_RATE as usize * CHANNELS as usize)
+            .map(|_| scheduler.next().unwrap())
+            .sum::<f32>()
+    });
+}
+
+#[bench]
+fn scheduler_fill_buffer_4x16_voices(b: &mut Bencher) {
+    let mut block = vec![0.0; BLOCK_SIZE];
+
+    b.iter(|| {
+        let mut scheduler = busy_mixer(16);
+
+        (0..SAMPLE_RATE as usize * CHANNELS as usize / BLOCK_SIZE)
+            .map(|_| {
+                scheduler.fill_buffer(&mut block);
+
+                block.iter().sum::<f32>()
+            })
+            .sum::<f32>()
+    });
+}
+
+/// Creates a `Scheduler` with `sources` sources, such as the sounds of a drum kit, each playing
+/// one event that lasts until the end of the benchmark.
+fn many_sources(sources: usize) -> Scheduler<rodio::source::Zero> {
+    let mut scheduler = Scheduler::new(
+        rodio::source::Zero::new(CHANNELS, SAMPLE_RATE),
+        SAMPLE_RATE,
+        CHANNELS,
+    );
+    let buffer = pad();
+
+    for _ in 0..sources {
+        scheduler.add_shared_source(buffer.clone());
+    }
+
+    let events = (0..sources).map(|source_id| PlaybackEvent::at_frame(source_id, source_id as u64));
+    scheduler.schedule_many(events).unwrap();
+
+    scheduler
+}
+
+/// Creates a `Scheduler` with `streams` streaming sources, each playing one event that lasts
+/// until the end of the benchmark. Streams are mixed one sample at a time, so this measures the
+/// cost of mixing the children of `Scheduler::next`.
+fn many_streams(streams: usize) -> Scheduler<rodio::source::Zero> {
+    let mut scheduler = Scheduler::new(
+        rodio::source::Zero::new(CHANNELS, SAMPLE_RATE),
+        SAMPLE_RATE,
+        CHANNELS,
+    );
+
+    for _ in 0..streams {
+        let stream_id = scheduler
+            .add_streaming_source(|| {
+                let samples = vec![0.5; SAMPLE_RATE as usize * CHANNELS as usize];
+
+                SamplesBuffer::new(CHANNELS, SAMPLE_RATE, samples)
+            })
+            .unwrap();
+        scheduler
+            .schedule(PlaybackEvent::at_frame(stream_id, 0))
+            .unwrap();
+    }
+
+    scheduler
+}
+
+/// Renders a second of a scheduler through its iterator.
+fn bench_scheduler_next<I>(b: &mut Bencher, scheduler: impl Fn() -> Scheduler<I>)
+where
+    I: rodio::Source,
+{
+    b.iter(|| {
+        let mut scheduler = scheduler();
+
+        (0..SAMPLE_RATE as usize * CHANNELS as usize)
+            .map(|_| scheduler.next().unwrap())
+            .sum::<f32>()
+    });
+}
+
+#[bench]
+fn scheduler_next_1_source(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_sources(1));
+}
+
+#[bench]
+fn scheduler_next_4_sources(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_sources(4));
+}
+
+#[bench]
+fn scheduler_next_20_sources(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_sources(20));
+}
+
+#[bench]
+fn scheduler_next_60_sources(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_sources(60));
+}
+
+#[bench]
+fn scheduler_next_1_stream(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_streams(1));
+}
+
+#[bench]
+fn scheduler_next_4_streams(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_streams(4));
+}
+
+#[bench]
+fn scheduler_next_20_streams(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_streams(20));
+}
+
+#[bench]
+fn scheduler_next_60_streams(b: &mut Bencher) {
+    bench_scheduler_next(b, || many_streams(60));
+}
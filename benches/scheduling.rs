@@ -0,0 +1,157 @@
+//! Benchmarks for loading charts into a scheduler.
+//!
+//! The charts are loaded in bulk at several sizes, so the time per note can be compared across
+//! them: it should stay about the same as the charts grow.
+
+#![feature(test)]
+
+extern crate test;
+
+use rodio::buffer::SamplesBuffer;
+use rodio_scheduler::{PlaybackEvent, SampleBuffer, Scheduler, SingleSourceScheduler};
+use test::Bencher;
+
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+
+/// Creates a short hitsound buffer.
+fn hitsound() -> SampleBuffer {
+    let samples = vec![0.5; 2400 * CHANNELS as usize];
+
+    SampleBuffer::new(
+        SamplesBuffer::new(CHANNELS, SAMPLE_RATE, samples),
+        SAMPLE_RATE,
+        CHANNELS,
+    )
+}
+
+/// Creates a chart of `notes` notes, spread over `sources` sources.
+///
+/// The notes are in order, and every 4 notes form a chord that starts on the same frame. Every
+/// 8th chord is written after the next one, like a chart edited by hand.
+fn chart(notes: u64, sources: usize) -> Vec<PlaybackEvent> {
+    (0..notes)
+        .map(|note| {
+            let chord = note / 4;
+            let chord = match chord % 8 {
+                6 => chord + 1,
+                7 => chord - 1,
+                _ => chord,
+            };
+
+            PlaybackEvent::at_frame(note as usize % sources, chord * 100)
+        })
+        .collect()
+}
+
+/// Shuffles a chart with a fixed seed, like a chart generated out of order.
+fn shuffle(mut chart: Vec<PlaybackEvent>) -> Vec<PlaybackEvent> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+
+    for index in (1..chart.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+
+        chart.swap(index, (state % (index as u64 + 1)) as usize);
+    }
+
+    chart
+}
+
+fn bench_schedule_events(b: &mut Bencher, chart: Vec<PlaybackEvent>) {
+    let buffer = hitsound();
+
+    b.iter(|| {
+        let mut scheduler = SingleSourceScheduler::from_buffer(buffer.clone());
+
+        scheduler.schedule_events(chart.iter().copied()).unwrap()
+    });
+}
+
+fn bench_schedule_many(b: &mut Bencher, chart: Vec<PlaybackEvent>) {
+    let buffer = hitsound();
+
+    b.iter(|| {
+        let mut scheduler = Scheduler::new(
+            rodio::source::Zero::new(CHANNELS, SAMPLE_RATE),
+            SAMPLE_RATE,
+            CHANNELS,
+        );
+        for _ in 0..4 {
+            scheduler.add_shared_source(buffer.clone());
+        }
+
+        scheduler.schedule_many(chart.iter().copied()).unwrap()
+    });
+}
+
+#[bench]
+fn schedule_events_1000(b: &mut Bencher) {
+    bench_schedule_events(b, chart(1000, 1));
+}
+
+#[bench]
+fn schedule_events_5000(b: &mut Bencher) {
+    bench_schedule_events(b, chart(5000, 1));
+}
+
+#[bench]
+fn schedule_events_20000(b: &mut Bencher) {
+    bench_schedule_events(b, chart(20000, 1));
+}
+
+#[bench]
+fn schedule_many_1000(b: &mut Bencher) {
+    bench_schedule_many(b, chart(1000, 4));
+}
+
+#[bench]
+fn schedule_many_5000(b: &mut Bencher) {
+    bench_schedule_many(b, chart(5000, 4));
+}
+
+#[bench]
+fn schedule_many_20000(b: &mut Bencher) {
+    bench_schedule_many(b, chart(20000, 4));
+}
+
+#[bench]
+fn schedule_events_shuffled_5000(b: &mut Bencher) {
+    bench_schedule_events(b, shuffle(chart(5000, 1)));
+}
+
+#[bench]
+fn schedule_events_shuffled_20000(b: &mut Bencher) {
+    bench_schedule_events(b, shuffle(chart(20000, 1)));
+}
+
+fn bench_schedule_event_one_by_one(b: &mut Bencher, chart: Vec<PlaybackEvent>) {
+    let buffer = hitsound();
+
+    b.iter(|| {
+        let mut scheduler = SingleSourceScheduler::from_buffer(buffer.clone());
+
+        for event in chart.iter() {
+            scheduler.schedule_event(*event).unwrap();
+        }
+    });
+}
+
+/// Schedules the chart one event at a time, for comparison.
+#[bench]
+fn schedule_event_one_by_one_5000(b: &mut Bencher) {
+    bench_schedule_event_one_by_one(b, chart(5000, 1));
+}
+
+/// Schedules a shuffled chart one event at a time, which shifts half of the schedule on every
+/// event.
+#[bench]
+fn schedule_event_one_by_one_shuffled_5000(b: &mut Bencher) {
+    bench_schedule_event_one_by_one(b, shuffle(chart(5000, 1)));
+}
+
+#[bench]
+fn schedule_event_one_by_one_shuffled_20000(b: &mut Bencher) {
+    bench_schedule_event_one_by_one(b, shuffle(chart(20000, 1)));
+}
@@ -0,0 +1,184 @@
+//! This module provides automation lanes, used to change a parameter on exact frames.
+//!
+//! An [`AutomationLane`] is a list of points, each of which either holds its value until the next
+//! point, or ramps towards it. Lanes are evaluated on the same frames as scheduled events, so a
+//! fade or a crescendo stays locked to the events it shapes, even after seeking.
+
+use crate::SampleType;
+
+/// The lowest value an [`AutomationCurve::Exponential`] ramp starts from or ramps to, about
+/// -80 dB. Ramps from or to silence use it instead of zero, but are still silent on the point
+/// itself.
+pub const EXPONENTIAL_FLOOR: f32 = 1e-4;
+
+/// How the value changes between a point and the next one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AutomationCurve {
+    /// The value is held until the next point, and jumps to it.
+    #[default]
+    Step,
+
+    /// The value ramps linearly towards the value of the next point.
+    Linear,
+
+    /// The value ramps towards the value of the next point by a constant ratio every frame,
+    /// which sounds even for gains. See [`EXPONENTIAL_FLOOR`] for ramps from or to zero.
+    Exponential,
+}
+
+/// A point of an `AutomationLane`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutomationPoint {
+    /// The frame the value is reached on.
+    pub frame: SampleType,
+
+    /// The value of the parameter on `frame`.
+    pub value: f32,
+
+    /// How the value changes from this point to the next one.
+    pub curve: AutomationCurve,
+}
+
+/// A list of points that set the value of a parameter over time.
+///
+/// Before the first point, the value of the first point is used, and after the last point, its
+/// value is held. A lane without points has no value, so the parameter is left untouched.
+///
+/// # Example
+///
+/// ```
+/// use rodio_scheduler::automation::{AutomationCurve, AutomationLane};
+///
+/// let mut lane = AutomationLane::new();
+///
+/// // Fade out over one second at 48 kHz, starting two seconds in.
+/// lane.set_point(2 * 48000, 1.0, AutomationCurve::Linear);
+/// lane.set_point(3 * 48000, 0.0, AutomationCurve::Step);
+///
+/// assert_eq!(lane.value_at(0), Some(1.0));
+/// assert_eq!(lane.value_at(2 * 48000 + 24000), Some(0.5));
+/// assert_eq!(lane.value_at(4 * 48000), Some(0.0));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AutomationLane {
+    /// The points of the lane, sorted by frame, with at most one point on every frame.
+    points: Vec<AutomationPoint>,
+}
+
+impl AutomationLane {
+    /// Creates a new `AutomationLane` without points.
+    #[inline]
+    pub fn new() -> AutomationLane {
+        AutomationLane::default()
+    }
+
+    /// Sets the value of the lane on a frame, replacing any point already on that frame.
+    ///
+    /// Values that are not finite are ignored.
+    pub fn set_point(&mut self, frame: SampleType, value: f32, curve: AutomationCurve) {
+        if !value.is_finite() {
+            return;
+        }
+
+        let point = AutomationPoint {
+            frame,
+            value,
+            curve,
+        };
+
+        let index = self.points.partition_point(|point| point.frame < frame);
+        match self.points.get_mut(index) {
+            Some(existing) if existing.frame == frame => *existing = point,
+            _ => self.points.insert(index, point),
+        }
+    }
+
+    /// Removes the point on a frame. Returns `true` if there was one.
+    pub fn remove_point(&mut self, frame: SampleType) -> bool {
+        match self
+            .points
+            .binary_search_by_key(&frame, |point| point.frame)
+        {
+            Ok(index) => {
+                self.points.remove(index);
+
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Removes every point of the lane.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Returns the points of the lane, sorted by frame.
+    #[inline]
+    pub fn points(&self) -> &[AutomationPoint] {
+        &self.points
+    }
+
+    /// Returns `true` if the lane has no points.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Returns the value of the lane on a frame, or `None` if it has no points.
+    #[inline]
+    pub fn value_at(&self, frame: SampleType) -> Option<f32> {
+        let index = self.points.partition_point(|point| point.frame <= frame);
+
+        let Some(previous) = index.checked_sub(1).map(|index| &self.points[index]) else {
+            return self.points.first().map(|point| point.value);
+        };
+        let Some(next) = self.points.get(index) else {
+            return Some(previous.value);
+        };
+
+        // The next point is after `frame`, so the segment is never empty.
+        let position = (frame - previous.frame) as f64 / (next.frame - previous.frame) as f64;
+
+        Some(interpolate(previous, next.value, position))
+    }
+
+    /// Returns whether the value changes between the given frame and the next point, and the
+    /// frame of that point, or `SampleType::MAX` if there is none.
+    #[inline]
+    pub(crate) fn segment_at(&self, frame: SampleType) -> (bool, SampleType) {
+        let index = self.points.partition_point(|point| point.frame <= frame);
+
+        match (index.checked_sub(1), self.points.get(index)) {
+            (Some(previous), Some(next)) => {
+                let previous = &self.points[previous];
+                let changes =
+                    previous.curve != AutomationCurve::Step && previous.value != next.value;
+
+                (changes, next.frame)
+            }
+            (None, Some(next)) => (false, next.frame),
+            (_, None) => (false, SampleType::MAX),
+        }
+    }
+}
+
+/// Returns the value `position` of the way from a point to the value of the next one, where
+/// `position` is in `[0.0, 1.0)`.
+#[inline]
+fn interpolate(point: &AutomationPoint, next: f32, position: f64) -> f32 {
+    match point.curve {
+        AutomationCurve::Step => point.value,
+        AutomationCurve::Linear => {
+            (point.value as f64 + (next as f64 - point.value as f64) * position) as f32
+        }
+        AutomationCurve::Exponential if position == 0.0 => point.value,
+        AutomationCurve::Exponential => {
+            let from = point.value.max(EXPONENTIAL_FLOOR) as f64;
+            let to = next.max(EXPONENTIAL_FLOOR) as f64;
+
+            (from * (to / from).powf(position)) as f32
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! This module provides a decoded audio buffer that can be shared between schedulers.
+
+use std::io::{Read, Seek};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::buffer::SamplesBuffer;
+use rodio::source::{Source, UniformSourceIterator};
+use rodio::{Decoder, Sample};
+
+use crate::{SchedulerError, time};
+
+/// A decoded and resampled audio source, ready to be scheduled.
+///
+/// The samples are reference counted, so cloning a `SampleBuffer` is cheap. This allows the same
+/// sound to back many scheduled sources, such as variants of a hitsound with different schedules,
+/// without decoding it or storing it in memory more than once.
+///
+/// # Example
+///
+/// ```
+/// use rodio::Source;
+/// use rodio_scheduler::{SampleBuffer, Scheduler};
+///
+/// let background = rodio::source::SineWave::new(440.0);
+/// let mut scheduler = Scheduler::new(background, 48000, 2);
+///
+/// let hitsound = rodio::source::SineWave::new(880.0).take_duration(std::time::Duration::from_millis(50));
+/// let buffer = SampleBuffer::new(hitsound, 48000, 2);
+///
+/// // Both sources play the same samples, but have their own playback schedules.
+/// let left_hand_id = scheduler.add_shared_source(buffer.clone());
+/// let right_hand_id = scheduler.add_shared_source(buffer);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SampleBuffer {
+    samples: Arc<[Sample]>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl SampleBuffer {
+    /// Decodes a source into a new `SampleBuffer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: The audio source to be decoded.
+    /// * `sample_rate`: The sample rate the source is converted to.
+    /// * `channels`: The number of channels the source is converted to.
+    ///
+    /// Panics if `channels` is `0`. Use [`SampleBuffer::try_new`] to get an error instead.
+    #[inline]
+    pub fn new(source: impl Source, sample_rate: u32, channels: u16) -> SampleBuffer {
+        assert!(channels > 0, "a SampleBuffer needs at least one channel");
+
+        SampleBuffer {
+            samples: UniformSourceIterator::new(source, channels, sample_rate).collect(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Decodes a source into a new `SampleBuffer`, failing if it has no samples.
+    ///
+    /// See [`SampleBuffer::new`]. Returns [`SchedulerError::NoChannels`] if the source or
+    /// `channels` has no channels, or [`SchedulerError::EmptySource`] if the source has no
+    /// samples.
+    #[inline]
+    pub fn try_new(
+        source: impl Source,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<SampleBuffer, SchedulerError> {
+        if channels == 0 || source.channels() == 0 {
+            return Err(SchedulerError::NoChannels);
+        }
+
+        let buffer = SampleBuffer::new(source, sample_rate, channels);
+
+        if buffer.is_empty() {
+            return Err(SchedulerError::EmptySource);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Decodes an encoded audio file, such as a WAV or an MP3 file, into a new `SampleBuffer`.
+    ///
+    /// Returns [`SchedulerError::Decode`] if the format of the data is not supported,
+    /// [`SchedulerError::NoChannels`] if it or `channels` has no channels, or
+    /// [`SchedulerError::EmptySource`] if it has no samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The encoded audio data, such as a `File`.
+    /// * `sample_rate`: The sample rate the source is converted to.
+    /// * `channels`: The number of channels the source is converted to.
+    #[inline]
+    pub fn decode<R>(
+        data: R,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<SampleBuffer, SchedulerError>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        SampleBuffer::try_new(Decoder::new(data)?, sample_rate, channels)
+    }
+
+    /// Returns the number of channels of the buffer.
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the sample rate of the buffer.
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the duration of the buffer.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        let frames = (self.samples.len() / self.channels as usize) as u64;
+
+        time::frames_to_duration(frames, self.sample_rate)
+    }
+
+    /// Returns a buffer with the given format, sharing the samples of this one if it already
+    /// matches.
+    #[inline]
+    pub(crate) fn with_format(&self, sample_rate: u32, channels: u16) -> SampleBuffer {
+        if self.sample_rate == sample_rate && self.channels == channels {
+            return self.clone();
+        }
+
+        let source = SamplesBuffer::new(self.channels, self.sample_rate, self.samples.to_vec());
+
+        SampleBuffer::new(source, sample_rate, channels)
+    }
+}
+
+impl Deref for SampleBuffer {
+    type Target = [Sample];
+
+    #[inline]
+    fn deref(&self) -> &[Sample] {
+        &self.samples
+    }
+}
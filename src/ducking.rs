@@ -0,0 +1,291 @@
+//! This module provides the ducking stage of a `Scheduler`, which turns the input down while
+//! scheduled sounds play over it.
+//!
+//! Ducking is keyed on the sources that have a ducking priority, see
+//! `SingleSourceScheduler::set_ducking_priority`. While any of them is sounding, the input is
+//! turned down to the depth of the [`Ducking`] settings, and so is every source with a lower
+//! priority. This keeps voice callouts above the music, and can keep them above hitsounds as well.
+//! Sources without a priority are never ducked, and never duck anything.
+
+use std::time::Duration;
+
+use rodio::Sample;
+
+use crate::{RENDER_BLOCK_SIZE, SampleType};
+
+/// The number of ducking priorities. Sources can have a priority from `0` to
+/// `DUCKING_LEVELS - 1`, and higher priorities duck lower ones.
+pub const DUCKING_LEVELS: usize = 8;
+
+/// What makes a source with a ducking priority duck the sounds below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuckingKey {
+    /// The source ducks while any of its events is playing, from the sample it starts on to the
+    /// sample it stops on, however quiet it is.
+    #[default]
+    Events,
+
+    /// The source ducks while its output is louder than a threshold, as a linear amplitude.
+    Level {
+        /// The amplitude the output of the source must exceed.
+        threshold: f32,
+    },
+}
+
+/// The settings of the ducking stage of a `Scheduler`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ducking {
+    /// The gain of ducked sounds, as a linear gain, so `0.25` turns them down by about 12 dB.
+    pub depth: f32,
+
+    /// How long the gain takes to go about two thirds of the way down once a key starts sounding.
+    pub attack: Duration,
+
+    /// How long the gain takes to recover about two thirds of the way once every key stops
+    /// sounding.
+    pub release: Duration,
+
+    /// What makes a source duck the sounds below it.
+    pub key: DuckingKey,
+}
+
+impl Default for Ducking {
+    #[inline]
+    fn default() -> Ducking {
+        Ducking {
+            depth: 0.25,
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(250),
+            key: DuckingKey::Events,
+        }
+    }
+}
+
+/// The running state of the ducking stage.
+///
+/// The gain of every priority level, and of the input, follows its keys with its own envelope.
+/// The envelopes move once per frame, so every channel of a frame is ducked by the same gain.
+/// Every buffer is allocated for a whole render block when the ducker is created, so changing
+/// the settings never allocates.
+pub(crate) struct Ducker {
+    settings: Option<Ducking>,
+    sample_rate: u32,
+    channels: u16,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    /// The gain of every priority level, and of the input last.
+    gains: [f32; DUCKING_LEVELS + 1],
+    /// The gains before every sample of the last render, so they can be restored when the
+    /// render is rewound.
+    history: Box<[f32]>,
+    /// Whether the gain of every priority level, and of the input last, moved in the last
+    /// render. The history of the other levels is left over from older renders.
+    advanced: [bool; DUCKING_LEVELS + 1],
+    /// The gain of the input on every sample of the last render.
+    input_gains: Box<[f32]>,
+    /// Scratch space for the samples of a priority level.
+    pub(crate) level_buffer: Box<[Sample]>,
+    /// Whether a key is sounding on every sample of the render, for the level being ducked.
+    pub(crate) keys: Box<[bool]>,
+}
+
+impl Ducker {
+    pub(crate) fn new(sample_rate: u32, channels: u16) -> Ducker {
+        Ducker {
+            settings: None,
+            sample_rate,
+            channels: channels.max(1),
+            attack_coefficient: 1.0,
+            release_coefficient: 1.0,
+            gains: [1.0; DUCKING_LEVELS + 1],
+            history: vec![1.0; (DUCKING_LEVELS + 1) * RENDER_BLOCK_SIZE].into_boxed_slice(),
+            advanced: [false; DUCKING_LEVELS + 1],
+            input_gains: vec![1.0; RENDER_BLOCK_SIZE].into_boxed_slice(),
+            level_buffer: vec![0.0; RENDER_BLOCK_SIZE].into_boxed_slice(),
+            keys: vec![false; RENDER_BLOCK_SIZE].into_boxed_slice(),
+        }
+    }
+
+    /// Returns the current settings, or `None` if ducking is off.
+    #[inline]
+    pub(crate) fn settings(&self) -> Option<Ducking> {
+        self.settings
+    }
+
+    /// Returns `true` if ducking is on.
+    #[inline]
+    pub(crate) fn is_active(&self) -> bool {
+        self.settings.is_some()
+    }
+
+    /// Changes the settings. The envelopes carry on from their current gains, unless ducking is
+    /// turned off.
+    #[inline]
+    pub(crate) fn set_settings(&mut self, settings: Option<Ducking>) {
+        self.settings = settings.map(|settings| Ducking {
+            depth: settings.depth.clamp(0.0, 1.0),
+            ..settings
+        });
+
+        match settings {
+            Some(settings) => {
+                self.attack_coefficient = self.coefficient(settings.attack);
+                self.release_coefficient = self.coefficient(settings.release);
+            }
+            None => self.reset(),
+        }
+    }
+
+    /// Returns the coefficient of an envelope that moves about two thirds of the way in `time`.
+    #[inline]
+    fn coefficient(&self, time: Duration) -> f32 {
+        let frames = time.as_secs_f32() * self.sample_rate as f32;
+
+        if frames > 0.0 {
+            1.0 - (-1.0 / frames).exp()
+        } else {
+            1.0
+        }
+    }
+
+    /// Brings every gain back up, such as after a seek.
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        self.gains.fill(1.0);
+        self.input_gains.fill(1.0);
+    }
+
+    /// Starts a new render, whose gains are recorded from the first sample of the render block.
+    #[inline]
+    pub(crate) fn start_render(&mut self) {
+        self.advanced.fill(false);
+    }
+
+    /// Restores the gains from before the given sample of the last render.
+    ///
+    /// Only the levels that moved during the render are restored, since the others held their
+    /// gain. Renders made while ducking is off don't record their gains, so they are left
+    /// untouched.
+    #[inline]
+    pub(crate) fn rewind(&mut self, position: usize) {
+        if !self.is_active() {
+            return;
+        }
+
+        for (level, gain) in self.gains.iter_mut().enumerate() {
+            if self.advanced[level] {
+                *gain = self.history[level * RENDER_BLOCK_SIZE + position];
+            }
+        }
+    }
+
+    /// Returns the gain of the input on the given sample of the last render.
+    #[inline]
+    pub(crate) fn input_gain(&self, position: usize) -> f32 {
+        if self.is_active() {
+            self.input_gains[position]
+        } else {
+            1.0
+        }
+    }
+
+    /// Adds the samples of a priority level to `output`, ducked by `keys`. The samples start on
+    /// the given sample of the render, which is the sample `start` of the output.
+    #[inline]
+    pub(crate) fn duck_level(
+        &mut self,
+        level: usize,
+        offset: usize,
+        start: SampleType,
+        samples: &[Sample],
+        keys: &[bool],
+        output: &mut [Sample],
+    ) {
+        for ((output, sample), gain) in output
+            .iter_mut()
+            .zip(samples)
+            .zip(self.envelope(level, offset, start, keys))
+        {
+            *output += sample * gain;
+        }
+    }
+
+    /// Computes the gain of the input, ducked by `keys`, starting on the given sample of the
+    /// render, which is the sample `start` of the output.
+    #[inline]
+    pub(crate) fn duck_input(&mut self, offset: usize, start: SampleType, keys: &[bool]) {
+        let mut input_gains = std::mem::take(&mut self.input_gains);
+
+        for (gain, envelope) in input_gains[offset..]
+            .iter_mut()
+            .zip(self.envelope(DUCKING_LEVELS, offset, start, keys))
+        {
+            *gain = envelope;
+        }
+
+        self.input_gains = input_gains;
+    }
+
+    /// Marks the samples on which a priority level is louder than the threshold, if the
+    /// ducking is keyed on levels. Returns `false` if it is keyed on events instead.
+    #[inline]
+    pub(crate) fn add_level_keys(&self, samples: &[Sample], keys: &mut [bool]) -> bool {
+        let Some(Ducking {
+            key: DuckingKey::Level { threshold },
+            ..
+        }) = self.settings
+        else {
+            return false;
+        };
+
+        for (key, sample) in keys.iter_mut().zip(samples) {
+            *key |= sample.abs() > threshold;
+        }
+
+        true
+    }
+
+    /// Returns the gains of a priority level for every key, starting on the given sample of the
+    /// render, which is the sample `start` of the output.
+    ///
+    /// The gain moves on the first sample of every frame, and is keyed if any sample of the frame
+    /// is.
+    #[inline]
+    fn envelope<'a>(
+        &'a mut self,
+        level: usize,
+        offset: usize,
+        start: SampleType,
+        keys: &'a [bool],
+    ) -> impl Iterator<Item = f32> + 'a {
+        let channels = self.channels as usize;
+        let channel = (start % channels as SampleType) as usize;
+        self.advanced[level] = true;
+
+        (0..keys.len()).map(move |index| {
+            let position = offset + index;
+            self.history[level * RENDER_BLOCK_SIZE + position] = self.gains[level];
+
+            if (channel + index).is_multiple_of(channels) {
+                let frame = &keys[index..keys.len().min(index + channels)];
+                self.step(level, frame.contains(&true));
+            }
+
+            self.gains[level]
+        })
+    }
+
+    /// Moves the gain of a priority level towards its target for one frame.
+    #[inline]
+    fn step(&mut self, level: usize, key: bool) {
+        let depth = self.settings.map_or(1.0, |settings| settings.depth);
+        let gain = self.gains[level];
+
+        let (target, coefficient) = if key {
+            (depth, self.attack_coefficient)
+        } else {
+            (1.0, self.release_coefficient)
+        };
+        self.gains[level] = gain + (target - gain) * coefficient;
+    }
+}
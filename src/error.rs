@@ -0,0 +1,123 @@
+//! This module provides the errors returned by a `Scheduler`.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use rodio::decoder::DecoderError;
+use rodio::source::SeekError;
+
+use crate::SampleType;
+
+/// An error returned when a `Scheduler` can't carry out an operation.
+#[derive(Debug)]
+pub enum SchedulerError {
+    /// There is no source with this identifier in the scheduler.
+    UnknownSource(usize),
+
+    /// The event, or one of its repetitions, starts too far in the future to be represented as a
+    /// sample index.
+    TimestampOverflow,
+
+    /// The event starts before the current playback position, so it can't be played from its
+    /// start. Both positions are measured in frames.
+    EventInPast {
+        /// The frame the event was scheduled on.
+        timestamp: SampleType,
+        /// The frame the scheduler is currently playing.
+        position: SampleType,
+    },
+
+    /// The source has no samples to play.
+    EmptySource,
+
+    /// The source, or the format it is converted to, has no channels.
+    NoChannels,
+
+    /// The event has a repeat configuration in frames, and was also given one in ticks.
+    ConflictingRepeat,
+
+    /// The event plays at a speed other than `1.0`, which a streaming source can't play.
+    UnsupportedSpeed(f32),
+
+    /// The source could not be decoded.
+    Decode(DecoderError),
+
+    /// The input source of the scheduler does not support seeking.
+    UnsupportedSeek {
+        /// The name of the source that does not support seeking.
+        underlying_source: &'static str,
+    },
+
+    /// The input source of the scheduler failed to seek.
+    Seek(SeekError),
+
+    /// The background thread that decodes a streaming source could not be spawned.
+    SpawnDecoder(io::Error),
+}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerError::UnknownSource(source_id) => {
+                write!(f, "there is no source with the identifier {source_id}")
+            }
+            SchedulerError::TimestampOverflow => {
+                write!(f, "the event timestamp is too large to be scheduled")
+            }
+            SchedulerError::EventInPast {
+                timestamp,
+                position,
+            } => write!(
+                f,
+                "the event starts on frame {timestamp}, before the playback position {position}"
+            ),
+            SchedulerError::EmptySource => write!(f, "the source has no samples"),
+            SchedulerError::NoChannels => write!(f, "the source has no channels"),
+            SchedulerError::ConflictingRepeat => {
+                write!(f, "the event is repeated both in frames and in ticks")
+            }
+            SchedulerError::UnsupportedSpeed(speed) => {
+                write!(f, "streaming sources can't play events at speed {speed}")
+            }
+            SchedulerError::Decode(error) => write!(f, "the source could not be decoded: {error}"),
+            SchedulerError::UnsupportedSeek { underlying_source } => {
+                write!(f, "seeking is not supported by {underlying_source}")
+            }
+            SchedulerError::Seek(error) => write!(f, "the input source failed to seek: {error}"),
+            SchedulerError::SpawnDecoder(error) => {
+                write!(f, "the streaming decoder thread could not be spawned: {error}")
+            }
+        }
+    }
+}
+
+impl Error for SchedulerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SchedulerError::Decode(error) => Some(error),
+            SchedulerError::Seek(error) => Some(error),
+            SchedulerError::SpawnDecoder(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecoderError> for SchedulerError {
+    #[inline]
+    fn from(error: DecoderError) -> SchedulerError {
+        SchedulerError::Decode(error)
+    }
+}
+
+impl From<SeekError> for SchedulerError {
+    #[inline]
+    fn from(error: SeekError) -> SchedulerError {
+        match error {
+            SeekError::NotSupported { underlying_source } => {
+                SchedulerError::UnsupportedSeek { underlying_source }
+            }
+            error => SchedulerError::Seek(error),
+        }
+    }
+}
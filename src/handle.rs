@@ -0,0 +1,381 @@
+//! This module provides a thread-safe handle to control a `Scheduler` while it is playing.
+//!
+//! Once a `Scheduler` is added to a rodio mixer it is moved into the audio thread, so it can no
+//! longer be accessed directly. A [`SchedulerHandle`] sends commands to it through a lock-free,
+//! preallocated queue, which the scheduler drains before producing each sample. This keeps the
+//! audio thread free of locks and allocations.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use rodio::source::Source;
+
+use crate::ducking::Ducking;
+use crate::master::{self, MasterStage};
+use crate::playhead::{Playhead, PlayheadHandle};
+use crate::queue::BoundedQueue;
+use crate::{
+    EVENT_NOTIFICATION_QUEUE_CAPACITY, EndPolicy, EventId, EventNotification,
+    LATE_EVENT_QUEUE_CAPACITY, LateEvent, LateEventPolicy, PlaybackEvent, SampleBuffer,
+    SingleSourceScheduler,
+};
+
+/// The number of commands that can be waiting to be applied by a `Scheduler`.
+pub const COMMAND_QUEUE_CAPACITY: usize = 1024;
+
+/// A command sent from a `SchedulerHandle` to its `Scheduler`.
+///
+/// Sources are boxed on the thread of the handle, so every slot of the queue stays small. The
+/// scheduler moves the source out of its box, and sends the empty box back to be freed by the
+/// handles, see [`SharedState::empty_sources`].
+pub(crate) enum Command {
+    ScheduleEvent(EventId, PlaybackEvent),
+    CancelEvent(EventId),
+    AddSource(usize, SourceBox),
+    SetLateEventPolicy(usize, LateEventPolicy),
+    SetMasterStage(MasterStage),
+    SetVolume(usize, f32),
+    SetMuted(usize, bool),
+    SetSoloed(usize, bool),
+    SetInputVolume(f32),
+    SetDucking(Option<Ducking>),
+    SetEndPolicy(EndPolicy),
+    ClearSchedule,
+}
+
+/// A source sent to a `Scheduler` by a handle, which is taken out of the box once it is added.
+pub(crate) type SourceBox = Box<Option<SingleSourceScheduler>>;
+
+/// Book-keeping of the source identifiers handed out by a `Scheduler` and its handles.
+pub(crate) struct SourceSlots {
+    /// The identifier of the next source to be added.
+    pub(crate) next_id: usize,
+    /// The number of sources the scheduler can hold without reallocating.
+    pub(crate) capacity: usize,
+}
+
+/// State shared between a `Scheduler` and its handles.
+pub(crate) struct SharedState {
+    pub(crate) commands: BoundedQueue<Command>,
+    /// The boxes of the sources added by handles, sent back by the scheduler so they are freed
+    /// outside of the audio thread.
+    pub(crate) empty_sources: BoundedQueue<SourceBox>,
+    pub(crate) source_slots: Mutex<SourceSlots>,
+    pub(crate) event_counter: Arc<AtomicU64>,
+    pub(crate) late_events: Arc<BoundedQueue<LateEvent>>,
+    pub(crate) notifications: Arc<BoundedQueue<EventNotification>>,
+    /// The gain applied by the master stage to the last frame, as the bits of an `f32`.
+    pub(crate) master_gain: Arc<AtomicU32>,
+    pub(crate) playhead: Arc<Playhead>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl SharedState {
+    pub(crate) fn new(sample_rate: u32, channels: u16, source_capacity: usize) -> SharedState {
+        SharedState {
+            commands: BoundedQueue::with_capacity(COMMAND_QUEUE_CAPACITY),
+            empty_sources: BoundedQueue::with_capacity(COMMAND_QUEUE_CAPACITY),
+            source_slots: Mutex::new(SourceSlots {
+                next_id: 0,
+                capacity: source_capacity,
+            }),
+            event_counter: Arc::new(AtomicU64::new(0)),
+            late_events: Arc::new(BoundedQueue::with_capacity(LATE_EVENT_QUEUE_CAPACITY)),
+            notifications: Arc::new(BoundedQueue::with_capacity(
+                EVENT_NOTIFICATION_QUEUE_CAPACITY,
+            )),
+            master_gain: Arc::new(AtomicU32::new(1.0_f32.to_bits())),
+            playhead: Arc::new(Playhead::new(sample_rate, channels)),
+            sample_rate,
+            channels,
+        }
+    }
+
+    /// Returns how much the master stage is turning the output down, in decibels.
+    pub(crate) fn gain_reduction(&self) -> f32 {
+        let gain = f32::from_bits(self.master_gain.load(Ordering::Relaxed));
+
+        -master::gain_to_decibels(gain).min(0.0)
+    }
+}
+
+/// An error returned when a command can't be sent to a `Scheduler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandleError {
+    /// The command queue is full. The scheduler drains it while playing, so the command can be
+    /// retried later.
+    QueueFull,
+
+    /// The scheduler can't hold any more sources without reallocating on the audio thread.
+    /// Create it with [`Scheduler::with_capacity`](crate::Scheduler::with_capacity) to reserve
+    /// room for sources added through a handle.
+    SourceCapacityExceeded,
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandleError::QueueFull => write!(f, "the scheduler command queue is full"),
+            HandleError::SourceCapacityExceeded => {
+                write!(f, "the scheduler has no capacity left for new sources")
+            }
+        }
+    }
+}
+
+impl Error for HandleError {}
+
+/// A cloneable handle used to control a `Scheduler` from any thread.
+///
+/// Commands are applied by the scheduler before it produces its next sample, in the order they
+/// were sent. Events that don't fit in the preallocated playback schedule of their source are
+/// dropped by the scheduler, since growing it would allocate on the audio thread. Events that
+/// [`Scheduler::schedule`](crate::Scheduler::schedule) would reject are dropped as well. Dropped
+/// events are reported through [`SchedulerHandle::pop_event_notification`] as
+/// [`EventNotification::EventDropped`], except for late events dropped by
+/// [`LateEventPolicy::Drop`], which are reported through [`SchedulerHandle::pop_late_event`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use rodio::{OutputStreamBuilder, Source};
+/// use rodio_scheduler::{Scheduler, PlaybackEvent};
+///
+/// # fn main() {
+///    let stream = OutputStreamBuilder::open_default_stream().unwrap();
+///
+///    let background = rodio::source::SineWave::new(440.0);
+///    let mut scheduler = Scheduler::with_capacity(background, 48000, 2, 8);
+///
+///    // Create a handle before moving the scheduler into the audio thread.
+///    let handle = scheduler.handle();
+///    stream.mixer().add(scheduler);
+///
+///    // Sources and events can still be added while the scheduler is playing.
+///    let note = rodio::source::SineWave::new(880.0).take_duration(Duration::from_millis(100));
+///    let note_id = handle.add_source(note).unwrap();
+///
+///    let event_id = handle.schedule_event(PlaybackEvent {
+///        source_id: note_id.into(),
+///        timestamp: 48000 * 2,
+///        repeat: None,
+///        ..Default::default()
+///    }).unwrap();
+///
+///    // Changed our mind.
+///    handle.cancel_event(event_id).unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SchedulerHandle {
+    shared: Arc<SharedState>,
+}
+
+impl SchedulerHandle {
+    pub(crate) fn new(shared: Arc<SharedState>) -> SchedulerHandle {
+        SchedulerHandle { shared }
+    }
+
+    /// Schedules a `PlaybackEvent` on the source identified by its `source_id`, which can be a
+    /// streaming source.
+    ///
+    /// Returns the identifier of the new event, which can be used to cancel it. Events on a
+    /// source that doesn't exist are dropped, see [`EventNotification::EventDropped`].
+    #[inline]
+    pub fn schedule_event(&self, event: PlaybackEvent) -> Result<EventId, HandleError> {
+        let id = EventId {
+            source_id: event.source_id.index(),
+            serial: self.shared.event_counter.fetch_add(1, Ordering::Relaxed),
+            streamed: event.source_id.is_streamed(),
+        };
+
+        self.send(Command::ScheduleEvent(id, event))?;
+
+        Ok(id)
+    }
+
+    /// Cancels a scheduled event and all of its repetitions, stopping it if it is already playing.
+    ///
+    /// This works for the events of streaming sources as well.
+    #[inline]
+    pub fn cancel_event(&self, id: EventId) -> Result<(), HandleError> {
+        self.send(Command::CancelEvent(id))
+    }
+
+    /// Sets what the source identified by `source_id` does with events scheduled before its
+    /// playback position.
+    ///
+    /// See [`SingleSourceScheduler::set_late_event_policy`].
+    #[inline]
+    pub fn set_late_event_policy(
+        &self,
+        source_id: usize,
+        policy: LateEventPolicy,
+    ) -> Result<(), HandleError> {
+        self.send(Command::SetLateEventPolicy(source_id, policy))
+    }
+
+    /// Sets the volume of the source identified by `source_id`.
+    ///
+    /// See [`SingleSourceScheduler::set_volume`].
+    #[inline]
+    pub fn set_volume(&self, source_id: usize, volume: f32) -> Result<(), HandleError> {
+        self.send(Command::SetVolume(source_id, volume))
+    }
+
+    /// Mutes or unmutes the source identified by `source_id`.
+    ///
+    /// See [`SingleSourceScheduler::set_muted`].
+    #[inline]
+    pub fn set_muted(&self, source_id: usize, muted: bool) -> Result<(), HandleError> {
+        self.send(Command::SetMuted(source_id, muted))
+    }
+
+    /// Solos or unsolos the source identified by `source_id`.
+    ///
+    /// See [`SingleSourceScheduler::set_soloed`].
+    #[inline]
+    pub fn set_soloed(&self, source_id: usize, soloed: bool) -> Result<(), HandleError> {
+        self.send(Command::SetSoloed(source_id, soloed))
+    }
+
+    /// Sets the volume of the input of the scheduler.
+    ///
+    /// See [`Scheduler::set_input_volume`](crate::Scheduler::set_input_volume).
+    #[inline]
+    pub fn set_input_volume(&self, volume: f32) -> Result<(), HandleError> {
+        self.send(Command::SetInputVolume(volume))
+    }
+
+    /// Enables ducking with the given settings, or disables it with `None`.
+    ///
+    /// See [`Scheduler::set_ducking`](crate::Scheduler::set_ducking).
+    #[inline]
+    pub fn set_ducking(&self, ducking: Option<Ducking>) -> Result<(), HandleError> {
+        self.send(Command::SetDucking(ducking))
+    }
+
+    /// Sets when the scheduler stops producing samples.
+    ///
+    /// See [`Scheduler::set_end_policy`](crate::Scheduler::set_end_policy).
+    #[inline]
+    pub fn set_end_policy(&self, policy: EndPolicy) -> Result<(), HandleError> {
+        self.send(Command::SetEndPolicy(policy))
+    }
+
+    /// Returns the oldest report of a late event that hasn't been read yet, from any source.
+    ///
+    /// Events sent through a handle can't be rejected with an error, so late events dropped by
+    /// [`LateEventPolicy::Drop`] are reported here as well.
+    #[inline]
+    pub fn pop_late_event(&self) -> Option<LateEvent> {
+        self.shared.late_events.pop()
+    }
+
+    /// Returns the oldest notification of an event starting or stopping that hasn't been read
+    /// yet, from any source.
+    ///
+    /// See [`SingleSourceScheduler::pop_event_notification`]. This can be polled by the game
+    /// thread to react to the events heard by the player. Events sent through a handle that the
+    /// scheduler had to drop are reported here as well, see [`EventNotification::EventDropped`].
+    #[inline]
+    pub fn pop_event_notification(&self) -> Option<EventNotification> {
+        self.shared.notifications.pop()
+    }
+
+    /// Sets the last stage the output of the scheduler passes through.
+    ///
+    /// See [`Scheduler::set_master_stage`](crate::Scheduler::set_master_stage).
+    #[inline]
+    pub fn set_master_stage(&self, stage: MasterStage) -> Result<(), HandleError> {
+        self.send(Command::SetMasterStage(stage))
+    }
+
+    /// Returns how much the master stage is turning the output down, in decibels.
+    ///
+    /// This is updated on every frame the scheduler plays, so it can be polled to draw a gain
+    /// reduction meter.
+    #[inline]
+    pub fn gain_reduction(&self) -> f32 {
+        self.shared.gain_reduction()
+    }
+
+    /// Creates a new `PlayheadHandle` that reads the playback position of the scheduler.
+    #[inline]
+    pub fn playhead(&self) -> PlayheadHandle {
+        PlayheadHandle::new(Arc::clone(&self.shared.playhead))
+    }
+
+    /// Removes every scheduled event from every source of the scheduler.
+    #[inline]
+    pub fn clear_schedule(&self) -> Result<(), HandleError> {
+        self.send(Command::ClearSchedule)
+    }
+
+    /// Adds a new source to the scheduler.
+    ///
+    /// The source is decoded and resampled on the calling thread before being sent to the
+    /// scheduler. Returns the identifier of the new source, which can be used to schedule
+    /// playback events right away.
+    pub fn add_source(&self, source: impl Source) -> Result<usize, HandleError> {
+        let source_scheduler =
+            SingleSourceScheduler::new(source, self.shared.sample_rate, self.shared.channels);
+
+        self.push_source(source_scheduler)
+    }
+
+    /// Adds a new source to the scheduler that plays the samples of a `SampleBuffer`.
+    ///
+    /// Works like [`Scheduler::add_shared_source`](crate::Scheduler::add_shared_source). Buffers
+    /// that need to be converted are converted on the calling thread.
+    pub fn add_shared_source(&self, buffer: SampleBuffer) -> Result<usize, HandleError> {
+        let buffer = buffer.with_format(self.shared.sample_rate, self.shared.channels);
+
+        self.push_source(SingleSourceScheduler::from_buffer(buffer))
+    }
+
+    fn push_source(
+        &self,
+        mut source_scheduler: SingleSourceScheduler,
+    ) -> Result<usize, HandleError> {
+        // Free the boxes of the sources the scheduler has already added, so it always has room
+        // to send them back.
+        while self.shared.empty_sources.pop().is_some() {}
+
+        source_scheduler.share_event_counter(Arc::clone(&self.shared.event_counter));
+        source_scheduler.share_late_events(Arc::clone(&self.shared.late_events));
+        source_scheduler.share_event_notifications(Arc::clone(&self.shared.notifications));
+
+        // The lock makes reserving the identifier and queueing the source a single step, so
+        // sources always arrive at the scheduler in the order of their identifiers.
+        let mut slots = self
+            .shared
+            .source_slots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if slots.next_id >= slots.capacity {
+            return Err(HandleError::SourceCapacityExceeded);
+        }
+
+        let source_id = slots.next_id;
+        source_scheduler.set_source_id(source_id);
+        self.send(Command::AddSource(source_id, Box::new(Some(source_scheduler))))?;
+        slots.next_id += 1;
+
+        Ok(source_id)
+    }
+
+    #[inline]
+    fn send(&self, command: Command) -> Result<(), HandleError> {
+        self.shared
+            .commands
+            .push(command)
+            .map_err(|_| HandleError::QueueFull)
+    }
+}
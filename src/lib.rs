@@ -5,7 +5,7 @@ A library for scheduling audio playback with `rodio`.
 at specific timestamps. This is useful for applications that need to accurately schedule a
 source to be played along other sources, such as rhythm games, digital audio workstations
 (DAWs), or music players. For synchronizing visuals or other external events with the audio
-playback, see the [rodio_playback_position](https://crates.io/crates/rodio_playback_position) crate.
+playback, see [`PlayheadHandle`].
 
 ## Important
 
@@ -15,6 +15,8 @@ which has not yet been stabilized.
 ## Features
 
 - **Sample-perfect Scheduling**: Schedule audio playback with sample-level accuracy.
+- **Streaming Sources**: Long clips can be decoded on demand by a background thread with a
+  [`StreamingSourceScheduler`], instead of being loaded in memory.
 - **SIMD Acceleration**: Uses SIMD for mixing audio samples, providing a small
   performance boost. This can be enabled with the `simd` feature flag.
 - **Optional Profiling**: Includes an optional `profiler` feature to instrument the code
@@ -51,11 +53,12 @@ use rodio_scheduler::{Scheduler, PlaybackEvent};
 
     // Schedule the sound to be played at 2 seconds.
     let event = PlaybackEvent {
-        source_id: note_hit_id,
-        timestamp: 48000 * 2, // 2 seconds in samples
+        source_id: note_hit_id.into(),
+        timestamp: 48000 * 2, // 2 seconds in frames
         repeat: None,
+        ..Default::default()
     };
-    scheduler.get_scheduler(note_hit_id).unwrap().schedule_event(event);
+    scheduler.schedule(event).unwrap();
 
     // Play the scheduled sounds.
     let _ = stream.mixer().add(scheduler);
@@ -68,47 +71,525 @@ use rodio_scheduler::{Scheduler, PlaybackEvent};
 */
 
 // rodio_scheduler requires nightly rust, because portable-simd is not stabilized yet.
-#![feature(portable_simd)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
 use rtsan_standalone::nonblocking;
 
 #[cfg(feature = "profiler")]
 use time_graph::instrument;
 
+pub mod automation;
+pub mod buffer;
+pub mod ducking;
+pub mod error;
+pub mod handle;
+pub mod master;
+pub mod playhead;
+mod queue;
 pub mod simd;
 pub mod simd_utils;
-
+pub mod streaming;
+mod strip;
+pub mod tempo;
+pub mod time;
+
+pub use automation::{AutomationCurve, AutomationLane};
+pub use buffer::SampleBuffer;
+pub use ducking::{Ducking, DuckingKey};
+pub use error::SchedulerError;
+pub use handle::{HandleError, SchedulerHandle};
+pub use master::{MasterStage, SoftClipCurve};
+pub use playhead::{PlaybackState, PlayheadHandle};
+pub use streaming::StreamingSourceScheduler;
+pub use strip::GAIN_RAMP_DURATION;
+pub use tempo::{BeatRepeat, TempoMap};
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError};
 use std::time::Duration;
 
 use rodio::Sample;
 use rodio::source::{SeekError, Source, UniformSourceIterator};
 
+use crate::queue::BoundedQueue;
+use crate::strip::ChannelStrip;
+
 type SampleType = u64;
 
+/// The default number of playbacks a `SingleSourceScheduler` can hold before reallocating its
+/// schedule, see [`SingleSourceScheduler::set_schedule_capacity`].
+pub const SCHEDULE_CAPACITY: usize = 1000;
+
+/// The number of late event reports that can be waiting to be read.
+pub const LATE_EVENT_QUEUE_CAPACITY: usize = 256;
+
+/// The number of event notifications that can be waiting to be read.
+pub const EVENT_NOTIFICATION_QUEUE_CAPACITY: usize = 1024;
+
+/// The number of samples a `Scheduler` renders ahead of its output, when it is used as an
+/// iterator.
+pub const RENDER_BLOCK_SIZE: usize = 512;
+
+/// The number of samples a `SingleSourceScheduler` mixes at a time while its gain is changing.
+const RAMP_CHUNK_SIZE: usize = 128;
+
 /// Represents a playback event to be scheduled.
+///
+/// Only `source_id` and `timestamp` are required, every other field can be left to its default:
+///
+/// ```
+/// use rodio_scheduler::PlaybackEvent;
+///
+/// let accent = PlaybackEvent {
+///     source_id: 0.into(),
+///     timestamp: 48000,
+///     gain: Some(1.5),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct PlaybackEvent {
     /// The identifier of the source to be played.
-    pub source_id: usize,
+    pub source_id: SourceId,
 
-    /// The timestamp at which the event should occur, measured in samples.
-    /// The user is responsible for providing a timestamp that is compatible with the scheduler's sample rate.
+    /// The timestamp at which the event should occur, measured in frames.
+    ///
+    /// A frame holds one sample for every channel, so this is the number of samples per channel
+    /// since the start of playback, at the scheduler's sample rate. See the `at_*` constructors to
+    /// create an event from other time units.
     pub timestamp: SampleType,
 
     /// An optional repeat configuration.
     ///
     /// The tuple contains two values:
-    /// 1. The duration of a single beat in samples.
+    /// 1. The duration of a single beat in frames.
     /// 2. The number of times the beat should be repeated.
+    ///
+    /// The repetitions are played after the original event, so an event with a repeat count of 3
+    /// will be played 4 times in total.
     pub repeat: Option<(SampleType, SampleType)>,
+
+    /// An optional gain applied to the event, as a linear factor.
+    ///
+    /// Defaults to `1.0`, which plays the source at its original volume.
+    pub gain: Option<f32>,
+
+    /// An optional stereo position for the event, from `-1.0` (left) to `1.0` (right).
+    ///
+    /// Defaults to `0.0`, which plays the source unchanged. Panning attenuates the opposite
+    /// channel, so it only affects the first two channels of the output and is ignored for mono
+    /// output.
+    pub pan: Option<f32>,
+
+    /// An optional playback rate for the event.
+    ///
+    /// Defaults to `1.0`. A speed of `2.0` plays the source twice as fast, an octave higher, and
+    /// a speed of `0.5` plays it twice as slow, an octave lower. Speeds that are not positive are
+    /// ignored.
+    pub speed: Option<f32>,
+}
+
+impl PlaybackEvent {
+    /// Creates an event that plays a source at the given frame.
+    #[inline]
+    pub fn at_frame(source_id: impl Into<SourceId>, frame: SampleType) -> PlaybackEvent {
+        PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: frame,
+            ..Default::default()
+        }
+    }
+
+    /// Creates an event that plays a source after the given duration, rounded to the nearest
+    /// frame at `sample_rate`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use rodio_scheduler::PlaybackEvent;
+    ///
+    /// let event = PlaybackEvent::at_duration(0, Duration::from_millis(1500), 48000);
+    /// assert_eq!(event.timestamp, 72000);
+    /// ```
+    #[inline]
+    pub fn at_duration(
+        source_id: impl Into<SourceId>,
+        duration: Duration,
+        sample_rate: u32,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_frame(source_id, time::duration_to_frames(duration, sample_rate))
+    }
+
+    /// Creates an event that plays a source after the given number of seconds, rounded to the
+    /// nearest frame at `sample_rate`.
+    #[inline]
+    pub fn at_seconds(
+        source_id: impl Into<SourceId>,
+        seconds: f64,
+        sample_rate: u32,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_frame(source_id, time::seconds_to_frames(seconds, sample_rate))
+    }
+
+    /// Creates an event that plays a source on the given beat of a constant tempo, rounded to the
+    /// nearest frame at `sample_rate`.
+    ///
+    /// Beat 0 is at the start of playback, and beats can be fractional.
+    ///
+    /// ```
+    /// use rodio_scheduler::PlaybackEvent;
+    ///
+    /// // The third beat at 120 BPM starts after one second.
+    /// let event = PlaybackEvent::at_beat(0, 120.0, 2.0, 48000);
+    /// assert_eq!(event.timestamp, 48000);
+    /// ```
+    #[inline]
+    pub fn at_beat(
+        source_id: impl Into<SourceId>,
+        bpm: f64,
+        beat: f64,
+        sample_rate: u32,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_frame(source_id, time::beats_to_frames(bpm, beat, sample_rate))
+    }
+
+    /// Returns the number of times this event will be played, including its repetitions.
+    #[inline]
+    pub(crate) fn playback_count(&self) -> usize {
+        let repeat_count = self.repeat.map_or(0, |(_, repeat_count)| repeat_count);
+
+        usize::try_from(repeat_count)
+            .unwrap_or(usize::MAX)
+            .saturating_add(1)
+    }
+
+    /// Returns the sample index of every playback of this event, including its repetitions, in
+    /// order.
+    ///
+    /// The timestamps must have been checked first, see [`PlaybackEvent::check_timestamps`].
+    #[inline]
+    pub(crate) fn playback_samples(&self, channels: u16) -> impl Iterator<Item = SampleType> {
+        let channels = channels as SampleType;
+        let timestamp = self.timestamp * channels;
+        let (beat_duration, repeat_count) = self.repeat.unwrap_or((0, 0));
+
+        (0..=repeat_count).map(move |beat| timestamp + beat * beat_duration * channels)
+    }
+
+    /// Checks that the sample index of every playback of this event can be represented, with the
+    /// given channel count.
+    #[inline]
+    pub(crate) fn check_timestamps(&self, channels: u16) -> Result<(), SchedulerError> {
+        let (beat_duration, repeat_count) = self.repeat.unwrap_or((0, 0));
+
+        // Repetitions are played in order, so checking the last one is enough.
+        beat_duration
+            .checked_mul(repeat_count)
+            .and_then(|offset| offset.checked_add(self.timestamp))
+            .and_then(|frame| frame.checked_mul(channels as SampleType))
+            .ok_or(SchedulerError::TimestampOverflow)?;
+
+        Ok(())
+    }
+}
+
+/// Returns the first frame that has not started playing after `samples` interleaved samples.
+#[inline]
+pub(crate) fn next_frame(samples: SampleType, channels: u16) -> SampleType {
+    samples.div_ceil(channels as SampleType)
+}
+
+/// Shrinks or grows the capacity of `values` to `capacity`, which must not be lower than its
+/// length.
+pub(crate) fn set_capacity<T>(values: &mut Vec<T>, capacity: usize) {
+    values.shrink_to(capacity);
+    values.reserve_exact(capacity - values.len());
+}
+
+/// The playback parameters of an event, with their defaults applied.
+#[derive(Clone, Copy, Debug)]
+struct ResolvedParameters {
+    gain: f32,
+    pan: f32,
+    speed: f64,
+}
+
+impl ResolvedParameters {
+    #[inline]
+    fn new(event: &PlaybackEvent) -> ResolvedParameters {
+        ResolvedParameters {
+            gain: event.gain.unwrap_or(1.0),
+            pan: event.pan.unwrap_or(0.0).clamp(-1.0, 1.0),
+            speed: event
+                .speed
+                .filter(|speed| speed.is_finite() && *speed > 0.0)
+                .map_or(1.0, f64::from),
+        }
+    }
+
+    /// Returns the gain of the event for one of the output channels.
+    #[inline]
+    fn channel_gain(&self, channel: u16, channels: u16) -> f32 {
+        match (channels, channel) {
+            (2.., 0) => self.gain * (1.0 - self.pan).min(1.0),
+            (2.., 1) => self.gain * (1.0 + self.pan).min(1.0),
+            _ => self.gain,
+        }
+    }
+}
+
+/// How a `SingleSourceScheduler` chooses which voice to stop when an event starts and its
+/// voice limit has been reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VoiceStealPolicy {
+    /// Stops the event that started first.
+    #[default]
+    Oldest,
+
+    /// Stops the event with the lowest gain. Events with the same gain are stolen oldest first.
+    Quietest,
+}
+
+/// When a `Scheduler` stops producing samples.
+///
+/// A source added to a rodio mixer plays until it returns `None`, so a scheduler that never ends
+/// is never removed from the mixer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EndPolicy {
+    /// The scheduler never ends, and plays silence once its input and every event have finished.
+    /// This allows events to be scheduled at any point in the future.
+    #[default]
+    Never,
+
+    /// The scheduler ends with its input, cutting off any event that is still playing.
+    WithInput,
+
+    /// The scheduler ends once its input and every scheduled event have finished playing,
+    /// including the delay of the master stage. Scheduling another event makes it play again,
+    /// but a rodio mixer drops its sources as soon as they end.
+    AfterLastEvent,
+}
+
+/// What a `SingleSourceScheduler` does with an event that is scheduled to start before its
+/// current playback position.
+///
+/// Events are often submitted slightly late by interactive applications, for example when the
+/// input that triggers them is only processed after the audio thread has moved on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LateEventPolicy {
+    /// The event is not scheduled.
+    #[default]
+    Drop,
+
+    /// The event is scheduled at its timestamp, so it starts playing immediately, from the
+    /// sample it would be playing if it had been scheduled in time.
+    Truncate,
+
+    /// The event is delayed until the next frame, so it starts playing immediately, from its
+    /// first sample. Its repetitions are delayed by the same amount.
+    PlayFromStart,
+}
+
+impl LateEventPolicy {
+    /// Returns the number of frames an event starting on `timestamp` must be delayed by, when
+    /// `position` is the first frame that has not started playing.
+    #[inline]
+    pub(crate) fn delay(
+        self,
+        timestamp: SampleType,
+        position: SampleType,
+    ) -> Result<SampleType, SchedulerError> {
+        if timestamp >= position {
+            return Ok(0);
+        }
+
+        match self {
+            LateEventPolicy::Drop => Err(SchedulerError::EventInPast {
+                timestamp,
+                position,
+            }),
+            LateEventPolicy::Truncate => Ok(0),
+            LateEventPolicy::PlayFromStart => Ok(position - timestamp),
+        }
+    }
+}
+
+/// A report of an event that was scheduled to start before the playback position of its
+/// source, and how it was handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LateEvent {
+    /// The identifier of the event.
+    pub id: EventId,
+
+    /// The frame the event was scheduled on.
+    pub timestamp: SampleType,
+
+    /// The first frame that had not started playing when the event was scheduled.
+    pub position: SampleType,
+
+    /// The policy that was applied to the event.
+    pub policy: LateEventPolicy,
+}
+
+impl LateEvent {
+    /// Returns how late the event was scheduled, in frames.
+    #[inline]
+    pub fn lateness(&self) -> SampleType {
+        self.position - self.timestamp
+    }
+}
+
+/// A notification of an event starting or stopping to play.
+///
+/// See [`SingleSourceScheduler::pop_event_notification`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventNotification {
+    /// An event started playing.
+    EventStarted {
+        /// The identifier of the event.
+        id: EventId,
+
+        /// The identifier of the source the event plays.
+        source_id: usize,
+
+        /// The frame the event started on.
+        frame: SampleType,
+    },
+
+    /// An event stopped playing, because it reached the end of its source, was stopped by a
+    /// voice limit or a choke group, or was cancelled.
+    EventEnded {
+        /// The identifier of the event.
+        id: EventId,
+
+        /// The identifier of the source the event played.
+        source_id: usize,
+
+        /// The first frame the event didn't play on.
+        frame: SampleType,
+    },
+
+    /// An event sent by a [`SchedulerHandle`] was dropped without being scheduled, because the
+    /// playback schedule of its source was full, or the scheduler would have rejected it.
+    ///
+    /// This is reported as soon as the scheduler receives the event. Late events dropped by
+    /// [`LateEventPolicy::Drop`] are reported as a [`LateEvent`] instead.
+    EventDropped {
+        /// The identifier of the event.
+        id: EventId,
+
+        /// The identifier of the source the event was scheduled on.
+        source_id: usize,
+
+        /// The frame the event was scheduled on.
+        frame: SampleType,
+    },
+}
+
+impl EventNotification {
+    /// Returns the identifier of the event.
+    #[inline]
+    pub fn id(&self) -> EventId {
+        match *self {
+            EventNotification::EventStarted { id, .. }
+            | EventNotification::EventEnded { id, .. }
+            | EventNotification::EventDropped { id, .. } => id,
+        }
+    }
+
+    /// Returns the frame the event started or stopped on, or was scheduled on if it was dropped.
+    #[inline]
+    pub fn frame(&self) -> SampleType {
+        match *self {
+            EventNotification::EventStarted { frame, .. }
+            | EventNotification::EventEnded { frame, .. }
+            | EventNotification::EventDropped { frame, .. } => frame,
+        }
+    }
+}
+
+/// An identifier for a source of a [`Scheduler`].
+///
+/// Streaming sources are numbered apart from the sources loaded in memory, so the identifier
+/// says which of the two it refers to. Plain indices convert to [`SourceId::Buffered`], so the
+/// identifiers returned by [`Scheduler::add_source`] can be used with `.into()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceId {
+    /// A source loaded in memory, see [`Scheduler::add_source`].
+    Buffered(usize),
+
+    /// A streaming source, see [`Scheduler::add_streaming_source`].
+    Streamed(usize),
+}
+
+impl SourceId {
+    /// Returns the index of the source among the sources of its kind.
+    #[inline]
+    pub fn index(&self) -> usize {
+        match *self {
+            SourceId::Buffered(index) | SourceId::Streamed(index) => index,
+        }
+    }
+
+    /// Returns `true` if this identifies a streaming source.
+    #[inline]
+    pub fn is_streamed(&self) -> bool {
+        matches!(self, SourceId::Streamed(_))
+    }
+}
+
+impl Default for SourceId {
+    #[inline]
+    fn default() -> SourceId {
+        SourceId::Buffered(0)
+    }
+}
+
+impl From<usize> for SourceId {
+    #[inline]
+    fn from(index: usize) -> SourceId {
+        SourceId::Buffered(index)
+    }
+}
+
+/// An identifier for a scheduled playback event.
+///
+/// Every repetition of an event shares the identifier of the event that created it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EventId {
+    source_id: usize,
+    serial: u64,
+    /// Whether the event was scheduled on a streaming source, which has its own identifiers.
+    streamed: bool,
+}
+
+impl EventId {
+    /// Returns the identifier of the source this event was scheduled for.
+    ///
+    /// Streaming sources are numbered apart from the other sources, so this is the identifier of
+    /// a stream if [`EventId::is_streamed`] returns `true`.
+    #[inline]
+    pub fn source_id(&self) -> usize {
+        self.source_id
+    }
+
+    /// Returns `true` if this event was scheduled on a streaming source, see
+    /// [`Scheduler::get_streaming_scheduler`].
+    #[inline]
+    pub fn is_streamed(&self) -> bool {
+        self.streamed
+    }
 }
 
 /// A source that schedules playback for a single audio source at precise timestamps.
-/// 
+///
 /// The source is fully loaded in memory when the scheduler is created, so scheduling long sources could
-/// result in a large memory allocation.
+/// result in a large memory allocation. Sources that are scheduled many times can share their
+/// samples through a [`SampleBuffer`], see [`SingleSourceScheduler::from_buffer`].
 pub struct SingleSourceScheduler {
     /// Backing buffer storing the sample to be scheduled.
-    source: Vec<f32>,
+    source: SampleBuffer,
 
     /// The target channel count.
     channels: u16,
@@ -116,14 +597,92 @@ pub struct SingleSourceScheduler {
     /// The target sample rate.
     sample_rate: u32,
 
+    /// The identifier of this source in its parent `Scheduler`, which is part of the identifier
+    /// of every event it schedules.
+    source_id: usize,
+
     /// The playback position of each event scheduled for this source, in samples.
     playback_schedule: Vec<SampleType>,
 
+    /// The identifier of each event in `playback_schedule`.
+    event_ids: Vec<EventId>,
+
+    /// The sample at which each event in `playback_schedule` stops playing.
+    event_ends: Vec<SampleType>,
+
+    /// The sample at which each event in `playback_schedule` is stopped by a voice limit or a
+    /// choke group, or its end if it plays until the end of the source.
+    event_stops: Vec<SampleType>,
+
+    /// The playback rate of each event in `playback_schedule`.
+    event_speeds: Vec<f64>,
+
+    /// The gain of each event in `playback_schedule`, for every output channel.
+    event_gains: Vec<Vec<f32>>,
+
+    /// The number of playbacks `playback_schedule` holds before finished events are reclaimed.
+    schedule_capacity: usize,
+
+    /// Scratch space for the playbacks appended by a bulk insert, paired with their indices.
+    appended_playbacks: Vec<(SampleType, usize)>,
+
+    /// Scratch space for the sorted order of the playback schedule after a bulk insert.
+    schedule_order: Vec<usize>,
+
+    /// An upper bound of the number of samples any event in `playback_schedule` plays for.
+    max_event_length: SampleType,
+
+    /// The maximum number of events that can play at the same time, if limited.
+    max_voices: Option<usize>,
+
+    /// How to choose the event to stop when `max_voices` is reached.
+    steal_policy: VoiceStealPolicy,
+
+    /// The choke group of this source. Starting an event stops every event of the other sources
+    /// in the same group.
+    choke_group: Option<u32>,
+
+    /// What to do with events scheduled before the playback position.
+    late_event_policy: LateEventPolicy,
+
+    /// The volume, mute and solo of this source.
+    strip: ChannelStrip,
+
+    /// The gain of this source over time, applied on top of `strip`.
+    gain_automation: AutomationLane,
+
+    /// The ducking priority of this source. Sounding events duck the sources with a lower
+    /// priority, and the input of the parent `Scheduler`.
+    ducking_priority: Option<u8>,
+
+    /// Scratch space for the samples mixed while the gain of this source is changing.
+    ramp_buffer: Box<[Sample]>,
+
+    /// Reports of the late events this source has scheduled or dropped.
+    ///
+    /// This is shared with the parent `Scheduler` and its handles, so the reports of every source
+    /// can be read in one place.
+    late_events: Arc<BoundedQueue<LateEvent>>,
+
+    /// Notifications of the events this source has started and stopped.
+    ///
+    /// This is shared with the parent `Scheduler` and its handles, like `late_events`.
+    notifications: Arc<BoundedQueue<EventNotification>>,
+
+    /// The sample up to which the events have been reported to `notifications`.
+    notified: SampleType,
+
+    /// Counter used to generate event identifiers.
+    ///
+    /// This is shared with the parent `Scheduler` and its handles, so identifiers are unique
+    /// across every source.
+    event_counter: Arc<AtomicU64>,
+
     /// An internal window for currently playing events in this source.
     ///
-    /// The first value of the tuple is the index to the oldest playback event 
-    /// that is still playing, while the second value is the index to the newest 
-    /// playback event that has not started playing yet. When they are the same, 
+    /// The first value of the tuple is the index to the oldest playback event
+    /// that is still playing, while the second value is the index to the newest
+    /// playback event that has not started playing yet. When they are the same,
     /// no sounds are playing.
     playback_position: (usize, usize),
 
@@ -133,261 +692,2591 @@ pub struct SingleSourceScheduler {
     samples_counted: SampleType,
 }
 
-impl SingleSourceScheduler {
-    /// Creates a new `SingleSourceScheduler`.
+impl SingleSourceScheduler {
+    /// Creates a new `SingleSourceScheduler`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: The audio source to be scheduled.
+    /// * `sample_rate`: The sample rate of the output audio.
+    /// * `channels`: The number of channels in the output audio.
+    #[inline]
+    pub fn new(source: impl Source, sample_rate: u32, channels: u16) -> SingleSourceScheduler {
+        SingleSourceScheduler::from_buffer(SampleBuffer::new(source, sample_rate, channels))
+    }
+
+    /// Creates a new `SingleSourceScheduler` that plays the samples of a `SampleBuffer`.
+    ///
+    /// The samples are shared with the buffer instead of being copied, and the output uses the
+    /// sample rate and channel count of the buffer.
+    #[inline]
+    pub fn from_buffer(source: SampleBuffer) -> SingleSourceScheduler {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+
+        SingleSourceScheduler {
+            source,
+            channels,
+            sample_rate,
+            source_id: 0,
+            playback_schedule: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_ids: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_ends: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_stops: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_speeds: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_gains: (0..channels)
+                .map(|_| Vec::with_capacity(SCHEDULE_CAPACITY))
+                .collect(),
+            schedule_capacity: SCHEDULE_CAPACITY,
+            appended_playbacks: Vec::with_capacity(SCHEDULE_CAPACITY),
+            schedule_order: Vec::with_capacity(SCHEDULE_CAPACITY),
+            max_event_length: 0,
+            max_voices: None,
+            steal_policy: VoiceStealPolicy::Oldest,
+            choke_group: None,
+            late_event_policy: LateEventPolicy::Drop,
+            strip: ChannelStrip::new(sample_rate, channels),
+            gain_automation: AutomationLane::new(),
+            ducking_priority: None,
+            ramp_buffer: vec![0.0; RAMP_CHUNK_SIZE].into_boxed_slice(),
+            late_events: Arc::new(BoundedQueue::with_capacity(LATE_EVENT_QUEUE_CAPACITY)),
+            notifications: Arc::new(BoundedQueue::with_capacity(
+                EVENT_NOTIFICATION_QUEUE_CAPACITY,
+            )),
+            notified: 0,
+            event_counter: Arc::new(AtomicU64::new(0)),
+            playback_position: (0, 0),
+            samples_counted: 0,
+        }
+    }
+
+    /// Schedules a `PlaybackEvent` for this source.
+    ///
+    /// The event's timestamp is converted to a sample index and inserted into the playback
+    /// schedule, keeping it sorted to ensure correct playback order. If the event has a repeat
+    /// configuration, every repetition is added to the schedule as well, spaced one beat apart
+    /// from the previous one.
+    ///
+    /// Events that start before the current playback position are handled following the
+    /// [`LateEventPolicy`] of this scheduler, and reported as a [`LateEvent`] if they are
+    /// scheduled anyway.
+    ///
+    /// The `source_id` of the event is ignored, and the identifier of the event refers to this
+    /// source instead, see [`SingleSourceScheduler::source_id`].
+    ///
+    /// Returns the identifier of the new event, which can be used to cancel it. Fails with
+    /// [`SchedulerError::TimestampOverflow`] if a playback is too far in the future to be
+    /// represented, or [`SchedulerError::EventInPast`] if the event is late and the policy is
+    /// [`LateEventPolicy::Drop`]. The schedule is left untouched on failure.
+    #[inline]
+    pub fn schedule_event(&mut self, event: PlaybackEvent) -> Result<EventId, SchedulerError> {
+        let id = self.new_event_id();
+
+        self.add_event(id, event)?;
+
+        Ok(id)
+    }
+
+    /// Schedules many `PlaybackEvent`s for this source at once.
+    ///
+    /// This is much faster than calling [`SingleSourceScheduler::schedule_event`] for every event
+    /// when loading a large chart: the whole schedule is sorted once, instead of shifting it for
+    /// every event, so the load time grows linearly with the number of events as long as they
+    /// are mostly in order.
+    ///
+    /// Either every event is scheduled, or none is: the first error returned by
+    /// [`SingleSourceScheduler::schedule_event`] for any of the events is returned, and the
+    /// schedule is left untouched. Otherwise, returns the identifiers of the new events, in the
+    /// same order. Like [`SingleSourceScheduler::schedule_event`], the `source_id` of every event
+    /// is ignored.
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn schedule_events(
+        &mut self,
+        events: impl IntoIterator<Item = PlaybackEvent>,
+    ) -> Result<Vec<EventId>, SchedulerError> {
+        let events: Vec<PlaybackEvent> = events.into_iter().collect();
+        for event in events.iter() {
+            self.check_event(event)?;
+        }
+
+        let ids: Vec<EventId> = events.iter().map(|_| self.new_event_id()).collect();
+
+        self.insert_events(ids.iter().copied().zip(events));
+
+        Ok(ids)
+    }
+
+    /// Schedules a `PlaybackEvent` on a beat of a `TempoMap`.
+    ///
+    /// The timestamp of the event is ignored, and replaced by the frame of `beat` at the sample
+    /// rate of this scheduler. The repeat configuration of the event is still measured in frames.
+    /// To space repetitions in ticks of the tempo map instead, so they follow its tempo changes,
+    /// pass a [`BeatRepeat`].
+    ///
+    /// Returns the identifier of the new event, which can be used to cancel it. Fails like
+    /// [`SingleSourceScheduler::schedule_event`], or with [`SchedulerError::ConflictingRepeat`] if
+    /// the event is given both a repeat configuration and a `BeatRepeat`.
+    #[inline]
+    pub fn schedule_beat(
+        &mut self,
+        tempo_map: &TempoMap,
+        beat: f64,
+        event: PlaybackEvent,
+        repeat: Option<BeatRepeat>,
+    ) -> Result<EventId, SchedulerError> {
+        let sample_rate = self.sample_rate;
+        let Some(BeatRepeat {
+            ticks,
+            count: repeat_count,
+        }) = repeat
+        else {
+            return self.schedule_event(PlaybackEvent {
+                timestamp: tempo_map.beat_to_frame(beat, sample_rate),
+                ..event
+            });
+        };
+
+        if event.repeat.is_some() {
+            return Err(SchedulerError::ConflictingRepeat);
+        }
+
+        let channels = self.channels as SampleType;
+        let parameters = ResolvedParameters::new(&event);
+
+        let sample_of = |repetition: SampleType| {
+            let beat = beat + tempo_map.ticks_to_beats(ticks.saturating_mul(repetition));
+
+            tempo_map
+                .beat_to_frame(beat, sample_rate)
+                .checked_mul(channels)
+                .ok_or(SchedulerError::TimestampOverflow)
+        };
+
+        // Repetitions are played in order, so checking the first and last ones is enough.
+        let first = sample_of(0)?;
+        let delay = self.late_event_delay(first / channels)? * channels;
+        sample_of(repeat_count)?
+            .checked_add(delay)
+            .ok_or(SchedulerError::TimestampOverflow)?;
+
+        let id = self.new_event_id();
+
+        for repetition in 0..=repeat_count {
+            let sample = sample_of(repetition)? + delay;
+
+            self.insert_timestamp(id, sample, parameters);
+        }
+
+        self.handle_late_event(id, first / channels);
+
+        Ok(id)
+    }
+
+    /// Cancels a scheduled event and all of its repetitions.
+    ///
+    /// If the event is already playing, it is stopped immediately. Returns `true` if the event was
+    /// found in the playback schedule.
+    #[inline]
+    pub fn cancel_event(&mut self, id: EventId) -> bool {
+        let len = self.event_ids.len();
+        let (start, end) = self.playback_position;
+        let mut playback_position = self.playback_position;
+
+        // Compact the schedule in a single pass, moving every playback that is kept over the
+        // cancelled ones, so an event with many repetitions isn't removed one shift at a time.
+        let mut kept = 0;
+        for index in 0..len {
+            if index == start {
+                playback_position.0 = kept;
+            }
+            if index == end {
+                playback_position.1 = kept;
+            }
+
+            if self.event_ids[index] == id {
+                self.report_removed(index, index + 1);
+            } else {
+                self.swap_playbacks(kept, index);
+                kept += 1;
+            }
+        }
+
+        if kept == len {
+            return false;
+        }
+
+        if start == len {
+            playback_position.0 = kept;
+        }
+        if end == len {
+            playback_position.1 = kept;
+        }
+
+        self.truncate_playbacks(kept);
+        self.playback_position = playback_position;
+
+        true
+    }
+
+    /// Cancels every event scheduled to start between `start` (inclusive) and `end` (exclusive).
+    ///
+    /// Like [`PlaybackEvent::timestamp`], `start` and `end` are measured in frames.
+    /// Repetitions are cancelled individually, so only the ones that fall in the range are
+    /// removed. Events that are already playing are stopped immediately. Returns the number of
+    /// cancelled playbacks.
+    #[inline]
+    pub fn cancel_range(&mut self, start: SampleType, end: SampleType) -> usize {
+        let channels = self.channels as SampleType;
+        let start = start.saturating_mul(channels);
+        let end = end.saturating_mul(channels);
+
+        let first = self.playback_schedule.partition_point(|&t| t < start);
+        let last = self
+            .playback_schedule
+            .partition_point(|&t| t < end)
+            .max(first);
+
+        self.remove_range(first, last);
+
+        last - first
+    }
+
+    /// Cancels every scheduled event, stopping the ones that are already playing.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.report_removed(0, self.playback_schedule.len());
+
+        self.playback_schedule.clear();
+        self.event_ids.clear();
+        self.event_ends.clear();
+        self.event_stops.clear();
+        self.event_speeds.clear();
+        for gains in self.event_gains.iter_mut() {
+            gains.clear();
+        }
+
+        self.max_event_length = 0;
+        self.playback_position = (0, 0);
+    }
+
+    /// Returns the identifier of this source in its parent `Scheduler`, or `0` if it is played
+    /// on its own.
+    #[inline]
+    pub fn source_id(&self) -> usize {
+        self.source_id
+    }
+
+    /// Sets the identifier of this source in its parent `Scheduler`.
+    #[inline]
+    pub(crate) fn set_source_id(&mut self, source_id: usize) {
+        self.source_id = source_id;
+    }
+
+    /// Shares an event identifier counter with this scheduler.
+    #[inline]
+    pub(crate) fn share_event_counter(&mut self, event_counter: Arc<AtomicU64>) {
+        self.event_counter = event_counter;
+    }
+
+    /// Generates a new, unique identifier for an event of this source.
+    #[inline]
+    pub(crate) fn new_event_id(&self) -> EventId {
+        EventId {
+            source_id: self.source_id,
+            serial: self.event_counter.fetch_add(1, Ordering::Relaxed),
+            streamed: false,
+        }
+    }
+
+    /// Sets the number of playbacks the schedule can hold, including repetitions, before
+    /// reallocating.
+    ///
+    /// When an event doesn't fit in the schedule, the events that have finished playing are
+    /// removed from it first, so long sessions that keep scheduling events don't grow it forever.
+    /// This never allocates, so it is safe on the audio thread, where events sent by a
+    /// [`SchedulerHandle`] are still dropped if they don't fit after that. Seeking back before a
+    /// removed event doesn't play it again.
+    ///
+    /// Defaults to [`SCHEDULE_CAPACITY`]. The schedule still grows past the capacity for events
+    /// scheduled directly, if none of its events have finished.
+    pub fn set_schedule_capacity(&mut self, capacity: usize) {
+        let capacity = capacity.max(self.playback_schedule.len());
+        self.schedule_capacity = capacity;
+
+        set_capacity(&mut self.playback_schedule, capacity);
+        set_capacity(&mut self.event_ids, capacity);
+        set_capacity(&mut self.event_ends, capacity);
+        set_capacity(&mut self.event_stops, capacity);
+        set_capacity(&mut self.event_speeds, capacity);
+        for gains in self.event_gains.iter_mut() {
+            set_capacity(gains, capacity);
+        }
+        set_capacity(&mut self.appended_playbacks, capacity);
+        set_capacity(&mut self.schedule_order, capacity);
+    }
+
+    /// Returns the number of playbacks the schedule can hold before reallocating.
+    #[inline]
+    pub fn schedule_capacity(&self) -> usize {
+        self.schedule_capacity
+    }
+
+    /// Removes the events that have finished playing if the schedule can't hold `event`, and
+    /// returns `true` if it fits in the schedule without reallocating it.
+    #[inline]
+    pub(crate) fn make_room_for(&mut self, event: &PlaybackEvent) -> bool {
+        self.reclaim_for(event.playback_count());
+
+        self.has_capacity_for(event)
+    }
+
+    /// Removes the events that have finished playing from the playback schedule if it can't hold
+    /// `playbacks` more playbacks.
+    #[inline]
+    fn reclaim_for(&mut self, playbacks: usize) {
+        if self.playback_schedule.len().saturating_add(playbacks) > self.schedule_capacity {
+            // Every event before the playback window has finished playing.
+            self.remove_range(0, self.playback_position.0);
+        }
+    }
+
+    /// Returns `true` if `event` fits in the playback schedule without reallocating it.
+    #[inline]
+    fn has_capacity_for(&self, event: &PlaybackEvent) -> bool {
+        let len = self.playback_schedule.len();
+        let capacity = self
+            .event_gains
+            .iter()
+            .map(Vec::capacity)
+            .chain([
+                self.playback_schedule.capacity(),
+                self.event_ids.capacity(),
+                self.event_ends.capacity(),
+                self.event_stops.capacity(),
+                self.event_speeds.capacity(),
+            ])
+            .min()
+            .unwrap_or(0);
+
+        event.playback_count() <= capacity - len
+    }
+
+    /// Sets what this scheduler does with events scheduled before its playback position.
+    #[inline]
+    pub fn set_late_event_policy(&mut self, policy: LateEventPolicy) {
+        self.late_event_policy = policy;
+    }
+
+    /// Returns what this scheduler does with events scheduled before its playback position.
+    #[inline]
+    pub fn late_event_policy(&self) -> LateEventPolicy {
+        self.late_event_policy
+    }
+
+    /// Returns the oldest report of a late event that hasn't been read yet.
+    ///
+    /// Events that are rejected with an error are not reported. Reports are dropped while
+    /// [`LATE_EVENT_QUEUE_CAPACITY`] of them are waiting to be read.
+    #[inline]
+    pub fn pop_late_event(&self) -> Option<LateEvent> {
+        self.late_events.pop()
+    }
+
+    /// Returns the oldest notification of an event starting or stopping that hasn't been read
+    /// yet.
+    ///
+    /// Events are reported once the samples they start or stop on have been played, and a
+    /// [`Scheduler`] reports them a render block at a time, so they can be read slightly after
+    /// they are heard. Their frames are exact, but notifications of different events are not
+    /// always in order. Events that were already playing on the position the scheduler seeked to
+    /// only report their end. Notifications are dropped while
+    /// [`EVENT_NOTIFICATION_QUEUE_CAPACITY`] of them are waiting to be read.
+    #[inline]
+    pub fn pop_event_notification(&self) -> Option<EventNotification> {
+        self.notifications.pop()
+    }
+
+    /// Shares an event notification queue with this scheduler.
+    #[inline]
+    pub(crate) fn share_event_notifications(
+        &mut self,
+        notifications: Arc<BoundedQueue<EventNotification>>,
+    ) {
+        self.notifications = notifications;
+    }
+
+    /// Reports the events that started or stopped on the samples played since the last report,
+    /// up to the given sample.
+    #[inline]
+    pub(crate) fn notify_until(&mut self, sample: SampleType) {
+        let from = self.notified;
+        if sample <= from {
+            return;
+        }
+        self.notified = sample;
+
+        // No event that started before this point can stop after it.
+        let first = self
+            .playback_schedule
+            .partition_point(|&t| t.saturating_add(self.max_event_length) <= from);
+        let last = self.playback_schedule.partition_point(|&t| t < sample);
+
+        let channels = self.channels;
+        for index in first..last {
+            let id = self.event_ids[index];
+            let start = self.playback_schedule[index];
+            let stop = self.event_stops[index];
+
+            if start >= from {
+                self.notify(EventNotification::EventStarted {
+                    id,
+                    source_id: id.source_id,
+                    frame: start / channels as SampleType,
+                });
+            }
+
+            // Events stopped on the sample they start on are reported along their start.
+            if stop <= sample && (stop > from || start >= from) {
+                self.notify(EventNotification::EventEnded {
+                    id,
+                    source_id: id.source_id,
+                    frame: next_frame(stop, channels),
+                });
+            }
+        }
+    }
+
+    /// Reports the end of the events between `first` (inclusive) and `last` (exclusive) that are
+    /// still playing, before they are removed from the schedule.
+    #[inline]
+    fn report_removed(&self, first: usize, last: usize) {
+        let position = self.notified;
+
+        for index in first..last {
+            if self.playback_schedule[index] < position && self.event_stops[index] > position {
+                let id = self.event_ids[index];
+
+                self.notify(EventNotification::EventEnded {
+                    id,
+                    source_id: id.source_id,
+                    frame: next_frame(position, self.channels),
+                });
+            }
+        }
+    }
+
+    /// Adds a notification to the queue, dropping it if the queue is full.
+    #[inline]
+    fn notify(&self, notification: EventNotification) {
+        let _ = self.notifications.push(notification);
+    }
+
+    /// Shares a late event report queue with this scheduler.
+    #[inline]
+    pub(crate) fn share_late_events(&mut self, late_events: Arc<BoundedQueue<LateEvent>>) {
+        self.late_events = late_events;
+    }
+
+    /// Checks that an event can be scheduled, following the late event policy.
+    #[inline]
+    pub(crate) fn check_event(&self, event: &PlaybackEvent) -> Result<(), SchedulerError> {
+        event.check_timestamps(self.channels)?;
+
+        let delay = self.late_event_delay(event.timestamp)?;
+        if delay > 0 {
+            PlaybackEvent {
+                timestamp: event.timestamp + delay,
+                ..*event
+            }
+            .check_timestamps(self.channels)?;
+        }
+
+        Ok(())
+    }
+
+    /// Schedules an event with a known identifier, following the late event policy.
+    ///
+    /// Late events that are dropped are returned as an error, and not reported.
+    #[inline]
+    pub(crate) fn add_event(
+        &mut self,
+        id: EventId,
+        event: PlaybackEvent,
+    ) -> Result<(), SchedulerError> {
+        self.check_event(&event)?;
+        self.reclaim_for(event.playback_count());
+
+        let delay = self.late_event_delay(event.timestamp)?;
+        self.insert_event(
+            id,
+            PlaybackEvent {
+                timestamp: event.timestamp + delay,
+                ..event
+            },
+        );
+
+        self.handle_late_event(id, event.timestamp);
+
+        Ok(())
+    }
+
+    /// Returns the number of frames an event starting on the given frame must be delayed by,
+    /// following the late event policy.
+    #[inline]
+    fn late_event_delay(&self, timestamp: SampleType) -> Result<SampleType, SchedulerError> {
+        let position = next_frame(self.samples_counted, self.channels);
+
+        self.late_event_policy.delay(timestamp, position)
+    }
+
+    /// Reports an event that was just inserted if it starts on a frame that has already been
+    /// played, and makes sure truncated events are part of the playback window.
+    #[inline]
+    fn handle_late_event(&mut self, id: EventId, timestamp: SampleType) {
+        if self.report_if_late(id, timestamp) && self.late_event_policy == LateEventPolicy::Truncate
+        {
+            // The playback window may have already moved past the start of the event.
+            self.widen_window(timestamp * self.channels as SampleType);
+        }
+    }
+
+    /// Widens the playback window to take in the playbacks starting on or after `timestamp` that
+    /// have already started, after they were inserted behind it.
+    ///
+    /// Only the bounds of the window move, so this takes logarithmic time, and events stopped by
+    /// a choke or a voice limit stay stopped.
+    #[inline]
+    fn widen_window(&mut self, timestamp: SampleType) {
+        let sample = self.samples_counted;
+        let first = self.playback_schedule.partition_point(|&t| t < timestamp);
+        let newest = self.playback_schedule.partition_point(|&t| t <= sample);
+
+        self.playback_position = (
+            self.playback_position.0.min(first).min(newest),
+            self.playback_position.1.max(newest),
+        );
+
+        self.limit_voices(sample);
+    }
+
+    /// Reports an event if it starts on a frame that has already been played. Returns `true` if
+    /// the event was late.
+    #[inline]
+    fn report_if_late(&self, id: EventId, timestamp: SampleType) -> bool {
+        let position = next_frame(self.samples_counted, self.channels);
+        if timestamp >= position {
+            return false;
+        }
+
+        self.report_late_event(LateEvent {
+            id,
+            timestamp,
+            position,
+            policy: self.late_event_policy,
+        });
+
+        // Truncated events start on a sample that has already been reported.
+        if self.late_event_policy == LateEventPolicy::Truncate {
+            self.notify(EventNotification::EventStarted {
+                id,
+                source_id: id.source_id,
+                frame: timestamp,
+            });
+        }
+
+        true
+    }
+
+    /// Adds a report to the late event queue, dropping it if the queue is full.
+    #[inline]
+    pub(crate) fn report_late_event(&self, late_event: LateEvent) {
+        let _ = self.late_events.push(late_event);
+    }
+
+    /// Inserts an event and all of its repetitions into the playback schedule.
+    ///
+    /// The event must have been checked first, see [`SingleSourceScheduler::check_event`]. The
+    /// schedule will only allocate if it doesn't have enough capacity to hold the event, see
+    /// [`SingleSourceScheduler::make_room_for`].
+    #[inline]
+    pub(crate) fn insert_event(&mut self, id: EventId, event: PlaybackEvent) {
+        let parameters = ResolvedParameters::new(&event);
+
+        for sample in event.playback_samples(self.channels) {
+            self.insert_timestamp(id, sample, parameters);
+        }
+    }
+
+    /// Inserts many events into the playback schedule at once, following the late event policy.
+    ///
+    /// Every event must have been checked first, see [`SingleSourceScheduler::check_event`].
+    /// Instead of inserting each playback in place, they are appended to the schedule, which is
+    /// then sorted once. Only the new playbacks are sorted, and then merged with the already
+    /// sorted schedule, so this takes linear time in the size of the schedule. The sort reuses
+    /// scratch buffers, so it doesn't allocate unless the schedule grows.
+    pub(crate) fn insert_events(
+        &mut self,
+        events: impl Iterator<Item = (EventId, PlaybackEvent)> + Clone,
+    ) {
+        let playbacks = events.clone().fold(0, |playbacks: usize, (_, event)| {
+            playbacks.saturating_add(event.playback_count())
+        });
+        self.reclaim_for(playbacks);
+        let sorted_len = self.playback_schedule.len();
+
+        // Sorting moves the playbacks of the window, so remember where it starts by timestamp.
+        let oldest = self
+            .playback_schedule
+            .get(self.playback_position.0)
+            .copied()
+            .unwrap_or(SampleType::MAX);
+
+        for (id, event) in events {
+            let delay = self.late_event_delay(event.timestamp).unwrap_or(0);
+            let parameters = ResolvedParameters::new(&event);
+            let delayed = PlaybackEvent {
+                timestamp: event.timestamp + delay,
+                ..event
+            };
+
+            for sample in delayed.playback_samples(self.channels) {
+                self.push_timestamp(id, sample, parameters);
+            }
+
+            self.report_if_late(id, event.timestamp);
+        }
+
+        let earliest = self.playback_schedule[sorted_len..]
+            .iter()
+            .copied()
+            .min()
+            .unwrap_or(SampleType::MAX);
+
+        if !self.playback_schedule.is_sorted() {
+            self.sort_schedule(sorted_len);
+        }
+
+        // Find the window again among the sorted playbacks, and take in the new ones that are
+        // late. Playbacks with the same timestamp as the oldest one may join it, which is fine
+        // since stopped events in the window are skipped.
+        let sample = self.samples_counted;
+        self.playback_position = (
+            self.playback_schedule.partition_point(|&t| t < oldest),
+            self.playback_schedule.partition_point(|&t| t <= sample),
+        );
+        self.widen_window(earliest);
+    }
+
+    /// Sorts the playback schedule by timestamp, keeping playbacks with the same timestamp in
+    /// the order they were added.
+    ///
+    /// The first `sorted_len` playbacks must already be sorted, as they are before a bulk insert.
+    fn sort_schedule(&mut self, sorted_len: usize) {
+        let mut appended = std::mem::take(&mut self.appended_playbacks);
+        let mut order = std::mem::take(&mut self.schedule_order);
+
+        // Sorting the timestamps along with their indices keeps the sort stable, and avoids
+        // looking up the timestamp of every index on each comparison.
+        appended.extend(
+            self.playback_schedule[sorted_len..]
+                .iter()
+                .copied()
+                .zip(sorted_len..),
+        );
+        appended.sort_unstable();
+
+        // Merge the new playbacks after the sorted ones with the same timestamp.
+        let mut next = 0;
+        for &(timestamp, index) in appended.iter() {
+            while next < sorted_len && self.playback_schedule[next] <= timestamp {
+                order.push(next);
+                next += 1;
+            }
+
+            order.push(index);
+        }
+        order.extend(next..sorted_len);
+
+        // Follow each cycle of the permutation, marking the visited playbacks as in place.
+        for start in 0..order.len() {
+            let mut current = start;
+
+            loop {
+                let next = order[current];
+                order[current] = current;
+
+                if next == start || next == current {
+                    break;
+                }
+
+                self.swap_playbacks(current, next);
+                current = next;
+            }
+        }
+
+        appended.clear();
+        order.clear();
+        self.appended_playbacks = appended;
+        self.schedule_order = order;
+    }
+
+    /// Swaps two playbacks in the playback schedule.
+    #[inline]
+    fn swap_playbacks(&mut self, a: usize, b: usize) {
+        self.playback_schedule.swap(a, b);
+        self.event_ids.swap(a, b);
+        self.event_ends.swap(a, b);
+        self.event_stops.swap(a, b);
+        self.event_speeds.swap(a, b);
+        for gains in self.event_gains.iter_mut() {
+            gains.swap(a, b);
+        }
+    }
+
+    /// Inserts a single timestamp into the playback schedule, after any other timestamps with
+    /// the same value, and shifts the playback window accordingly.
+    ///
+    /// This shifts every later playback, which takes linear time. The schedule is bounded by its
+    /// capacity, and events are usually scheduled ahead of the others, so the shift is a short
+    /// copy at the end of the schedule. The render loop scans the schedule as contiguous sorted
+    /// arrays, which a structure with faster inserts would give up. Many events should be
+    /// inserted in bulk instead, see [`SingleSourceScheduler::insert_events`].
+    #[inline]
+    fn insert_timestamp(
+        &mut self,
+        id: EventId,
+        timestamp: SampleType,
+        parameters: ResolvedParameters,
+    ) {
+        let index = self.playback_schedule.partition_point(|&t| t <= timestamp);
+        let end = self.playback_end(timestamp, parameters);
+
+        self.playback_schedule.insert(index, timestamp);
+        self.event_ids.insert(index, id);
+        self.event_ends.insert(index, end);
+        self.event_stops.insert(index, end);
+        self.event_speeds.insert(index, parameters.speed);
+        for (channel, gains) in self.event_gains.iter_mut().enumerate() {
+            gains.insert(
+                index,
+                parameters.channel_gain(channel as u16, self.channels),
+            );
+        }
+
+        if index < self.playback_position.0 {
+            self.playback_position.0 += 1;
+        }
+
+        if index < self.playback_position.1 {
+            self.playback_position.1 += 1;
+        }
+    }
+
+    /// Appends a single timestamp to the playback schedule, without keeping it sorted or
+    /// updating the playback window.
+    #[inline]
+    fn push_timestamp(
+        &mut self,
+        id: EventId,
+        timestamp: SampleType,
+        parameters: ResolvedParameters,
+    ) {
+        let end = self.playback_end(timestamp, parameters);
+
+        self.playback_schedule.push(timestamp);
+        self.event_ids.push(id);
+        self.event_ends.push(end);
+        self.event_stops.push(end);
+        self.event_speeds.push(parameters.speed);
+        for (channel, gains) in self.event_gains.iter_mut().enumerate() {
+            gains.push(parameters.channel_gain(channel as u16, self.channels));
+        }
+    }
+
+    /// Returns the sample at which a playback starting on `timestamp` stops playing, and keeps
+    /// track of the longest playback.
+    #[inline]
+    fn playback_end(
+        &mut self,
+        timestamp: SampleType,
+        parameters: ResolvedParameters,
+    ) -> SampleType {
+        // The number of frames the event plays for, after being resampled to its speed.
+        let source_frames = (self.source.len() / self.channels as usize) as f64;
+        let frames = (source_frames / parameters.speed).ceil() as SampleType;
+        let length = frames.saturating_mul(self.channels as SampleType);
+
+        self.max_event_length = self.max_event_length.max(length);
+
+        timestamp.saturating_add(length)
+    }
+
+    /// Removes the events between `first` (inclusive) and `last` (exclusive) from the playback
+    /// schedule, and shifts the playback window accordingly.
+    #[inline]
+    fn remove_range(&mut self, first: usize, last: usize) {
+        self.report_removed(first, last);
+
+        self.playback_schedule.drain(first..last);
+        self.event_ids.drain(first..last);
+        self.event_ends.drain(first..last);
+        self.event_stops.drain(first..last);
+        self.event_speeds.drain(first..last);
+        for gains in self.event_gains.iter_mut() {
+            gains.drain(first..last);
+        }
+
+        let shift = |position: usize| {
+            if position >= last {
+                position - (last - first)
+            } else {
+                position.min(first)
+            }
+        };
+
+        self.playback_position = (
+            shift(self.playback_position.0),
+            shift(self.playback_position.1),
+        );
+    }
+
+    /// Shortens the schedule to its first `len` playbacks.
+    #[inline]
+    fn truncate_playbacks(&mut self, len: usize) {
+        self.playback_schedule.truncate(len);
+        self.event_ids.truncate(len);
+        self.event_ends.truncate(len);
+        self.event_stops.truncate(len);
+        self.event_speeds.truncate(len);
+        for gains in self.event_gains.iter_mut() {
+            gains.truncate(len);
+        }
+    }
+
+    /// Moves the scheduler to a new sample index, and recomputes the playback window so events
+    /// that are playing at that point resume from the right sample.
+    #[inline]
+    pub(crate) fn set_position(&mut self, sample: SampleType) {
+        self.samples_counted = sample;
+        self.notified = sample;
+
+        // No event that started before this point can still be playing.
+        let first_candidate = self
+            .playback_schedule
+            .partition_point(|&t| t.saturating_add(self.max_event_length) <= sample);
+        let newest = self.playback_schedule.partition_point(|&t| t <= sample);
+
+        // Events stopped after this point play again, until they are stopped again.
+        for index in first_candidate..self.playback_schedule.len() {
+            if self.event_stops[index] > sample {
+                self.event_stops[index] = self.event_ends[index];
+            }
+        }
+
+        let oldest = (first_candidate..newest)
+            .find(|&index| self.event_stops[index] > sample)
+            .unwrap_or(newest);
+
+        self.playback_position = (oldest, newest);
+
+        self.limit_voices(sample);
+    }
+
+    /// Limits the number of events that can play at the same time.
+    ///
+    /// When an event starts while `max_voices` events are already playing, one of them is
+    /// stopped, chosen by `policy`. `None` removes the limit.
+    #[inline]
+    pub fn set_voice_limit(&mut self, max_voices: Option<usize>, policy: VoiceStealPolicy) {
+        self.max_voices = max_voices.map(|max_voices| max_voices.max(1));
+        self.steal_policy = policy;
+    }
+
+    /// Sets the choke group of this source.
+    ///
+    /// When a `Scheduler` starts an event of a source in a choke group, it stops every playing
+    /// event of the other sources in the same group, on the same sample. This is useful for
+    /// sounds that can't play at the same time, like an open and a closed hi-hat. `None` removes
+    /// the source from its group.
+    #[inline]
+    pub fn set_choke_group(&mut self, group: Option<u32>) {
+        self.choke_group = group;
+    }
+
+    /// Returns the choke group of this source.
+    #[inline]
+    pub fn choke_group(&self) -> Option<u32> {
+        self.choke_group
+    }
+
+    /// Sets the volume of this source, as a linear gain applied on top of the gain of every
+    /// event. Negative volumes are treated as silence.
+    ///
+    /// The change is ramped over [`GAIN_RAMP_DURATION`] from the playback position, so it doesn't
+    /// click.
+    #[inline]
+    pub fn set_volume(&mut self, volume: f32) {
+        self.strip.set_volume(volume, self.samples_counted);
+    }
+
+    /// Returns the volume of this source.
+    #[inline]
+    pub fn volume(&self) -> f32 {
+        self.strip.volume()
+    }
+
+    /// Mutes or unmutes this source, keeping its volume. The change is ramped like
+    /// [`SingleSourceScheduler::set_volume`].
+    #[inline]
+    pub fn set_muted(&mut self, muted: bool) {
+        self.strip.set_muted(muted, self.samples_counted);
+    }
+
+    /// Returns `true` if this source is muted.
+    #[inline]
+    pub fn is_muted(&self) -> bool {
+        self.strip.is_muted()
+    }
+
+    /// Solos or unsolos this source.
+    ///
+    /// While any source of a `Scheduler` is soloed, its input and every source that isn't soloed
+    /// are silenced. A soloed source that is muted stays silent. This has no effect on a
+    /// `SingleSourceScheduler` played on its own.
+    #[inline]
+    pub fn set_soloed(&mut self, soloed: bool) {
+        self.strip.set_soloed(soloed, self.samples_counted);
+    }
+
+    /// Returns `true` if this source is soloed.
+    #[inline]
+    pub fn is_soloed(&self) -> bool {
+        self.strip.is_soloed()
+    }
+
+    /// Silences this source because another source is soloed, or brings it back.
+    #[inline]
+    pub(crate) fn set_silenced_by_solo(&mut self, silenced: bool) {
+        self.strip.set_silenced(silenced, self.samples_counted);
+    }
+
+    /// Returns the automation lane of the gain of this source.
+    #[inline]
+    pub fn gain_automation(&self) -> &AutomationLane {
+        &self.gain_automation
+    }
+
+    /// Returns the automation lane of the gain of this source, so its points can be changed.
+    ///
+    /// The lane is a linear gain, applied on top of the volume of the source and the gain of
+    /// every event. It is evaluated on the frame of every sample played, so a point on the frame
+    /// of an event applies from the first sample of the event.
+    #[inline]
+    pub fn gain_automation_mut(&mut self) -> &mut AutomationLane {
+        &mut self.gain_automation
+    }
+
+    /// Sets the ducking priority of this source.
+    ///
+    /// When a `Scheduler` has ducking enabled, see [`Scheduler::set_ducking`], a source with a
+    /// priority turns the input down while it is sounding, along with every source that has a
+    /// lower priority. Priorities above `DUCKING_LEVELS - 1` are lowered to it. `None` removes
+    /// the source from ducking, so it neither ducks nor is ducked.
+    ///
+    /// See [`ducking::DUCKING_LEVELS`].
+    #[inline]
+    pub fn set_ducking_priority(&mut self, priority: Option<u8>) {
+        self.ducking_priority =
+            priority.map(|priority| priority.min(ducking::DUCKING_LEVELS as u8 - 1));
+    }
+
+    /// Returns the ducking priority of this source.
+    #[inline]
+    pub fn ducking_priority(&self) -> Option<u8> {
+        self.ducking_priority
+    }
+
+    /// Marks the samples of `sounding`, which starts on the given sample, on which an event of
+    /// this source is playing. Muted and soloed out sources are never sounding.
+    #[inline]
+    pub(crate) fn mark_sounding(&self, start: SampleType, sounding: &mut [bool]) {
+        if self.strip.is_silent() {
+            return;
+        }
+
+        let end = start + sounding.len() as SampleType;
+        let first = self
+            .playback_schedule
+            .partition_point(|&t| t.saturating_add(self.max_event_length) <= start);
+        let last = self.playback_schedule.partition_point(|&t| t < end);
+
+        for event in first..last {
+            let from = start.max(self.playback_schedule[event]);
+            let to = end.min(self.event_stops[event]);
+
+            if from < to {
+                sounding[(from - start) as usize..(to - start) as usize].fill(true);
+            }
+        }
+    }
+
+    /// Returns the gain of this source on the given sample, from its channel strip and its gain
+    /// automation.
+    #[inline]
+    fn gain_at(&self, sample: SampleType) -> f32 {
+        let frame = sample / self.channels as SampleType;
+        let automation = self.gain_automation.value_at(frame).unwrap_or(1.0);
+
+        self.strip.gain_at(sample) * automation
+    }
+
+    /// Returns `true` if an event starts on the given sample.
+    #[inline]
+    pub(crate) fn has_event_starting_at(&self, sample: SampleType) -> bool {
+        self.playback_schedule.binary_search(&sample).is_ok()
+    }
+
+    /// Returns the sample of the last event that started at or before the given sample.
+    #[inline]
+    pub(crate) fn last_start_before(&self, sample: SampleType) -> Option<SampleType> {
+        let index = self.playback_schedule.partition_point(|&t| t <= sample);
+
+        index
+            .checked_sub(1)
+            .map(|index| self.playback_schedule[index])
+    }
+
+    /// Stops every event that started before the given sample and is still playing on it.
+    #[inline]
+    pub(crate) fn choke(&mut self, sample: SampleType) {
+        let (oldest, newest) = self.playback_position;
+
+        for index in oldest..newest {
+            if self.playback_schedule[index] < sample && self.event_stops[index] > sample {
+                self.event_stops[index] = sample;
+            }
+        }
+    }
+
+    /// Stops events until no more than `max_voices` are playing on the given sample.
+    #[inline]
+    fn limit_voices(&mut self, sample: SampleType) {
+        let Some(max_voices) = self.max_voices else {
+            return;
+        };
+
+        let (oldest, newest) = self.playback_position;
+        let mut voices = (oldest..newest)
+            .filter(|&index| self.event_stops[index] > sample)
+            .count();
+
+        while voices > max_voices {
+            let Some(victim) = self.voice_to_steal(sample) else {
+                break;
+            };
+
+            self.event_stops[victim] = sample;
+            voices -= 1;
+        }
+    }
+
+    /// Returns the index of the playing event to stop on the given sample, following the steal
+    /// policy.
+    #[inline]
+    fn voice_to_steal(&self, sample: SampleType) -> Option<usize> {
+        let (oldest, newest) = self.playback_position;
+        let playing = |index: &usize| self.event_stops[*index] > sample;
+
+        // Prefer stealing events that were already playing over the ones starting now.
+        let started = |index: &usize| self.playback_schedule[*index] < sample;
+        let mut candidates = (oldest..newest).filter(playing).filter(started);
+
+        let victim = match self.steal_policy {
+            VoiceStealPolicy::Oldest => candidates.next(),
+            VoiceStealPolicy::Quietest => candidates
+                .min_by(|&a, &b| self.event_peak_gain(a).total_cmp(&self.event_peak_gain(b))),
+        };
+
+        victim.or_else(|| (oldest..newest).find(playing))
+    }
+
+    /// Returns the highest gain of an event across every channel.
+    #[inline]
+    fn event_peak_gain(&self, index: usize) -> f32 {
+        self.event_gains
+            .iter()
+            .fold(0.0, |peak, gains| peak.max(gains[index].abs()))
+    }
+
+    /// Moves the playback window to the given sample, stopping finished events and starting the
+    /// ones scheduled on or before it.
+    #[inline]
+    fn update_window(&mut self, sample: SampleType) {
+        if self.playback_schedule.is_empty() {
+            return;
+        }
+
+        while self.playback_position.0 < self.playback_position.1
+            && self.event_stops[self.playback_position.0] <= sample
+        {
+            self.playback_position.0 += 1
+        }
+
+        let newest = self.playback_position.1;
+        while self.playback_position.1 < self.playback_schedule.len()
+            && self.playback_schedule[self.playback_position.1] <= sample
+        {
+            self.playback_position.1 += 1
+        }
+
+        if self.playback_position.1 != newest {
+            self.limit_voices(sample);
+        }
+    }
+
+    /// Returns the sample on which the last event of the schedule stops playing, or `0` if the
+    /// schedule is empty.
+    #[inline]
+    pub(crate) fn schedule_end(&self) -> SampleType {
+        let Some(&last) = self.playback_schedule.last() else {
+            return 0;
+        };
+
+        // Only the events that could still be playing when the last one starts can stop later.
+        let first = self
+            .playback_schedule
+            .partition_point(|&t| t.saturating_add(self.max_event_length) <= last);
+
+        self.event_stops[first..].iter().copied().max().unwrap_or(0)
+    }
+
+    /// Returns the sample of the first event that starts after the given sample.
+    #[inline]
+    pub(crate) fn next_start_after(&self, sample: SampleType) -> Option<SampleType> {
+        let index = self.playback_schedule.partition_point(|&t| t <= sample);
+
+        self.playback_schedule.get(index).copied()
+    }
+
+    /// Renders the next `buffer.len()` samples into `buffer`, overwriting its contents.
+    ///
+    /// This produces the same samples as calling [`Iterator::next`] once for every sample of the
+    /// buffer, with silence where it would return `None`, but it mixes each playing event as a
+    /// contiguous block instead of one sample at a time. Use it instead of the iterator when
+    /// rendering many events.
+    #[inline]
+    #[nonblocking]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn fill_buffer(&mut self, buffer: &mut [Sample]) {
+        buffer.fill(0.0);
+
+        self.mix_into(buffer);
+        self.notify_until(self.samples_counted);
+    }
+
+    /// Adds the next `buffer.len()` samples to `buffer`.
+    #[inline]
+    #[nonblocking]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub(crate) fn mix_into(&mut self, buffer: &mut [Sample]) {
+        let mut offset = 0;
+
+        while offset < buffer.len() {
+            let start = self.samples_counted;
+            self.update_window(start);
+
+            // Render up to the next event start, where the voice limit must be applied again.
+            let remaining = (buffer.len() - offset) as SampleType;
+            let length = self
+                .playback_schedule
+                .get(self.playback_position.1)
+                .map_or(remaining, |&next| (next - start).min(remaining));
+
+            // The gain changes on every sample of a ramp or an automation segment, so those are
+            // mixed a chunk at a time, and the rest up to where the gain can change next.
+            let channels = self.channels as SampleType;
+            let (automated, automation_end) = self.gain_automation.segment_at(start / channels);
+            let mut length = length.min(automation_end.saturating_mul(channels) - start);
+
+            let ramp_end = self.strip.ramp_end_after(start);
+            if ramp_end > start {
+                length = length.min(ramp_end - start);
+            }
+
+            let changing = automated || ramp_end > start;
+            if changing {
+                length = length.min(RAMP_CHUNK_SIZE as SampleType);
+            }
+            let end = offset + length as usize;
+
+            if changing {
+                self.mix_ramp(&mut buffer[offset..end], start);
+            } else {
+                self.mix_window(&mut buffer[offset..end], start, self.gain_at(start));
+            }
+
+            self.samples_counted += length;
+            offset = end;
+        }
+    }
+
+    /// Adds every event of the playback window to `buffer`, which starts on the given sample,
+    /// with the gain of every sample.
+    ///
+    /// The buffer must fit in `ramp_buffer`, and no event may start inside it.
+    #[inline]
+    fn mix_ramp(&mut self, buffer: &mut [Sample], start: SampleType) {
+        let mut ramp_buffer = std::mem::take(&mut self.ramp_buffer);
+        let samples = &mut ramp_buffer[..buffer.len()];
+
+        samples.fill(0.0);
+        self.mix_window(samples, start, 1.0);
+
+        for ((output, sample), index) in buffer.iter_mut().zip(samples.iter()).zip(start..) {
+            *output += sample * self.gain_at(index);
+        }
+
+        self.ramp_buffer = ramp_buffer;
+    }
+
+    /// Adds every event of the playback window to `buffer`, which starts on the given sample,
+    /// multiplied by `volume`.
+    ///
+    /// No event may start inside the buffer.
+    #[inline]
+    fn mix_window(&self, buffer: &mut [Sample], start: SampleType, volume: f32) {
+        if volume == 0.0 {
+            return;
+        }
+
+        let channels = self.channels as SampleType;
+        let end = start + buffer.len() as SampleType;
+        let (oldest, newest) = self.playback_position;
+
+        for event in oldest..newest {
+            let timestamp = self.playback_schedule[event];
+            let from = start.max(timestamp);
+            let to = end.min(self.event_stops[event]);
+            if from >= to {
+                continue;
+            }
+
+            let output = &mut buffer[(from - start) as usize..(to - start) as usize];
+            let gains = self
+                .event_gains
+                .iter()
+                .map(move |gains| gains[event] * volume)
+                .cycle()
+                .skip((from % channels) as usize);
+
+            if self.event_speeds[event] == 1.0 {
+                let first = usize::try_from(from - timestamp).unwrap_or(usize::MAX);
+                let source = self.source.get(first..).unwrap_or_default();
+
+                if simd::GAIN_PATTERN_LEN.is_multiple_of(self.channels as usize) {
+                    let mut pattern = [0.0; simd::GAIN_PATTERN_LEN];
+                    pattern
+                        .iter_mut()
+                        .zip(gains)
+                        .for_each(|(lane, gain)| *lane = gain);
+
+                    simd::mix_samples_with_gains(output, source, pattern);
+                } else {
+                    for ((output, sample), gain) in output.iter_mut().zip(source).zip(gains) {
+                        *output += sample * gain;
+                    }
+                }
+            } else {
+                for ((output, sample), gain) in output.iter_mut().zip(from..to).zip(gains) {
+                    *output += self.resampled_sample(event, sample) * gain;
+                }
+            }
+        }
+    }
+
+    /// Returns the sample an event plays on the given sample index, interpolating between the
+    /// samples of the source when it plays at a different speed.
+    #[inline]
+    fn resampled_sample(&self, event: usize, sample: SampleType) -> Sample {
+        let channels = self.channels as SampleType;
+        let channel = sample % channels;
+
+        let elapsed = (sample - self.playback_schedule[event]) / channels;
+        let position = elapsed as f64 * self.event_speeds[event];
+        let frame = position.floor();
+        let fraction = (position - frame) as Sample;
+
+        let index = (frame as SampleType)
+            .saturating_mul(channels)
+            .saturating_add(channel);
+        let source_sample = |index: SampleType| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| self.source.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        let current = source_sample(index);
+        let next = source_sample(index.saturating_add(channels));
+
+        current + (next - current) * fraction
+    }
+}
+
+impl Iterator for SingleSourceScheduler {
+    type Item = Sample;
+
+    #[inline]
+    #[nonblocking]
+    #[cfg_attr(feature = "profiler", instrument(name = "SingleSourceScheduler::next"))]
+    fn next(&mut self) -> Option<Sample> {
+        // Cache the sample index for this sample
+        let s = self.samples_counted;
+
+        // Set the sample index for the next sample
+        self.samples_counted += 1;
+
+        self.update_window(s);
+        self.notify_until(self.samples_counted);
+
+        let channel = (s % self.channels as SampleType) as usize;
+        let parameters = simd::EventParameters {
+            gains: &self.event_gains[channel],
+            speeds: &self.event_speeds,
+            stops: &self.event_stops,
+            channels: self.channels,
+        };
+
+        let gain = self.gain_at(s);
+
+        simd::retrieve_and_mix_samples_with_parameters(
+            &self.source,
+            &self.playback_schedule,
+            parameters,
+            self.playback_position,
+            s,
+        )
+        .map(|sample| sample * gain)
+    }
+
+    #[inline]
+    #[cfg_attr(
+        feature = "profiler",
+        instrument(name = "SingleSourceScheduler::size_hint")
+    )]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let Some(&last_element) = self.playback_schedule.last() else {
+            return (0, None);
+        };
+
+        let last_element: usize = last_element.try_into().unwrap_or(usize::MAX);
+        let lower_bound = last_element.saturating_add(self.source.len());
+
+        (lower_bound, None)
+    }
+}
+
+impl Source for SingleSourceScheduler {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = self.channels as SampleType;
+
+        // Keep the current channel, so the next sample is still for the channel the output expects.
+        let channel = self.samples_counted % channels;
+        let frame = time::duration_to_frames(pos, self.sample_rate);
+
+        self.set_position(frame.saturating_mul(channels).saturating_add(channel));
+
+        Ok(())
+    }
+}
+
+/// A source that schedules playback of other sources at precise timestamps.
+///
+/// The `Scheduler` takes an input source and allows you to schedule additional sources
+/// to be played at specific sample timestamps. It mixes the output of the scheduled sources
+/// with the input source.
+///
+/// # Type Parameters
+///
+/// * `I`: The type of the input audio source, which will play behind other scheduled sources.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use rodio::{Decoder, Source, OutputStreamBuilder};
+/// use rodio_scheduler::{Scheduler, PlaybackEvent};
+///
+/// # fn main() {
+///    // Get an output stream handle to the default physical sound device.
+///    let stream = OutputStreamBuilder::open_default_stream().unwrap();
+///
+///    // Load a sound from a file.
+///    let metronome = File::open("assets/metronome.wav").unwrap();
+///    let metronome_decoder_source = Decoder::new(metronome).unwrap();
+///
+///    // Create a scheduler.
+///    let mut scheduler = Scheduler::new(metronome_decoder_source, 48000, 2);
+///
+///    // Load a sound to be scheduled.
+///    let note_hit = File::open("assets/note_hit.wav").unwrap();
+///    let note_hit_decoder_source = Decoder::new(note_hit).unwrap();
+///
+///    // Add the sound to the scheduler, with a list of playback events to schedule.
+///    let note_hit_id = scheduler.add_source(note_hit_decoder_source);
+///
+///    // Schedule the sound to be played at a specific timestamp.
+///    let event = PlaybackEvent {
+///        source_id: note_hit_id.into(),
+///        timestamp: scheduler.sample_rate() as u64 * 2, // 2 seconds in
+///        repeat: None,
+///        ..Default::default()
+///    };
+///    scheduler.schedule(event).unwrap();
+///
+///    // Load another sound to be scheduled.
+///    let sine_clip = rodio::source::SineWave::new(440.0).take_duration(std::time::Duration::from_millis(500));
+///
+///    // Schedule the new sound.
+///    let sine_clip_id = scheduler.add_source(sine_clip);
+///    let event = PlaybackEvent {
+///        source_id: sine_clip_id.into(),
+///        timestamp: scheduler.sample_rate() as u64 * 4, // 4 seconds in
+///        repeat: None,
+///        ..Default::default()
+///    };
+///    scheduler.schedule(event).unwrap();
+///
+///    // Play the scheduled sounds.
+///    let _ = stream.mixer().add(scheduler);
+///
+///    // The sound plays in a separate audio thread,
+///    // so we need to keep the main thread alive while it's playing.
+///    std::thread::sleep(std::time::Duration::from_secs(5));
+/// # }
+/// ```
+pub struct Scheduler<I>
+where
+    I: Source,
+{
+    /// The main input source that the scheduled sources will be mixed with.
+    input: UniformSourceIterator<I>,
+    /// A vector of `SingleSourceScheduler`s, each managing a single scheduled source.
+    sources: Vec<SingleSourceScheduler>,
+    /// A vector of `StreamingSourceScheduler`s, for sources that are decoded on demand.
+    streams: Vec<StreamingSourceScheduler>,
+    /// The samples mixed on every output sample: the sample of the block rendered by the
+    /// scheduled sources, then one sample for every stream. Keeping them contiguous lets them be
+    /// summed with SIMD.
+    child_samples: Vec<Sample>,
+    /// The volume of the input, which is also silenced while any source is soloed.
+    input_strip: ChannelStrip,
+    /// The gain of the input over time, applied on top of `input_strip`.
+    input_automation: AutomationLane,
+    /// Turns the input and lower priority sources down while sources with a ducking priority
+    /// are sounding.
+    ducker: ducking::Ducker,
+    /// The last stage of the output, which keeps it from clipping.
+    master: master::MasterBus,
+    /// State shared with every `SchedulerHandle` created from this scheduler.
+    shared: Arc<handle::SharedState>,
+    /// Number of samples counted, used to keep sources added during playback in sync.
+    samples_counted: SampleType,
+    /// When the scheduler stops producing samples.
+    end_policy: EndPolicy,
+    /// The sample on which the input ended, if it has.
+    input_end: Option<SampleType>,
+    /// The sample on which the last event of the scheduled sources stops, cached since it
+    /// is checked on every sample once the input has ended. Cleared whenever a schedule or the
+    /// stop of an event may have changed.
+    events_end: Cell<Option<SampleType>>,
+    /// The scheduled sources, rendered ahead of the output by `Iterator::next`.
+    block: Box<[Sample]>,
+    /// The index of the next sample of `block` to be played.
+    block_position: usize,
+    /// The number of samples of `block` that have been rendered.
+    block_len: usize,
+}
+
+impl<I> Scheduler<I>
+where
+    I: Source,
+{
+    /// Creates a new `Scheduler`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input`: The main audio source.
+    /// * `sample_rate`: The sample rate of the output audio.
+    /// * `channels`: The number of channels in the output audio.
+    #[inline]
+    pub fn new(input: I, sample_rate: u32, channels: u16) -> Scheduler<I> {
+        let shared = handle::SharedState::new(sample_rate, channels, 0);
+        let meter = Arc::clone(&shared.master_gain);
+
+        Scheduler {
+            input: UniformSourceIterator::new(input, channels, sample_rate),
+            sources: Vec::new(),
+            streams: Vec::new(),
+            child_samples: vec![0.0],
+            input_strip: ChannelStrip::new(sample_rate, channels),
+            input_automation: AutomationLane::new(),
+            ducker: ducking::Ducker::new(sample_rate, channels),
+            master: master::MasterBus::new(sample_rate, channels, meter),
+            shared: Arc::new(shared),
+            samples_counted: 0,
+            end_policy: EndPolicy::Never,
+            input_end: None,
+            events_end: Cell::new(None),
+            block: vec![0.0; RENDER_BLOCK_SIZE].into_boxed_slice(),
+            block_position: 0,
+            block_len: 0,
+        }
+    }
+
+    /// Creates a new `Scheduler` with a specified capacity for scheduled sources.
+    ///
+    /// # Arguments
+    ///
+    /// * `input`: The main audio source.
+    /// * `sample_rate`: The sample rate of the output audio.
+    /// * `channels`: The number of channels in the output audio.
+    /// * `capacity`: The initial capacity for the number of scheduled sources.
+    #[inline]
+    pub fn with_capacity(
+        input: I,
+        sample_rate: u32,
+        channels: u16,
+        capacity: usize,
+    ) -> Scheduler<I> {
+        let sources = Vec::with_capacity(capacity);
+        let shared = handle::SharedState::new(sample_rate, channels, sources.capacity());
+        let meter = Arc::clone(&shared.master_gain);
+
+        Scheduler {
+            input: UniformSourceIterator::new(input, channels, sample_rate),
+            sources,
+            streams: Vec::new(),
+            child_samples: vec![0.0],
+            input_strip: ChannelStrip::new(sample_rate, channels),
+            input_automation: AutomationLane::new(),
+            ducker: ducking::Ducker::new(sample_rate, channels),
+            master: master::MasterBus::new(sample_rate, channels, meter),
+            shared: Arc::new(shared),
+            samples_counted: 0,
+            end_policy: EndPolicy::Never,
+            input_end: None,
+            events_end: Cell::new(None),
+            block: vec![0.0; RENDER_BLOCK_SIZE].into_boxed_slice(),
+            block_position: 0,
+            block_len: 0,
+        }
+    }
+
+    /// Adds a new source to the scheduler.
+    ///
+    /// Returns a `usize` identifier for the new source, which can be used to schedule playback events.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn add_source(&mut self, source: impl Source) -> usize {
+        let source_scheduler: SingleSourceScheduler =
+            SingleSourceScheduler::new(source, self.sample_rate(), self.channels());
+
+        self.push_source(source_scheduler)
+    }
+
+    /// Adds a new source to the scheduler that plays the samples of a `SampleBuffer`.
+    ///
+    /// The samples are shared with the buffer, so the same buffer can be added many times, with a
+    /// different playback schedule each time, without copying it. Buffers that don't match the
+    /// sample rate and channel count of the scheduler are converted first, which does copy them.
+    ///
+    /// Returns a `usize` identifier for the new source, which can be used to schedule playback events.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn add_shared_source(&mut self, buffer: SampleBuffer) -> usize {
+        let buffer = buffer.with_format(self.sample_rate(), self.channels());
+
+        self.push_source(SingleSourceScheduler::from_buffer(buffer))
+    }
+
+    /// Adds a new source to the scheduler, failing if it has no samples.
+    ///
+    /// See [`Scheduler::add_source`]. Returns [`SchedulerError::EmptySource`] if the source has no
+    /// samples, or [`SchedulerError::NoChannels`] if it has no channels, in which case no source
+    /// is added.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn try_add_source(&mut self, source: impl Source) -> Result<usize, SchedulerError> {
+        let buffer = SampleBuffer::try_new(source, self.sample_rate(), self.channels())?;
+
+        Ok(self.push_source(SingleSourceScheduler::from_buffer(buffer)))
+    }
+
+    #[inline]
+    fn push_source(&mut self, mut source_scheduler: SingleSourceScheduler) -> usize {
+        source_scheduler.share_event_counter(Arc::clone(&self.shared.event_counter));
+        source_scheduler.share_late_events(Arc::clone(&self.shared.late_events));
+        source_scheduler.share_event_notifications(Arc::clone(&self.shared.notifications));
+        source_scheduler.set_position(self.samples_counted);
+
+        // The new source must be rendered along the others from this point.
+        self.rewind_block();
+
+        // Hold the lock while the source is added, so handles can't reserve the same identifier.
+        let shared = Arc::clone(&self.shared);
+        let mut slots = shared
+            .source_slots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        // Apply any source that was already added through a handle first, to keep the identifiers
+        // in order.
+        self.process_commands();
+        while self.shared.empty_sources.pop().is_some() {}
+
+        source_scheduler.set_source_id(self.sources.len());
+        self.sources.push(source_scheduler);
+
+        slots.next_id = self.sources.len();
+        slots.capacity = self.sources.capacity();
+
+        self.sources.len() - 1
+    }
+
+    /// Adds a new streaming source to the scheduler.
+    ///
+    /// Unlike [`Scheduler::add_source`], the source is not loaded in memory. Instead, `factory` is
+    /// called on a background thread to create a new instance of the source every time one of its
+    /// events starts playing. See [`StreamingSourceScheduler`] for details.
+    ///
+    /// Streaming sources are numbered apart from the other sources, so the identifier of the new
+    /// source is a [`SourceId::Streamed`], which can be used to schedule its events. Its index can
+    /// be used with [`Scheduler::get_streaming_scheduler`]. Fails with
+    /// [`SchedulerError::SpawnDecoder`] if the background thread can't be spawned, in which case
+    /// no source is added.
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn add_streaming_source<F, S>(&mut self, factory: F) -> Result<SourceId, SchedulerError>
+    where
+        F: Fn() -> S + Send + 'static,
+        S: Source + Send + 'static,
+    {
+        let mut stream =
+            StreamingSourceScheduler::new(factory, self.sample_rate(), self.channels())?;
+        stream.share_event_counter(Arc::clone(&self.shared.event_counter));
+        stream.share_late_events(Arc::clone(&self.shared.late_events));
+        stream.share_event_notifications(Arc::clone(&self.shared.notifications));
+        stream.set_stream_id(self.streams.len());
+        stream.set_position(self.samples_counted);
+
+        self.streams.push(stream);
+        self.child_samples.push(0.0);
+
+        Ok(SourceId::Streamed(self.streams.len() - 1))
+    }
+
+    /// Creates a new `SchedulerHandle` that can be used to control this scheduler from other
+    /// threads, even after it has been moved into the audio thread.
+    #[inline]
+    pub fn handle(&self) -> SchedulerHandle {
+        SchedulerHandle::new(Arc::clone(&self.shared))
+    }
+
+    /// Creates a new `PlayheadHandle` that reads the playback position of this scheduler from
+    /// other threads, even after it has been moved into the audio thread.
+    #[inline]
+    pub fn playhead(&self) -> PlayheadHandle {
+        PlayheadHandle::new(Arc::clone(&self.shared.playhead))
+    }
+
+    /// Publishes the position of the scheduler to its playheads, on the timeline of the schedule.
+    #[inline]
+    fn update_playhead(&self) {
+        let latency = self.master.latency() * self.channels() as SampleType;
+
+        self.shared
+            .playhead
+            .set_position(self.samples_counted.saturating_sub(latency));
+    }
+
+    /// Applies every command sent by the handles of this scheduler.
+    ///
+    /// This never allocates or blocks: commands that would require the scheduler to grow its
+    /// buffers are dropped instead. The render block is rewound at most once, before the first
+    /// command that changes it, so a burst of commands costs about as much as a single one.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    fn process_commands(&mut self) {
+        let mut rewound = false;
+
+        while let Some(command) = self.shared.commands.pop() {
+            // Commands apply from the next sample played, not the next one rendered.
+            if !rewound && self.changes_block(&command) {
+                self.rewind_block();
+                rewound = true;
+            }
+
+            self.events_end.set(None);
+
+            match command {
+                handle::Command::ScheduleEvent(id, event) => {
+                    let added = if id.is_streamed() {
+                        if let Some(stream) = self.streams.get_mut(id.source_id())
+                            && stream.make_room_for(&event)
+                        {
+                            Some(stream.add_event(id, event))
+                        } else {
+                            None
+                        }
+                    } else if let Some(source) = self.sources.get_mut(id.source_id())
+                        && source.make_room_for(&event)
+                    {
+                        Some(source.add_event(id, event))
+                    } else {
+                        None
+                    };
+
+                    // Handles can't be told the event was dropped, so report it instead.
+                    let dropped = match added {
+                        Some(Err(SchedulerError::EventInPast {
+                            timestamp,
+                            position,
+                        })) => {
+                            let _ = self.shared.late_events.push(LateEvent {
+                                id,
+                                timestamp,
+                                position,
+                                policy: LateEventPolicy::Drop,
+                            });
+                            false
+                        }
+                        Some(result) => result.is_err(),
+                        None => true,
+                    };
+
+                    if dropped {
+                        let _ = self.shared.notifications.push(EventNotification::EventDropped {
+                            id,
+                            source_id: id.source_id(),
+                            frame: event.timestamp,
+                        });
+                    }
+                }
+                handle::Command::SetLateEventPolicy(source_id, policy) => {
+                    if let Some(source) = self.sources.get_mut(source_id) {
+                        source.set_late_event_policy(policy);
+                    }
+                }
+                handle::Command::CancelEvent(id) if id.is_streamed() => {
+                    if let Some(stream) = self.streams.get_mut(id.source_id()) {
+                        stream.cancel_event(id);
+                    }
+                }
+                handle::Command::CancelEvent(id) => {
+                    if let Some(source) = self.sources.get_mut(id.source_id()) {
+                        source.cancel_event(id);
+                    }
+                }
+                handle::Command::AddSource(source_id, mut source_box) => {
+                    // Handles reserve identifiers in order and only up to our capacity, so this
+                    // never reallocates.
+                    debug_assert_eq!(source_id, self.sources.len());
+
+                    let Some(mut source) = source_box.take() else {
+                        continue;
+                    };
+
+                    // The box is freed by the handles, which empty the queue before adding a
+                    // source, and it holds as many boxes as there can be commands.
+                    let _ = self.shared.empty_sources.push(source_box);
+
+                    // The new source has no events yet, so it can join the others where they
+                    // are without rewinding them.
+                    source.set_position(self.render_position());
+                    self.sources.push(source);
+                }
+                handle::Command::SetMasterStage(stage) => {
+                    self.master.set_stage(stage);
+                }
+                handle::Command::SetVolume(source_id, volume) => {
+                    if let Some(source) = self.sources.get_mut(source_id) {
+                        source.set_volume(volume);
+                    }
+                }
+                handle::Command::SetMuted(source_id, muted) => {
+                    if let Some(source) = self.sources.get_mut(source_id) {
+                        source.set_muted(muted);
+                    }
+                }
+                handle::Command::SetSoloed(source_id, soloed) => {
+                    if let Some(source) = self.sources.get_mut(source_id) {
+                        source.set_soloed(soloed);
+                    }
+                }
+                handle::Command::SetInputVolume(volume) => {
+                    self.set_input_volume(volume);
+                }
+                handle::Command::SetDucking(ducking) => {
+                    self.ducker.set_settings(ducking);
+                }
+                handle::Command::SetEndPolicy(policy) => {
+                    self.set_end_policy(policy);
+                }
+                handle::Command::ClearSchedule => {
+                    for source in self.sources.iter_mut() {
+                        source.clear();
+                    }
+                    for stream in self.streams.iter_mut() {
+                        stream.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mixes a sample rendered by the scheduled sources with the next sample of every stream.
+    ///
+    /// The scheduled sources are not mixed here: `render_sources` has each of them add its
+    /// events to the block as contiguous slices, so they arrive as a single sample. Streams are
+    /// decoded one sample at a time, so their samples are collected into `child_samples` after
+    /// it, and the whole slice is summed with SIMD. It is summed rather than mixed with
+    /// [`simd::mix_samples`], which would clip the mix before the master stage.
+    #[inline]
+    #[nonblocking]
+    fn mix_children(&mut self, scheduled_sample: Sample) -> Sample {
+        self.child_samples[0] = scheduled_sample;
+        for (output, stream) in self.child_samples[1..].iter_mut().zip(self.streams.iter_mut()) {
+            *output = stream.next().unwrap_or_default();
+        }
+
+        simd::sum_samples(&self.child_samples)
+    }
+
+    /// Returns `true` if a command changes the samples rendered ahead of the output, so the
+    /// render block must be rewound before it is applied.
+    #[inline]
+    fn changes_block(&self, command: &handle::Command) -> bool {
+        match command {
+            handle::Command::ScheduleEvent(_, event) => {
+                let channels = self.channels() as SampleType;
+
+                event.timestamp.saturating_mul(channels) < self.render_position()
+            }
+            handle::Command::CancelEvent(_)
+            | handle::Command::SetVolume(..)
+            | handle::Command::SetMuted(..)
+            | handle::Command::SetSoloed(..)
+            | handle::Command::SetDucking(_)
+            | handle::Command::ClearSchedule => true,
+            handle::Command::AddSource(..)
+            | handle::Command::SetLateEventPolicy(..)
+            | handle::Command::SetMasterStage(_)
+            | handle::Command::SetInputVolume(_)
+            | handle::Command::SetEndPolicy(_) => false,
+        }
+    }
+
+    /// Returns the sample the scheduled sources have been rendered up to.
+    #[inline]
+    fn render_position(&self) -> SampleType {
+        self.samples_counted + (self.block_len - self.block_position) as SampleType
+    }
+
+    /// Discards the samples rendered ahead of the output, and moves the sources back to the
+    /// output position, so changes to their schedules are heard on the next sample.
+    #[inline]
+    fn rewind_block(&mut self) {
+        self.notify_sources();
+
+        // The schedules may be changed once this returns.
+        self.events_end.set(None);
+
+        if self.block_position == self.block_len {
+            return;
+        }
+
+        self.ducker.rewind(self.block_position);
+
+        self.block_position = 0;
+        self.block_len = 0;
+        self.move_sources(self.samples_counted);
+    }
+
+    /// Reports the events that started or stopped on the samples played so far.
+    #[inline]
+    fn notify_sources(&mut self) {
+        for source in self.sources.iter_mut() {
+            source.notify_until(self.samples_counted);
+        }
+    }
+
+    /// Moves every scheduled source to the given sample.
+    #[inline]
+    fn move_sources(&mut self, sample: SampleType) {
+        self.events_end.set(None);
+
+        for source in self.sources.iter_mut() {
+            source.set_position(sample);
+        }
+
+        // Apply the last choke of every source before this point, since it may still be silencing
+        // other sources.
+        for source in 0..self.sources.len() {
+            let group = self.sources[source].choke_group();
+            let start = self.sources[source].last_start_before(sample);
+
+            if let (Some(group), Some(start)) = (group, start) {
+                self.choke_group(group, source, start);
+            }
+        }
+    }
+
+    /// Renders the scheduled sources into `buffer`, overwriting its contents.
+    ///
+    /// The sources must all be on the same sample, which is where the buffer starts.
+    #[inline]
+    #[nonblocking]
+    #[cfg_attr(feature = "profiler", instrument)]
+    fn render_sources(&mut self, buffer: &mut [Sample], start: SampleType) {
+        buffer.fill(0.0);
+        self.ducker.start_render();
+
+        self.apply_solos(start);
+
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let sample = start + offset as SampleType;
+
+            // Choke groups must be applied before the sources produce the sample their events
+            // start on, so render up to the next event start of a source in a group.
+            self.apply_choke_groups(sample);
+
+            let end = self
+                .sources
+                .iter()
+                .filter(|source| source.choke_group().is_some())
+                .filter_map(|source| source.next_start_after(sample))
+                .min()
+                .map_or(buffer.len(), |next| {
+                    buffer.len().min(offset + (next - sample) as usize)
+                });
+
+            if self.ducker.is_active() {
+                self.render_ducked(&mut buffer[offset..end], offset, sample);
+            } else {
+                for source in self.sources.iter_mut() {
+                    source.mix_into(&mut buffer[offset..end]);
+                }
+            }
+
+            offset = end;
+        }
+
+        // Chokes and voice limits may have stopped events early.
+        self.events_end.set(None);
+    }
+
+    /// Adds the scheduled sources to `buffer`, which starts on the given sample, `offset`
+    /// samples into the render, ducking them by priority.
+    ///
+    /// Each priority level is rendered on its own, from the highest down, and ducked by the keys
+    /// of the levels above it. The input is ducked by the keys of every level.
+    #[inline]
+    #[nonblocking]
+    fn render_ducked(&mut self, buffer: &mut [Sample], offset: usize, start: SampleType) {
+        let mut keys = std::mem::take(&mut self.ducker.keys);
+        let mut level_buffer = std::mem::take(&mut self.ducker.level_buffer);
+        let keys_slice = &mut keys[..buffer.len()];
+        let level_samples = &mut level_buffer[..buffer.len()];
+
+        keys_slice.fill(false);
+        for level in (0..ducking::DUCKING_LEVELS).rev() {
+            let priority = Some(level as u8);
+            if !self
+                .sources
+                .iter()
+                .any(|source| source.ducking_priority() == priority)
+            {
+                continue;
+            }
+
+            level_samples.fill(0.0);
+            for source in self.sources.iter_mut() {
+                if source.ducking_priority() == priority {
+                    source.mix_into(level_samples);
+                }
+            }
+
+            self.ducker
+                .duck_level(level, offset, start, level_samples, keys_slice, buffer);
+
+            if !self.ducker.add_level_keys(level_samples, keys_slice) {
+                for source in self.sources.iter() {
+                    if source.ducking_priority() == priority {
+                        source.mark_sounding(start, keys_slice);
+                    }
+                }
+            }
+        }
+
+        for source in self.sources.iter_mut() {
+            if source.ducking_priority().is_none() {
+                source.mix_into(buffer);
+            }
+        }
+
+        self.ducker.duck_input(offset, start, keys_slice);
+
+        self.ducker.keys = keys;
+        self.ducker.level_buffer = level_buffer;
+    }
+
+    /// Renders the next `buffer.len()` samples of the scheduler into `buffer`, overwriting its
+    /// contents.
+    ///
+    /// This produces the same samples as calling [`Iterator::next`] once for every sample of the
+    /// buffer, but the scheduled sources are mixed a block at a time, with each playing event
+    /// added as a contiguous slice. Commands sent by handles are applied at the start of the
+    /// buffer.
+    ///
+    /// Like the iterator, the scheduler stops on the end set by its [`EndPolicy`]: the rest of
+    /// the buffer is filled with silence, and the scheduler stays on its end. See
+    /// [`Scheduler::has_ended`] to find out when to stop.
+    #[nonblocking]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn fill_buffer(&mut self, buffer: &mut [Sample]) {
+        self.process_commands();
+        self.rewind_block();
+
+        let mut played = 0;
+
+        // The ducking gains of the input are computed a render block at a time.
+        'blocks: for block in buffer.chunks_mut(RENDER_BLOCK_SIZE) {
+            self.render_sources(block, self.samples_counted);
+
+            for (position, sample) in block.iter_mut().enumerate() {
+                let input_sample = match self.next_input() {
+                    Some(input_sample) => input_sample,
+                    None => {
+                        // Move the sources back to the end, as if the rest was never rendered.
+                        self.ducker.rewind(position);
+                        self.move_sources(self.samples_counted);
+
+                        break 'blocks;
+                    }
+                };
+
+                let input_gain =
+                    self.input_gain_at(self.samples_counted) * self.ducker.input_gain(position);
+                self.samples_counted += 1;
+
+                let input_sample = input_sample.map(|sample| sample * input_gain);
+                *sample = input_sample.unwrap_or_default() + self.mix_children(*sample);
+                played += 1;
+            }
+        }
+
+        let (buffer, silence) = buffer.split_at_mut(played);
+        self.master.process_buffer(buffer);
+        silence.fill(0.0);
+
+        self.notify_sources();
+        self.update_playhead();
+        self.shared.playhead.set_state(if self.has_ended() {
+            PlaybackState::Ended
+        } else {
+            PlaybackState::Playing
+        });
+    }
+
+    /// Returns the next sample of the input, `Some(None)` if the input has ended but the
+    /// scheduler hasn't, or `None` if the scheduler has reached its end.
+    #[inline]
+    fn next_input(&mut self) -> Option<Option<Sample>> {
+        if self.has_ended() {
+            return None;
+        }
+
+        let input_sample = self.input.next();
+        if input_sample.is_none() {
+            self.input_end.get_or_insert(self.samples_counted);
+
+            if self.has_ended() {
+                return None;
+            }
+        }
+
+        Some(input_sample)
+    }
+
+    /// Silences the input and every source that isn't soloed while any source is soloed, from
+    /// the given sample.
+    #[inline]
+    fn apply_solos(&mut self, sample: SampleType) {
+        let soloing = self.sources.iter().any(SingleSourceScheduler::is_soloed)
+            || self.streams.iter().any(StreamingSourceScheduler::is_soloed);
+
+        for source in self.sources.iter_mut() {
+            let silenced = soloing && !source.is_soloed();
+            source.set_silenced_by_solo(silenced);
+        }
+        for stream in self.streams.iter_mut() {
+            let silenced = soloing && !stream.is_soloed();
+            stream.set_silenced_by_solo(silenced);
+        }
+
+        self.input_strip.set_silenced(soloing, sample);
+    }
+
+    /// Stops the events choked by sources that start an event on the given sample.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    fn apply_choke_groups(&mut self, sample: SampleType) {
+        for source in 0..self.sources.len() {
+            let Some(group) = self.sources[source].choke_group() else {
+                continue;
+            };
+
+            if self.sources[source].has_event_starting_at(sample) {
+                self.choke_group(group, source, sample);
+            }
+        }
+    }
+
+    /// Stops the events of every source in a choke group, other than `except`, that are playing
+    /// on the given sample.
+    #[inline]
+    fn choke_group(&mut self, group: u32, except: usize, sample: SampleType) {
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            if index != except && source.choke_group() == Some(group) {
+                source.choke(sample);
+            }
+        }
+    }
+
+    /// Retrieves a mutable reference to a `SingleSourceScheduler` by its ID.
+    ///
+    /// This allows you to schedule events for a specific source. Returns
+    /// [`SchedulerError::UnknownSource`] if there is no source with that identifier.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn get_scheduler(
+        &mut self,
+        source_idx: usize,
+    ) -> Result<&mut SingleSourceScheduler, SchedulerError> {
+        self.source_mut(source_idx)
+    }
+
+    /// Schedules a `PlaybackEvent` on the source identified by its `source_id`.
+    ///
+    /// Returns the identifier of the new event, which can be used to cancel it, or
+    /// [`SchedulerError::UnknownSource`] if there is no source with that identifier. See
+    /// [`SingleSourceScheduler::schedule_event`] and [`StreamingSourceScheduler::schedule_event`]
+    /// for the other errors.
+    #[inline]
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn schedule(&mut self, event: PlaybackEvent) -> Result<EventId, SchedulerError> {
+        match event.source_id {
+            SourceId::Buffered(source_id) => self.source_mut(source_id)?.schedule_event(event),
+            SourceId::Streamed(stream_id) => self.stream_mut(stream_id)?.schedule_event(event),
+        }
+    }
+
+    /// Schedules many `PlaybackEvent`s, each on the source identified by its `source_id`.
     ///
-    /// # Arguments
+    /// Like [`SingleSourceScheduler::schedule_events`], the events are inserted in bulk, so this
+    /// is the fastest way to load a large chart.
     ///
-    /// * `source`: The audio source to be scheduled.
-    /// * `sample_rate`: The sample rate of the output audio.
-    /// * `channels`: The number of channels in the output audio.
-    #[inline]
-    pub fn new(source: impl Source, sample_rate: u32, channels: u16) -> SingleSourceScheduler {
-        SingleSourceScheduler {
-            source: UniformSourceIterator::new(source, channels, sample_rate).collect(),
-            channels,
-            sample_rate,
-            playback_schedule: Vec::with_capacity(1000),
-            playback_position: (0, 0),
-            samples_counted: 0,
+    /// Either every event is scheduled, or none is: if any event refers to an unknown source or
+    /// can't be scheduled, the first error is returned and the schedule is left untouched.
+    /// Otherwise, returns the identifiers of the new events, in the same order.
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn schedule_many(
+        &mut self,
+        events: impl IntoIterator<Item = PlaybackEvent>,
+    ) -> Result<Vec<EventId>, SchedulerError> {
+        self.process_commands();
+        self.rewind_block();
+
+        let events: Vec<PlaybackEvent> = events.into_iter().collect();
+        for event in events.iter() {
+            match event.source_id {
+                SourceId::Buffered(source_id) => self
+                    .sources
+                    .get(source_id)
+                    .ok_or(SchedulerError::UnknownSource(source_id))?
+                    .check_event(event)?,
+                SourceId::Streamed(stream_id) => self
+                    .streams
+                    .get(stream_id)
+                    .ok_or(SchedulerError::UnknownSource(stream_id))?
+                    .check_event(event)?,
+            }
         }
+
+        let ids: Vec<EventId> = events
+            .iter()
+            .map(|event| match event.source_id {
+                SourceId::Buffered(source_id) => self.sources[source_id].new_event_id(),
+                SourceId::Streamed(stream_id) => self.streams[stream_id].new_event_id(),
+            })
+            .collect();
+
+        // Insert the events of each source in bulk, see `SingleSourceScheduler::schedule_events`.
+        let mut order: Vec<usize> = (0..events.len()).collect();
+        order.sort_by_key(|&index| events[index].source_id);
+
+        for batch in order.chunk_by(|&a, &b| events[a].source_id == events[b].source_id) {
+            match events[batch[0]].source_id {
+                SourceId::Buffered(source_id) => self.sources[source_id]
+                    .insert_events(batch.iter().map(|&index| (ids[index], events[index]))),
+                SourceId::Streamed(stream_id) => {
+                    for &index in batch {
+                        self.streams[stream_id].insert_event(ids[index], events[index]);
+                    }
+                }
+            }
+        }
+
+        Ok(ids)
     }
 
-    /// Schedules a `PlaybackEvent` for this source.
+    /// Schedules a `PlaybackEvent` on a beat of a `TempoMap`, for the source identified by its
+    /// `source_id`.
     ///
-    /// The event's timestamp is converted to a sample index and added to the playback schedule.
-    /// The schedule is then sorted to ensure correct playback order.
+    /// See [`SingleSourceScheduler::schedule_beat`]. Returns [`SchedulerError::UnknownSource`] if
+    /// there is no source with that identifier.
     #[inline]
-    pub fn schedule_event(&mut self, event: PlaybackEvent) {
-        self.playback_schedule
-            .push(event.timestamp * self.channels as SampleType);
-        self.playback_schedule.sort();
+    pub fn schedule_beat(
+        &mut self,
+        tempo_map: &TempoMap,
+        beat: f64,
+        event: PlaybackEvent,
+        repeat: Option<BeatRepeat>,
+    ) -> Result<EventId, SchedulerError> {
+        match event.source_id {
+            SourceId::Buffered(source_id) => self
+                .source_mut(source_id)?
+                .schedule_beat(tempo_map, beat, event, repeat),
+            SourceId::Streamed(stream_id) => self
+                .stream_mut(stream_id)?
+                .schedule_beat(tempo_map, beat, event, repeat),
+        }
     }
-}
 
-impl Iterator for SingleSourceScheduler {
-    type Item = Sample;
+    /// Returns the source with the given identifier, including sources added by a handle that
+    /// haven't been applied yet.
+    #[inline]
+    fn source_mut(
+        &mut self,
+        source_id: usize,
+    ) -> Result<&mut SingleSourceScheduler, SchedulerError> {
+        self.process_commands();
+        self.rewind_block();
+
+        self.sources
+            .get_mut(source_id)
+            .ok_or(SchedulerError::UnknownSource(source_id))
+    }
 
+    /// Converts a duration to a number of frames at the sample rate of this scheduler, rounding
+    /// to the nearest frame.
     #[inline]
-    #[nonblocking]
-    #[cfg_attr(feature = "profiler", instrument(name = "SingleSourceScheduler::next"))]
-    fn next(&mut self) -> Option<Sample> {
-        // Cache the sample index for this sample
-        let s = self.samples_counted;
+    pub fn duration_to_frames(&self, duration: Duration) -> SampleType {
+        time::duration_to_frames(duration, self.sample_rate())
+    }
 
-        // Set the sample index for the next sample
-        self.samples_counted += 1;
+    /// Converts a number of frames at the sample rate of this scheduler to a duration.
+    #[inline]
+    pub fn frames_to_duration(&self, frames: SampleType) -> Duration {
+        time::frames_to_duration(frames, self.sample_rate())
+    }
 
-        // Update the playback position
-        if !self.playback_schedule.is_empty() {
-            let source_size: SampleType = self.source.len() as SampleType - 1;
-            let schedule_size: usize = self.playback_schedule.len() - 1;
+    /// Converts a number of interleaved samples, as produced by this scheduler, to a number of
+    /// frames.
+    #[inline]
+    pub fn samples_to_frames(&self, samples: SampleType) -> SampleType {
+        samples / self.channels() as SampleType
+    }
 
-            while self.playback_position.0 < schedule_size
-                && (self.playback_schedule[self.playback_position.0] + source_size) < s
-            {
-                self.playback_position.0 += 1
-            }
+    /// Creates an event that plays a source after the given duration.
+    ///
+    /// See [`PlaybackEvent::at_duration`].
+    #[inline]
+    pub fn event_at_duration(
+        &self,
+        source_id: impl Into<SourceId>,
+        duration: Duration,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_duration(source_id, duration, self.sample_rate())
+    }
 
-            while self.playback_position.1 <= schedule_size
-                && self.playback_schedule[self.playback_position.1] <= s
-            {
-                self.playback_position.1 += 1
-            }
-        }
+    /// Creates an event that plays a source after the given number of seconds.
+    ///
+    /// See [`PlaybackEvent::at_seconds`].
+    #[inline]
+    pub fn event_at_seconds(&self, source_id: impl Into<SourceId>, seconds: f64) -> PlaybackEvent {
+        PlaybackEvent::at_seconds(source_id, seconds, self.sample_rate())
+    }
 
-        simd::retrieve_and_mix_samples(
-            &self.source,
-            &self.playback_schedule,
-            self.playback_position,
-            s,
-        )
+    /// Creates an event that plays a source on the given beat of a constant tempo.
+    ///
+    /// See [`PlaybackEvent::at_beat`].
+    #[inline]
+    pub fn event_at_beat(
+        &self,
+        source_id: impl Into<SourceId>,
+        bpm: f64,
+        beat: f64,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_beat(source_id, bpm, beat, self.sample_rate())
     }
 
+    /// Retrieves a mutable reference to a `StreamingSourceScheduler` by its ID.
+    ///
+    /// This allows you to schedule events for a specific streaming source. Returns
+    /// [`SchedulerError::UnknownSource`] if there is no streaming source with that identifier.
     #[inline]
-    #[cfg_attr(
-        feature = "profiler",
-        instrument(name = "SingleSourceScheduler::size_hint")
-    )]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let last_element: usize = self.playback_schedule[self.playback_schedule.len() - 1]
-            .try_into()
-            .unwrap_or(usize::MAX);
-        let lower_bound = last_element + self.source.len();
+    #[cfg_attr(feature = "profiler", instrument)]
+    pub fn get_streaming_scheduler(
+        &mut self,
+        stream_idx: usize,
+    ) -> Result<&mut StreamingSourceScheduler, SchedulerError> {
+        self.stream_mut(stream_idx)
+    }
 
-        (lower_bound, None)
+    /// Returns the streaming source with the given identifier.
+    #[inline]
+    fn stream_mut(
+        &mut self,
+        stream_id: usize,
+    ) -> Result<&mut StreamingSourceScheduler, SchedulerError> {
+        // A solo must be applied to the sources from the next sample played.
+        self.rewind_block();
+
+        self.streams
+            .get_mut(stream_id)
+            .ok_or(SchedulerError::UnknownSource(stream_id))
     }
-}
 
-impl Source for SingleSourceScheduler {
+    /// Returns the oldest report of a late event that hasn't been read yet, from any source.
+    ///
+    /// See [`SingleSourceScheduler::pop_late_event`].
     #[inline]
-    fn current_span_len(&self) -> Option<usize> {
-        None
+    pub fn pop_late_event(&self) -> Option<LateEvent> {
+        self.shared.late_events.pop()
     }
 
+    /// Returns the oldest notification of an event starting or stopping that hasn't been read
+    /// yet, from any source.
+    ///
+    /// See [`SingleSourceScheduler::pop_event_notification`] and
+    /// [`StreamingSourceScheduler::pop_event_notification`].
     #[inline]
-    fn channels(&self) -> u16 {
-        self.channels
+    pub fn pop_event_notification(&self) -> Option<EventNotification> {
+        self.shared.notifications.pop()
     }
 
+    /// Sets the last stage the output of the scheduler passes through, which can keep it from
+    /// clipping.
+    ///
+    /// See [`MasterStage`]. The stage starts over every time it is set, and every time the
+    /// scheduler seeks.
     #[inline]
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    pub fn set_master_stage(&mut self, stage: MasterStage) {
+        self.master.set_stage(stage);
     }
 
+    /// Returns the last stage the output of the scheduler passes through.
     #[inline]
-    fn total_duration(&self) -> Option<Duration> {
-        None
+    pub fn master_stage(&self) -> MasterStage {
+        self.master.stage()
     }
 
+    /// Sets the volume of the input, as a linear gain. Negative volumes are treated as silence.
+    ///
+    /// The change is ramped over [`GAIN_RAMP_DURATION`], so it doesn't click. The volume of each
+    /// scheduled source is set on the source, see [`SingleSourceScheduler::set_volume`].
     #[inline]
-    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
-        let samples_secs = pos.as_secs() * self.sample_rate() as SampleType;
+    pub fn set_input_volume(&mut self, volume: f32) {
+        self.input_strip.set_volume(volume, self.samples_counted);
+    }
 
-        let nanos_per_sample = 1_000_000_000 / self.sample_rate();
-        let samples_nanos = pos.subsec_nanos() / nanos_per_sample;
+    /// Returns the volume of the input.
+    #[inline]
+    pub fn input_volume(&self) -> f32 {
+        self.input_strip.volume()
+    }
 
-        self.samples_counted = (samples_secs + samples_nanos as SampleType) * self.channels as SampleType;
+    /// Returns the automation lane of the gain of the input.
+    #[inline]
+    pub fn input_gain_automation(&self) -> &AutomationLane {
+        &self.input_automation
+    }
 
-        Ok(())
+    /// Returns the automation lane of the gain of the input, so its points can be changed.
+    ///
+    /// The lane is a linear gain, applied on top of the volume of the input, on the same frames
+    /// as the scheduled events. The gain of each scheduled source is automated on the source, see
+    /// [`SingleSourceScheduler::gain_automation_mut`].
+    #[inline]
+    pub fn input_gain_automation_mut(&mut self) -> &mut AutomationLane {
+        &mut self.input_automation
     }
-}
 
-/// A source that schedules playback of other sources at precise timestamps.
-///
-/// The `Scheduler` takes an input source and allows you to schedule additional sources
-/// to be played at specific sample timestamps. It mixes the output of the scheduled sources
-/// with the input source.
-///
-/// # Type Parameters
-///
-/// * `I`: The type of the input audio source, which will play behind other scheduled sources.
-///
-/// # Example
-///
-/// ```no_run
-/// use std::fs::File;
-///
-/// use rodio::{Decoder, Source, OutputStreamBuilder};
-/// use rodio_scheduler::{Scheduler, PlaybackEvent};
-///
-/// # fn main() {
-///    // Get an output stream handle to the default physical sound device.
-///    let stream = OutputStreamBuilder::open_default_stream().unwrap();
-///
-///    // Load a sound from a file.
-///    let metronome = File::open("assets/metronome.wav").unwrap();
-///    let metronome_decoder_source = Decoder::new(metronome).unwrap();
-///
-///    // Create a scheduler.
-///    let mut scheduler = Scheduler::new(metronome_decoder_source, 48000, 2);
-///
-///    // Load a sound to be scheduled.
-///    let note_hit = File::open("assets/note_hit.wav").unwrap();
-///    let note_hit_decoder_source = Decoder::new(note_hit).unwrap();
-///
-///    // Add the sound to the scheduler, with a list of playback events to schedule.
-///    let note_hit_id = scheduler.add_source(note_hit_decoder_source);
-///
-///    // Schedule the sound to be played at a specific timestamp.
-///    let event = PlaybackEvent {
-///        source_id: note_hit_id,
-///        timestamp: scheduler.sample_rate() as u64 * 2, // 2 seconds in
-///        repeat: None,
-///    };
-///    scheduler.get_scheduler(note_hit_id).unwrap().schedule_event(event);
-///
-///    // Load another sound to be scheduled.
-///    let sine_clip = rodio::source::SineWave::new(440.0).take_duration(std::time::Duration::from_millis(500));
-///
-///    // Schedule the new sound.
-///    let sine_clip_id = scheduler.add_source(sine_clip);
-///    let event = PlaybackEvent {
-///        source_id: sine_clip_id,
-///        timestamp: scheduler.sample_rate() as u64 * 4, // 4 seconds in
-///        repeat: None,
-///    };
-///    scheduler.get_scheduler(sine_clip_id).unwrap().schedule_event(event);
-///
-///    // Play the scheduled sounds.
-///    let _ = stream.mixer().add(scheduler);
-///
-///    // The sound plays in a separate audio thread,
-///    // so we need to keep the main thread alive while it's playing.
-///    std::thread::sleep(std::time::Duration::from_secs(5));
-/// # }
-/// ```
-pub struct Scheduler<I>
-where
-    I: Source,
-{
-    /// The main input source that the scheduled sources will be mixed with.
-    input: UniformSourceIterator<I>,
-    /// A vector of `SingleSourceScheduler`s, each managing a single scheduled source.
-    sources: Vec<SingleSourceScheduler>,
-}
+    /// Returns the gain of the input on the given sample, from its volume and its automation.
+    #[inline]
+    fn input_gain_at(&self, sample: SampleType) -> f32 {
+        let frame = sample / self.channels() as SampleType;
+        let automation = self.input_automation.value_at(frame).unwrap_or(1.0);
 
-impl<I> Scheduler<I>
-where
-    I: Source,
-{
-    /// Creates a new `Scheduler`.
-    ///
-    /// # Arguments
+        self.input_strip.gain_at(sample) * automation
+    }
+
+    /// Enables ducking with the given settings, or disables it with `None`.
     ///
-    /// * `input`: The main audio source.
-    /// * `sample_rate`: The sample rate of the output audio.
-    /// * `channels`: The number of channels in the output audio.
+    /// While ducking is enabled, every source with a ducking priority turns the input down while
+    /// it is sounding, along with the sources that have a lower priority. See [`Ducking`] and
+    /// [`SingleSourceScheduler::set_ducking_priority`]. Ducking is disabled by default.
     #[inline]
-    pub fn new(input: I, sample_rate: u32, channels: u16) -> Scheduler<I> {
-        Scheduler {
-            input: UniformSourceIterator::new(input, channels, sample_rate),
-            sources: Vec::new(),
-        }
+    pub fn set_ducking(&mut self, ducking: Option<Ducking>) {
+        self.rewind_block();
+        self.ducker.set_settings(ducking);
     }
 
-    /// Creates a new `Scheduler` with a specified capacity for scheduled sources.
+    /// Returns the ducking settings of the scheduler, or `None` if ducking is disabled.
+    #[inline]
+    pub fn ducking(&self) -> Option<Ducking> {
+        self.ducker.settings()
+    }
+
+    /// Returns how much the master stage is turning the output down, in decibels.
     ///
-    /// # Arguments
+    /// This is `0.0` when the output is left untouched, and grows as peaks are clipped or
+    /// limited. See [`SchedulerHandle::gain_reduction`] to read it while the scheduler is
+    /// playing.
+    #[inline]
+    pub fn gain_reduction(&self) -> f32 {
+        self.shared.gain_reduction()
+    }
+
+    /// Sets when the scheduler stops producing samples.
     ///
-    /// * `input`: The main audio source.
-    /// * `sample_rate`: The sample rate of the output audio.
-    /// * `channels`: The number of channels in the output audio.
-    /// * `capacity`: The initial capacity for the number of scheduled sources.
+    /// See [`EndPolicy`]. [`Iterator::size_hint`] and [`Source::total_duration`] follow the
+    /// policy as well.
     #[inline]
-    pub fn with_capacity(
-        input: I,
-        sample_rate: u32,
-        channels: u16,
-        capacity: usize,
-    ) -> Scheduler<I> {
-        Scheduler {
-            input: UniformSourceIterator::new(input, channels, sample_rate),
-            sources: Vec::with_capacity(capacity),
+    pub fn set_end_policy(&mut self, policy: EndPolicy) {
+        self.end_policy = policy;
+    }
+
+    /// Returns when the scheduler stops producing samples.
+    #[inline]
+    pub fn end_policy(&self) -> EndPolicy {
+        self.end_policy
+    }
+
+    /// Returns `true` if the scheduler has reached the end set by its [`EndPolicy`], in which
+    /// case its iterator returns `None`.
+    #[inline]
+    pub fn has_ended(&self) -> bool {
+        match self.end_policy {
+            EndPolicy::Never => false,
+            EndPolicy::WithInput => self.input_end.is_some(),
+            EndPolicy::AfterLastEvent => {
+                let channels = self.channels() as SampleType;
+
+                // Only end on a frame boundary, so no channel is left without its sample.
+                self.input_end.is_some()
+                    && self.samples_counted.is_multiple_of(channels)
+                    && self
+                        .scheduled_end()
+                        .is_some_and(|end| self.samples_counted >= end)
+            }
         }
     }
 
-    /// Adds a new source to the scheduler.
+    /// Returns the sample on which the input ends, or `None` if it is unknown.
     ///
-    /// Returns a `usize` identifier for the new source, which can be used to schedule playback events.
+    /// The input is assumed to end on its total duration until it actually ends.
     #[inline]
-    #[cfg_attr(feature = "profiler", instrument)]
-    pub fn add_source(&mut self, source: impl Source) -> usize {
-        let source_scheduler: SingleSourceScheduler =
-            SingleSourceScheduler::new(source, self.sample_rate(), self.channels());
+    fn input_end(&self) -> Option<SampleType> {
+        let channels = self.channels() as SampleType;
 
-        self.sources.push(source_scheduler);
+        self.input_end.or_else(|| {
+            let duration = self.input.total_duration()?;
 
-        self.sources.len() - 1
+            Some(self.duration_to_frames(duration).saturating_mul(channels))
+        })
     }
 
-    /// Retrieves a mutable reference to a `SingleSourceScheduler` by its ID.
+    /// Returns the sample on which the input and every scheduled event have finished playing,
+    /// including the delay of the master stage, or `None` if it is unknown because the input
+    /// has no known duration, or a streaming source is playing or has events left.
+    #[inline]
+    fn scheduled_end(&self) -> Option<SampleType> {
+        if !self.streams.iter().all(StreamingSourceScheduler::is_idle) {
+            return None;
+        }
+
+        let channels = self.channels() as SampleType;
+        let input_end = self.input_end()?;
+        let events_end = self.events_end();
+        let latency = self.master.latency().saturating_mul(channels);
+
+        Some(input_end.max(events_end).saturating_add(latency))
+    }
+
+    /// Returns the sample on which the last event of the scheduled sources stops playing, or `0`
+    /// if none is scheduled.
+    #[inline]
+    fn events_end(&self) -> SampleType {
+        if let Some(end) = self.events_end.get() {
+            return end;
+        }
+
+        let end = self
+            .sources
+            .iter()
+            .map(SingleSourceScheduler::schedule_end)
+            .max()
+            .unwrap_or(0);
+        self.events_end.set(Some(end));
+
+        end
+    }
+
+    /// Seeks the scheduler and every scheduled source to a position.
     ///
-    /// This allows you to schedule events for a specific source.
+    /// This is the same as [`Source::try_seek`], with the error converted to a
+    /// [`SchedulerError`]: [`SchedulerError::UnsupportedSeek`] if the input source can't seek, or
+    /// [`SchedulerError::Seek`] if it failed to. The scheduled sources are left untouched on
+    /// failure.
     #[inline]
-    #[cfg_attr(feature = "profiler", instrument)]
-    pub fn get_scheduler(&mut self, source_idx: usize) -> Option<&mut SingleSourceScheduler> {
-        self.sources.get_mut(source_idx)
+    pub fn seek(&mut self, pos: Duration) -> Result<(), SchedulerError> {
+        Ok(self.try_seek(pos)?)
     }
 }
 
@@ -401,21 +3290,62 @@ where
     #[nonblocking]
     #[cfg_attr(feature = "profiler", instrument(name = "Scheduler::next"))]
     fn next(&mut self) -> Option<Sample> {
-        let input_sample = self.input.next();
+        self.process_commands();
 
-        // NOTE: This could be mixed using the simd::mix_samples method, but it would require us to
-        // get the sample data out of the iterator and into a contiguous slice, and using SIMD operations
-        // would only start being more efficient for applications with > 4 simultaneous schedulers.
-        Some(self
-            .sources
-            .iter_mut()
-            .filter_map(|source| source.next())
-            .fold(input_sample.unwrap_or_default(), |accumulator, sample| accumulator + sample))
+        let Some(input_sample) = self.next_input() else {
+            self.notify_sources();
+            self.shared.playhead.set_state(PlaybackState::Ended);
+
+            return None;
+        };
+
+        // Render the scheduled sources a block at a time, see `Scheduler::fill_buffer`.
+        if self.block_position == self.block_len {
+            self.notify_sources();
+
+            let mut block = std::mem::take(&mut self.block);
+            self.render_sources(&mut block, self.samples_counted);
+
+            self.block = block;
+            self.block_position = 0;
+            self.block_len = self.block.len();
+        }
+
+        let scheduled_sample = self.block[self.block_position];
+        let ducking_gain = self.ducker.input_gain(self.block_position);
+        self.block_position += 1;
+
+        let input_gain = self.input_gain_at(self.samples_counted) * ducking_gain;
+        self.samples_counted += 1;
+
+        let input_sample = input_sample.map(|sample| sample * input_gain);
+
+        let sample = input_sample.unwrap_or_default() + self.mix_children(scheduled_sample);
+        let sample = self.master.process(sample);
+
+        self.update_playhead();
+        self.shared.playhead.set_state(PlaybackState::Playing);
+
+        Some(sample)
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.input.size_hint()
+        let end = match self.end_policy {
+            EndPolicy::Never => None,
+            EndPolicy::WithInput => self.input_end(),
+            EndPolicy::AfterLastEvent => self.scheduled_end(),
+        };
+
+        match end {
+            Some(end) => {
+                let remaining = end.saturating_sub(self.samples_counted);
+                let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+
+                (remaining, Some(remaining))
+            }
+            None => (self.input.size_hint().0, None),
+        }
     }
 }
 
@@ -440,11 +3370,49 @@ where
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        self.input.total_duration()
+        match self.end_policy {
+            EndPolicy::Never => None,
+            EndPolicy::WithInput => self.input.total_duration(),
+            EndPolicy::AfterLastEvent => {
+                let input = self.input.total_duration()?;
+                let end = self.frames_to_duration(self.samples_to_frames(self.scheduled_end()?));
+
+                Some(input.max(end))
+            }
+        }
     }
 
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
-        self.input.try_seek(pos)
+        // Seek the input first, so the scheduled sources are left untouched if it fails.
+        self.input.try_seek(pos)?;
+        self.input_end = None;
+
+        // Sources added by a handle must be seeked too.
+        self.process_commands();
+        self.notify_sources();
+
+        // Keep the current channel, so the next sample is still for the channel the output expects.
+        let channels = self.channels() as SampleType;
+        let channel = self.samples_counted % channels;
+        let frame = time::duration_to_frames(pos, self.sample_rate());
+
+        self.samples_counted = frame.saturating_mul(channels).saturating_add(channel);
+
+        self.block_position = 0;
+        self.block_len = 0;
+        self.move_sources(self.samples_counted);
+
+        // The lookahead of the limiter holds samples from before the seek.
+        self.master.reset();
+        self.ducker.reset();
+
+        for stream in self.streams.iter_mut() {
+            stream.set_position(self.samples_counted);
+        }
+
+        self.update_playhead();
+
+        Ok(())
     }
 }
@@ -0,0 +1,366 @@
+//! This module provides the master stage of a `Scheduler`, which keeps its output from clipping.
+//!
+//! Every sample produced by a `Scheduler` goes through its [`MasterStage`] last, after the input
+//! and every scheduled source have been mixed together. By default the stage is
+//! [`MasterStage::Off`], so dense passages can exceed the `[-1.0, 1.0]` range of the output
+//! device and clip harshly. The other stages either shape the peaks, or turn the whole mix down
+//! just enough to keep them under a ceiling.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use rodio::Sample;
+
+use crate::time;
+
+/// The longest lookahead of a [`MasterStage::Limiter`]. Longer lookaheads are shortened to it.
+///
+/// The buffers of the limiter are allocated for this lookahead up front, so the stage can be
+/// changed on the audio thread without allocating.
+pub const MAX_LIMITER_LOOKAHEAD: Duration = Duration::from_millis(50);
+
+/// The curve used by [`MasterStage::SoftClip`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SoftClipCurve {
+    /// A hyperbolic tangent, which starts bending quiet samples slightly, and approaches the
+    /// ceiling without ever reaching it.
+    #[default]
+    Tanh,
+
+    /// A cubic polynomial, which leaves quiet samples closer to their original level and
+    /// reaches the ceiling at the ceiling, clipping anything louder.
+    Cubic,
+}
+
+/// The last stage a `Scheduler` passes its output through.
+///
+/// Every `ceiling` is a linear amplitude, so `1.0` is full scale and `0.5` leaves about 6 dB of
+/// headroom. See [`decibels_to_gain`] to set it in decibels. Ceilings that are not positive are
+/// replaced with `1.0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MasterStage {
+    /// The output is left untouched.
+    #[default]
+    Off,
+
+    /// Samples louder than the ceiling are clamped to it.
+    ///
+    /// This is the cheapest stage, but clamped peaks sound harsh.
+    HardClip {
+        /// The highest amplitude of the output.
+        ceiling: f32,
+    },
+
+    /// Samples are bent towards the ceiling by a smooth curve, so peaks are rounded off instead
+    /// of clamped.
+    SoftClip {
+        /// The shape of the curve.
+        curve: SoftClipCurve,
+        /// The highest amplitude of the output.
+        ceiling: f32,
+    },
+
+    /// The whole mix is turned down just enough to keep it under the ceiling, and brought back
+    /// up afterwards.
+    ///
+    /// The limiter sees every peak `lookahead` before it is played, so it can ramp the gain
+    /// down smoothly before it, without ever letting a sample exceed the ceiling. This delays the
+    /// output by the lookahead.
+    Limiter {
+        /// The highest amplitude of the output.
+        ceiling: f32,
+        /// How far ahead the limiter looks for peaks, up to [`MAX_LIMITER_LOOKAHEAD`]. The gain
+        /// ramps down over this time.
+        lookahead: Duration,
+        /// How long the gain takes to recover about two thirds of the way after a peak.
+        release: Duration,
+    },
+}
+
+/// Converts a level in decibels to a linear gain, so `-6.0` is about `0.5`.
+#[inline]
+pub fn decibels_to_gain(decibels: f32) -> f32 {
+    10.0_f32.powf(decibels / 20.0)
+}
+
+/// Converts a linear gain to a level in decibels, so `0.5` is about `-6.0`.
+#[inline]
+pub fn gain_to_decibels(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
+
+/// The running state of a `MasterStage`.
+///
+/// Every buffer is allocated for the longest lookahead when the bus is created, so changing the
+/// stage never allocates.
+pub(crate) struct MasterBus {
+    stage: MasterStage,
+    sample_rate: u32,
+    channels: usize,
+    ceiling: f32,
+    /// The channel of the next sample.
+    channel: usize,
+    /// The highest amplitude of the frame being received, before and after the stage.
+    input_peak: f32,
+    output_peak: f32,
+    /// The gain of the last frame, shared with every handle for metering.
+    meter: Arc<AtomicU32>,
+
+    /// The lookahead of the limiter, in frames.
+    lookahead: usize,
+    release_coefficient: f32,
+    /// The gain applied to the frame being played.
+    gain: f32,
+    /// The input of the limiter, played `lookahead` frames later.
+    delay: Box<[Sample]>,
+    delay_position: usize,
+    /// The frames received, used to expire the holds.
+    frames: u64,
+    /// A ring of increasing target gains with the frame they were computed for, so the first one
+    /// is the lowest gain needed by the frames in the lookahead.
+    holds: Box<[(f32, u64)]>,
+    holds_start: usize,
+    holds_len: usize,
+    /// The last `lookahead` held gains, averaged to smooth the gain ramps.
+    smoothing: Box<[f32]>,
+    smoothing_position: usize,
+    smoothing_sum: f64,
+}
+
+impl MasterBus {
+    pub(crate) fn new(sample_rate: u32, channels: u16, meter: Arc<AtomicU32>) -> MasterBus {
+        let max_lookahead = time::duration_to_frames(MAX_LIMITER_LOOKAHEAD, sample_rate).max(1);
+        let max_lookahead = usize::try_from(max_lookahead).unwrap_or(usize::MAX);
+        let channels = channels.max(1) as usize;
+
+        let mut bus = MasterBus {
+            stage: MasterStage::Off,
+            sample_rate,
+            channels,
+            ceiling: 1.0,
+            channel: 0,
+            input_peak: 0.0,
+            output_peak: 0.0,
+            meter,
+            lookahead: 1,
+            release_coefficient: 1.0,
+            gain: 1.0,
+            delay: vec![0.0; max_lookahead * channels].into_boxed_slice(),
+            delay_position: 0,
+            frames: 0,
+            holds: vec![(1.0, 0); max_lookahead].into_boxed_slice(),
+            holds_start: 0,
+            holds_len: 0,
+            smoothing: vec![1.0; max_lookahead].into_boxed_slice(),
+            smoothing_position: 0,
+            smoothing_sum: 0.0,
+        };
+        bus.reset();
+
+        bus
+    }
+
+    /// Returns the current stage.
+    #[inline]
+    pub(crate) fn stage(&self) -> MasterStage {
+        self.stage
+    }
+
+    /// Returns how many frames the stage delays the output by.
+    #[inline]
+    pub(crate) fn latency(&self) -> u64 {
+        match self.stage {
+            MasterStage::Limiter { .. } => self.lookahead as u64,
+            _ => 0,
+        }
+    }
+
+    /// Changes the stage, and resets its state.
+    #[inline]
+    pub(crate) fn set_stage(&mut self, stage: MasterStage) {
+        let sanitize = |ceiling: f32| if ceiling > 0.0 { ceiling } else { 1.0 };
+
+        self.stage = stage;
+        self.ceiling = match stage {
+            MasterStage::Off => 1.0,
+            MasterStage::HardClip { ceiling }
+            | MasterStage::SoftClip { ceiling, .. }
+            | MasterStage::Limiter { ceiling, .. } => sanitize(ceiling),
+        };
+
+        if let MasterStage::Limiter {
+            lookahead, release, ..
+        } = stage
+        {
+            let lookahead = time::duration_to_frames(lookahead, self.sample_rate);
+            let lookahead = usize::try_from(lookahead).unwrap_or(usize::MAX);
+            self.lookahead = lookahead.clamp(1, self.holds.len());
+
+            let release = release.as_secs_f32() * self.sample_rate as f32;
+            self.release_coefficient = if release > 0.0 {
+                1.0 - (-1.0 / release).exp()
+            } else {
+                1.0
+            };
+        }
+
+        self.reset();
+    }
+
+    /// Forgets every sample received, such as after a seek. The channel of the next sample is
+    /// kept.
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        self.input_peak = 0.0;
+        self.output_peak = 0.0;
+        self.gain = 1.0;
+        self.meter.store(1.0_f32.to_bits(), Ordering::Relaxed);
+
+        self.delay.fill(0.0);
+        self.delay_position = 0;
+        self.frames = 0;
+        self.holds_start = 0;
+        self.holds_len = 0;
+        self.smoothing.fill(1.0);
+        self.smoothing_position = 0;
+        self.smoothing_sum = self.lookahead as f64;
+    }
+
+    /// Passes every sample of a buffer through the stage.
+    #[inline]
+    pub(crate) fn process_buffer(&mut self, buffer: &mut [Sample]) {
+        if self.stage == MasterStage::Off {
+            self.channel = (self.channel + buffer.len()) % self.channels;
+
+            return;
+        }
+
+        for sample in buffer.iter_mut() {
+            *sample = self.process(*sample);
+        }
+    }
+
+    /// Passes the next sample through the stage.
+    #[inline]
+    pub(crate) fn process(&mut self, sample: Sample) -> Sample {
+        let output = match self.stage {
+            MasterStage::Off => sample,
+            MasterStage::HardClip { .. } => sample.clamp(-self.ceiling, self.ceiling),
+            MasterStage::SoftClip { curve, .. } => self.soft_clip(curve, sample),
+            MasterStage::Limiter { .. } => self.limit(sample),
+        };
+
+        self.input_peak = self.input_peak.max(sample.abs());
+        self.output_peak = self.output_peak.max(output.abs());
+
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.end_frame();
+        }
+
+        output
+    }
+
+    /// Bends a sample towards the ceiling.
+    #[inline]
+    fn soft_clip(&self, curve: SoftClipCurve, sample: Sample) -> Sample {
+        let x = sample / self.ceiling;
+
+        let y = match curve {
+            SoftClipCurve::Tanh => x.tanh(),
+            SoftClipCurve::Cubic => {
+                let x = x.clamp(-1.0, 1.0);
+
+                1.5 * x - 0.5 * x * x * x
+            }
+        };
+
+        y * self.ceiling
+    }
+
+    /// Stores a sample in the lookahead, and returns the delayed sample with the limiter gain.
+    #[inline]
+    fn limit(&mut self, sample: Sample) -> Sample {
+        let delayed = std::mem::replace(&mut self.delay[self.delay_position], sample);
+
+        self.delay_position += 1;
+        if self.delay_position == self.lookahead * self.channels {
+            self.delay_position = 0;
+        }
+
+        // The gain never lets a peak through, but rounding could still nudge it over.
+        (delayed * self.gain).clamp(-self.ceiling, self.ceiling)
+    }
+
+    /// Updates the gain and the meter once every channel of a frame has been received.
+    #[inline]
+    fn end_frame(&mut self) {
+        let gain = match self.stage {
+            MasterStage::Limiter { .. } => {
+                self.update_limiter_gain();
+
+                self.gain
+            }
+            _ if self.input_peak > self.output_peak => self.output_peak / self.input_peak,
+            _ => 1.0,
+        };
+
+        self.meter.store(gain.to_bits(), Ordering::Relaxed);
+        self.input_peak = 0.0;
+        self.output_peak = 0.0;
+    }
+
+    /// Computes the gain of the next frame to be played, from the peak of the frame received.
+    ///
+    /// The lowest gain needed in the lookahead is held, and averaged over the lookahead, so the
+    /// gain reaches the target of every frame by the time it is played.
+    #[inline]
+    fn update_limiter_gain(&mut self) {
+        let target = if self.input_peak > self.ceiling {
+            self.ceiling / self.input_peak
+        } else {
+            1.0
+        };
+
+        let frame = self.frames;
+        self.frames += 1;
+
+        // Drop the holds that left the lookahead, and keep the rest increasing.
+        let capacity = self.holds.len();
+        while self.holds_len > 0
+            && self.holds[self.holds_start].1 + (self.lookahead as u64) <= frame
+        {
+            self.holds_start = (self.holds_start + 1) % capacity;
+            self.holds_len -= 1;
+        }
+
+        while self.holds_len > 0 {
+            let last = (self.holds_start + self.holds_len - 1) % capacity;
+            if self.holds[last].0 < target {
+                break;
+            }
+
+            self.holds_len -= 1;
+        }
+        self.holds[(self.holds_start + self.holds_len) % capacity] = (target, frame);
+        self.holds_len += 1;
+
+        let held = self.holds[self.holds_start].0;
+        let oldest = std::mem::replace(&mut self.smoothing[self.smoothing_position], held);
+        self.smoothing_sum += held as f64 - oldest as f64;
+
+        self.smoothing_position += 1;
+        if self.smoothing_position == self.lookahead {
+            self.smoothing_position = 0;
+        }
+
+        let smoothed = ((self.smoothing_sum / self.lookahead as f64) as f32).min(1.0);
+        self.gain = if smoothed < self.gain {
+            smoothed
+        } else {
+            self.gain + (smoothed - self.gain) * self.release_coefficient
+        };
+    }
+}
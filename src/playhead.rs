@@ -0,0 +1,147 @@
+//! This module provides a handle to read the playback position of a `Scheduler` from any thread.
+//!
+//! The scheduler publishes its position with atomic stores after every sample it plays, so
+//! visuals can follow the audio without locking the audio thread. A [`PlayheadHandle`] is
+//! created with [`Scheduler::playhead`](crate::Scheduler::playhead) or
+//! [`SchedulerHandle::playhead`](crate::SchedulerHandle::playhead).
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::{SampleType, time};
+
+/// Whether a `Scheduler` is playing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PlaybackState {
+    /// The scheduler hasn't played any sample yet.
+    #[default]
+    Idle,
+
+    /// The scheduler is playing.
+    Playing,
+
+    /// The scheduler has reached the end set by its [`EndPolicy`](crate::EndPolicy). It plays
+    /// again if it is seeked back, or if another event is scheduled.
+    Ended,
+}
+
+impl PlaybackState {
+    #[inline]
+    fn from_u8(state: u8) -> PlaybackState {
+        match state {
+            1 => PlaybackState::Playing,
+            2 => PlaybackState::Ended,
+            _ => PlaybackState::Idle,
+        }
+    }
+}
+
+/// The position and state of a `Scheduler`, written by the audio thread.
+pub(crate) struct Playhead {
+    /// The number of samples played, interleaved.
+    position: AtomicU64,
+    state: AtomicU8,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl Playhead {
+    pub(crate) fn new(sample_rate: u32, channels: u16) -> Playhead {
+        Playhead {
+            position: AtomicU64::new(0),
+            state: AtomicU8::new(PlaybackState::Idle as u8),
+            sample_rate,
+            channels: channels.max(1),
+        }
+    }
+
+    /// Publishes the position of the scheduler, in samples.
+    #[inline]
+    pub(crate) fn set_position(&self, position: SampleType) {
+        self.position.store(position, Ordering::Relaxed);
+    }
+
+    /// Publishes the state of the scheduler.
+    #[inline]
+    pub(crate) fn set_state(&self, state: PlaybackState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+/// A cloneable handle used to read the playback position of a `Scheduler` from any thread.
+///
+/// The position is on the timeline of the schedule: the delay of the master stage is taken out,
+/// so an event scheduled on a frame is heard when the playhead reaches that frame. It is updated
+/// after every sample played by [`Iterator::next`], after every buffer filled by
+/// [`Scheduler::fill_buffer`](crate::Scheduler::fill_buffer), and on every seek.
+///
+/// # Example
+///
+/// ```no_run
+/// use rodio::OutputStreamBuilder;
+/// use rodio_scheduler::Scheduler;
+///
+/// # fn main() {
+///    let stream = OutputStreamBuilder::open_default_stream().unwrap();
+///
+///    let background = rodio::source::SineWave::new(440.0);
+///    let scheduler = Scheduler::new(background, 48000, 2);
+///
+///    // Create a playhead before moving the scheduler into the audio thread.
+///    let playhead = scheduler.playhead();
+///    stream.mixer().add(scheduler);
+///
+///    // Draw a note that is hit on frame 96000, at 1000 pixels per second.
+///    let seconds_left = (96000.0 - playhead.frame() as f64) / playhead.sample_rate() as f64;
+///    let distance = seconds_left * 1000.0;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct PlayheadHandle {
+    playhead: Arc<Playhead>,
+}
+
+impl PlayheadHandle {
+    pub(crate) fn new(playhead: Arc<Playhead>) -> PlayheadHandle {
+        PlayheadHandle { playhead }
+    }
+
+    /// Returns the number of samples the scheduler has played, interleaved.
+    #[inline]
+    pub fn position(&self) -> SampleType {
+        self.playhead.position.load(Ordering::Relaxed)
+    }
+
+    /// Returns the frame the scheduler has reached, which is the frame of the next sample it
+    /// plays.
+    #[inline]
+    pub fn frame(&self) -> SampleType {
+        self.position() / self.playhead.channels as SampleType
+    }
+
+    /// Returns the position of the scheduler as a duration.
+    #[inline]
+    pub fn time(&self) -> Duration {
+        time::frames_to_duration(self.frame(), self.playhead.sample_rate)
+    }
+
+    /// Returns whether the scheduler is playing.
+    #[inline]
+    pub fn state(&self) -> PlaybackState {
+        PlaybackState::from_u8(self.playhead.state.load(Ordering::Relaxed))
+    }
+
+    /// Returns the sample rate of the scheduler.
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.playhead.sample_rate
+    }
+
+    /// Returns the number of channels of the scheduler.
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.playhead.channels
+    }
+}
@@ -0,0 +1,129 @@
+//! A bounded, lock-free queue used to pass messages to and from the audio thread.
+//!
+//! This is an implementation of Dmitry Vyukov's bounded MPMC queue. Every slot is allocated
+//! up front, so pushing and popping never allocate, and neither operation blocks: a full queue
+//! rejects the value and an empty queue returns `None`.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Slot<T> {
+    /// Sequence number used to determine whether the slot is ready to be written or read.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A bounded multi-producer, multi-consumer queue.
+pub(crate) struct BoundedQueue<T> {
+    buffer: Box<[Slot<T>]>,
+    mask: usize,
+    enqueue_position: AtomicUsize,
+    dequeue_position: AtomicUsize,
+}
+
+// SAFETY: Values are only moved in and out of the slots by the thread that claimed them through
+// the sequence numbers, so the queue can be shared as long as the values can be sent.
+unsafe impl<T: Send> Send for BoundedQueue<T> {}
+unsafe impl<T: Send> Sync for BoundedQueue<T> {}
+
+impl<T> BoundedQueue<T> {
+    /// Creates a new queue that can hold at least `capacity` values.
+    ///
+    /// The capacity is rounded up to the next power of two.
+    pub(crate) fn with_capacity(capacity: usize) -> BoundedQueue<T> {
+        let capacity = capacity.max(2).next_power_of_two();
+
+        let buffer = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        BoundedQueue {
+            buffer,
+            mask: capacity - 1,
+            enqueue_position: AtomicUsize::new(0),
+            dequeue_position: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pushes a value into the queue.
+    ///
+    /// Returns the value back if the queue is full.
+    #[inline]
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut position = self.enqueue_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.buffer[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence as isize - position as isize;
+
+            if difference == 0 {
+                match self.enqueue_position.compare_exchange_weak(
+                    position,
+                    position + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The compare exchange above gave us exclusive access to this slot.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(position + 1, Ordering::Release);
+
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                }
+            } else if difference < 0 {
+                return Err(value);
+            } else {
+                position = self.enqueue_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops the oldest value from the queue, if there is one.
+    #[inline]
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut position = self.dequeue_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.buffer[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence as isize - (position + 1) as isize;
+
+            if difference == 0 {
+                match self.dequeue_position.compare_exchange_weak(
+                    position,
+                    position + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The compare exchange above gave us exclusive access to this slot,
+                        // and the sequence number guarantees that it was written to.
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence
+                            .store(position + self.mask + 1, Ordering::Release);
+
+                        return Some(value);
+                    }
+                    Err(current) => position = current,
+                }
+            } else if difference < 0 {
+                return None;
+            } else {
+                position = self.dequeue_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Drop for BoundedQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
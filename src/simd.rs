@@ -12,7 +12,9 @@ use std::simd::cmp::SimdPartialEq;
 #[cfg(feature = "simd")]
 use std::simd::cmp::SimdPartialOrd;
 #[cfg(feature = "simd")]
-use std::simd::{Mask, Simd, Select};
+use std::simd::num::{SimdFloat, SimdUint};
+#[cfg(feature = "simd")]
+use std::simd::{Mask, Select, Simd, StdFloat};
 
 #[cfg(feature = "simd")]
 use crate::simd_utils::SimdOps;
@@ -21,51 +23,114 @@ use crate::simd_utils::{SimdIter, SimdIterator, gather_select_or_checked_u64};
 
 use rodio::Sample;
 
+/// Per-event playback parameters, stored in parallel with a playback schedule.
+///
+/// Every slice must have the same length as the playback schedule it describes.
+#[derive(Clone, Copy, Debug)]
+pub struct EventParameters<'a> {
+    /// The gain of each event for the channel being mixed, with panning already applied.
+    pub gains: &'a [f32],
+
+    /// The playback rate of each event. A speed of `1.0` plays the source at its original rate.
+    pub speeds: &'a [f64],
+
+    /// The sample at which each event stops playing, even if the source has not ended yet.
+    pub stops: &'a [u64],
+
+    /// The number of interleaved channels in the source.
+    pub channels: u16,
+}
+
 /// Retrieves samples from a source based on a playback schedule.
 ///
-/// This is a scalar fallback function used when the `simd` feature is not enabled.
+/// This is a scalar fallback function used when the `simd` feature is not enabled. The samples
+/// are produced lazily, so nothing is allocated.
 #[inline]
 #[cfg(not(feature = "simd"))]
 #[cfg_attr(feature = "profiler", instrument)]
 pub fn retrieve_samples_scalar<'a>(
     source: &'a [Sample],
-    playback_schedule: &[u64],
+    playback_schedule: &'a [u64],
     queue_index: (usize, usize),
     sample_n: u64,
-) -> Vec<Sample> {
-    if playback_schedule.len() == 0 || queue_index.0 == queue_index.1 {
-        return Vec::new();
-    }
+) -> impl Iterator<Item = Sample> + 'a {
+    let playback_queue: &'a [u64] = if playback_schedule.is_empty() {
+        &[]
+    } else {
+        &playback_schedule[queue_index.0..queue_index.1]
+    };
 
-    let playback_queue: &[u64] = &playback_schedule[queue_index.0..queue_index.1];
-    let mut output = Vec::with_capacity(playback_queue.len());
+    playback_queue.iter().map(move |&timestamp| {
+        if timestamp > sample_n {
+            return 0.0;
+        }
 
-    if playback_queue.len() == 0 {
-        output.push(0.0);
+        usize::try_from(sample_n - timestamp)
+            .ok()
+            .and_then(|index| source.get(index))
+            .copied()
+            .unwrap_or(0.0)
+    })
+}
 
-        return output;
+/// Retrieves and mixes samples from a source based on a playback schedule, applying the playback
+/// parameters of each event.
+///
+/// The source is resampled with linear interpolation for events that don't play at their
+/// original speed. This is a scalar fallback function used when the `simd` feature is not
+/// enabled.
+#[inline]
+#[cfg(not(feature = "simd"))]
+#[cfg_attr(feature = "profiler", instrument)]
+pub fn retrieve_and_mix_samples_with_parameters_scalar(
+    source: &[Sample],
+    playback_schedule: &[u64],
+    parameters: EventParameters,
+    queue_index: (usize, usize),
+    sample_n: u64,
+) -> Option<Sample> {
+    if queue_index.0 == queue_index.1 {
+        return None;
     }
 
-    for &timestamp in playback_queue {
-        if timestamp > sample_n {
-            output.push(0.0);
-
-            continue;
-        };
+    let channels = parameters.channels as u64;
+    let channel = sample_n % channels;
 
-        let index = (sample_n - timestamp) as usize;
-        if index >= source.len() {
-            output.push(0.0);
+    let window = queue_index.0..queue_index.1;
+    let events = playback_schedule[window.clone()]
+        .iter()
+        .zip(&parameters.stops[window.clone()])
+        .zip(&parameters.speeds[window.clone()])
+        .zip(&parameters.gains[window]);
 
+    let mut output = 0.0;
+    for (((&timestamp, &stop), &speed), &gain) in events {
+        if timestamp > sample_n || sample_n >= stop {
             continue;
         }
 
-        if let Some(sample) = source.get(index) {
-            output.push(*sample);
-        }
+        let position = ((sample_n - timestamp) / channels) as f64 * speed;
+        let frame = position.floor();
+        let fraction = (position - frame) as Sample;
+
+        let index = (frame as u64)
+            .saturating_mul(channels)
+            .saturating_add(channel);
+        let sample = |index: u64| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| source.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        let current = sample(index);
+        let next = sample(index.saturating_add(channels));
+
+        output += (current + (next - current) * fraction) * gain;
     }
 
-    output
+    Some(output)
 }
 
 /// Mixes a slice of samples with an input sample.
@@ -74,13 +139,16 @@ pub fn retrieve_samples_scalar<'a>(
 #[inline]
 #[cfg(not(feature = "simd"))]
 #[cfg_attr(feature = "profiler", instrument)]
-pub fn mix_samples_scalar(samples: &[Sample], input_sample: Option<Sample>) -> Option<Sample> {
+pub fn mix_samples_scalar(
+    samples: impl IntoIterator<Item = Sample>,
+    input_sample: Option<Sample>,
+) -> Option<Sample> {
     samples
         .into_iter()
         .fold(input_sample, |accumulator, sample| match accumulator {
             Some(s1) => Some(s1 + sample),
             // If you want to make scheduled playback stop after the input Source ended, return None here
-            None => Some(*sample),
+            None => Some(sample),
         })
 }
 
@@ -124,6 +192,70 @@ pub fn retrieve_samples_simd<'a, const N: usize>(
     simd_iter.map(f)
 }
 
+/// Retrieves samples from a source based on a playback schedule using SIMD instructions,
+/// applying the playback parameters of each event.
+///
+/// The source is resampled with linear interpolation for events that don't play at their
+/// original speed. This function is used when the `simd` feature is enabled.
+#[inline]
+#[cfg(feature = "simd")]
+#[cfg_attr(feature = "profiler", instrument)]
+pub fn retrieve_samples_with_parameters_simd<'a, const N: usize>(
+    source: &'a [Sample],
+    playback_schedule: &'a [u64],
+    parameters: EventParameters<'a>,
+    queue_index: (usize, usize),
+    sample_n: u64,
+) -> impl SimdIterator<Sample, N> + 'a {
+    let window = queue_index.0..queue_index.1;
+
+    // See retrieve_samples_simd for why out of bounds timestamps are filled with u64::MAX.
+    let out_of_bounds = Simd::splat(u64::MAX);
+
+    let timestamps: SimdIter<'a, u64, N> =
+        SimdIter::from_slice_or(&playback_schedule[window.clone()], out_of_bounds);
+    let speeds: SimdIter<'a, f64, N> =
+        SimdIter::from_slice_or(&parameters.speeds[window.clone()], Simd::splat(1.0));
+    let gains: SimdIter<'a, f32, N> =
+        SimdIter::from_slice_or_default(&parameters.gains[window.clone()]);
+    let stops: SimdIter<'a, u64, N> = SimdIter::from_slice_or_default(&parameters.stops[window]);
+
+    let channels = parameters.channels as u64;
+    let simd_channels = Simd::splat(channels);
+    let simd_channel = Simd::splat(sample_n % channels);
+
+    let timestamps_and_parameters = timestamps.zip(speeds).zip(gains).zip(stops);
+
+    timestamps_and_parameters.map(
+        move |((((data, load_mask), (speed, _)), (gain, _)), (stop, _))| {
+            let simd_sample_n = Simd::splat(sample_n);
+
+            // Safeguard: Dont gather indexes set as out of bounds, that happen after the current sample_n,
+            // or that have been stopped.
+            let mask = !data.simd_eq(out_of_bounds)
+                & data.simd_le(simd_sample_n)
+                & simd_sample_n.simd_lt(stop)
+                & load_mask;
+
+            // Masked lanes could underflow here, but they are never gathered.
+            let frames = (simd_sample_n - data) / simd_channels;
+            let position = frames.cast::<f64>() * speed;
+            let frame = position.floor();
+            let fraction: Simd<f32, N> = (position - frame).cast();
+
+            let idxs = frame.cast::<u64>() * simd_channels + simd_channel;
+            let zero = Simd::splat(0.0);
+
+            let current = gather_select_or_checked_u64(source, idxs, mask, zero);
+            let next = gather_select_or_checked_u64(source, idxs + simd_channels, mask, zero);
+
+            let samples: Simd<Sample, N> = (current + (next - current) * fraction) * gain;
+
+            (samples, Mask::splat(true))
+        },
+    )
+}
+
 /// Mixes a slice of samples with an input sample using SIMD instructions.
 ///
 /// This function is used when the `simd` feature is enabled.
@@ -154,6 +286,87 @@ fn mix_samples_simd<const N: usize>(
     }
 }
 
+/// The number of gains in the repeating pattern of [`mix_samples_with_gains`].
+///
+/// Any channel count that divides it can be mixed with a pattern.
+pub const GAIN_PATTERN_LEN: usize = 8;
+
+/// Adds a slice of samples to an output slice, multiplying them by a repeating pattern of gains.
+///
+/// The gain of each sample is `gains[index % GAIN_PATTERN_LEN]`, which allows mixing interleaved
+/// audio with a different gain for each channel. Only the samples that fit in both slices are
+/// mixed.
+///
+/// This function will use SIMD instructions if the `simd` feature is enabled, otherwise it will
+/// use a scalar fallback.
+#[inline]
+#[cfg_attr(feature = "profiler", instrument)]
+pub fn mix_samples_with_gains(
+    output: &mut [Sample],
+    samples: &[Sample],
+    gains: [f32; GAIN_PATTERN_LEN],
+) {
+    let length = output.len().min(samples.len());
+    let (output, samples) = (&mut output[..length], &samples[..length]);
+
+    #[cfg(feature = "simd")]
+    {
+        // SIMD algorithm
+        let simd_gains: Simd<f32, GAIN_PATTERN_LEN> = Simd::from_array(gains);
+
+        let mut output_chunks = output.chunks_exact_mut(GAIN_PATTERN_LEN);
+        let mut sample_chunks = samples.chunks_exact(GAIN_PATTERN_LEN);
+        for (output, samples) in (&mut output_chunks).zip(&mut sample_chunks) {
+            let mixed = Simd::from_slice(output) + Simd::from_slice(samples) * simd_gains;
+
+            mixed.copy_to_slice(output);
+        }
+
+        // The remainders start on a multiple of the pattern length, so the pattern starts over.
+        mix_samples_with_gains_scalar(
+            output_chunks.into_remainder(),
+            sample_chunks.remainder(),
+            &gains,
+        );
+    }
+
+    #[cfg(not(feature = "simd"))]
+    {
+        // Fallback scalar algorithm
+        mix_samples_with_gains_scalar(output, samples, &gains);
+    }
+}
+
+/// Adds a slice of samples to an output slice, multiplying them by a repeating pattern of gains.
+#[inline]
+fn mix_samples_with_gains_scalar(output: &mut [Sample], samples: &[Sample], gains: &[f32]) {
+    for ((output, sample), gain) in output.iter_mut().zip(samples).zip(gains.iter().cycle()) {
+        *output += sample * gain;
+    }
+}
+
+/// Adds every sample of a slice together.
+///
+/// Unlike [`mix_samples`], the result is not clamped. This function will use SIMD instructions
+/// if the `simd` feature is enabled, otherwise it will use a scalar fallback.
+#[inline]
+#[cfg_attr(feature = "profiler", instrument)]
+pub fn sum_samples(samples: &[Sample]) -> Sample {
+    #[cfg(feature = "simd")]
+    {
+        let simd_iter: SimdIter<Sample, 4> = SimdIter::from_slice_or_default(samples);
+
+        // SIMD algorithm
+        mix_samples_simd::<4>(simd_iter, None).unwrap_or(0.0)
+    }
+
+    #[cfg(not(feature = "simd"))]
+    {
+        // Fallback scalar algorithm
+        mix_samples_scalar(samples.iter().copied(), None).unwrap_or(0.0)
+    }
+}
+
 /// Mixes a slice of samples with an input sample.
 ///
 /// This function will use SIMD instructions if the `simd` feature is enabled, otherwise it will
@@ -172,7 +385,8 @@ pub fn mix_samples(samples: &[Sample], input_sample: Option<Sample>) -> Option<S
     #[cfg(not(feature = "simd"))]
     {
         // Fallback scalar algorithm
-        mix_samples_scalar(samples, input_sample).map(|s: Sample| s.clamp(-1.0, 1.0))
+        mix_samples_scalar(samples.iter().copied(), input_sample)
+            .map(|s: Sample| s.clamp(-1.0, 1.0))
     }
 }
 
@@ -205,6 +419,47 @@ pub fn retrieve_and_mix_samples(
             retrieve_samples_scalar(source, playback_schedule, queue_index, sample_n);
 
         // Mix scheduled and input samples
-        mix_samples_scalar(playing_samples.as_slice(), None)
+        mix_samples_scalar(playing_samples, None)
+    }
+}
+
+/// Retrieves and mixes samples from a source, applying the playback parameters of each event.
+///
+/// This function will use SIMD instructions if the `simd` feature is enabled, otherwise it will
+/// use a scalar fallback.
+#[inline]
+#[cfg_attr(feature = "profiler", instrument)]
+pub fn retrieve_and_mix_samples_with_parameters(
+    source: &[Sample],
+    playback_schedule: &[u64],
+    parameters: EventParameters,
+    queue_index: (usize, usize),
+    sample_n: u64,
+) -> Option<Sample> {
+    #[cfg(feature = "simd")]
+    {
+        // SIMD algorithm
+        let playing_samples = retrieve_samples_with_parameters_simd::<4>(
+            source,
+            playback_schedule,
+            parameters,
+            queue_index,
+            sample_n,
+        );
+
+        // Mix scheduled and input samples
+        mix_samples_simd(playing_samples, None)
+    }
+
+    #[cfg(not(feature = "simd"))]
+    {
+        // Fallback scalar algorithm
+        retrieve_and_mix_samples_with_parameters_scalar(
+            source,
+            playback_schedule,
+            parameters,
+            queue_index,
+            sample_n,
+        )
     }
 }
@@ -123,10 +123,8 @@ where
             let start = self.i * N;
             let end = self.src.len();
 
-            let indices: Vec<usize> = (0..N).collect();
-            let indices_simd: Simd<usize, N> = Simd::from_slice(&indices);
             let valid_loads: Mask<T::Mask, N> =
-                indices_simd.simd_lt(Simd::splat(end - start)).cast();
+                Mask::from_array(std::array::from_fn(|lane| lane < end - start));
 
             Some((Simd::load_or(&self.src[start..end], self.or), valid_loads))
         };
@@ -140,7 +138,7 @@ where
     #[cfg_attr(feature = "profiler", instrument(name = "SimdIter::size_hint"))]
     fn size_hint(&self) -> (usize, Option<usize>) {
         let body_size = self.src.len() / N;
-        let tail_size = if self.src.len() % N == 0 { 0 } else { 1 };
+        let tail_size = if self.src.len().is_multiple_of(N) { 0 } else { 1 };
         let size = body_size + tail_size;
 
         (size, Some(size))
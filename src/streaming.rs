@@ -0,0 +1,1221 @@
+//! This module provides a scheduler for sources that are too long to be preloaded in memory.
+//!
+//! A [`StreamingSourceScheduler`] decodes its source on demand, on a background thread. Each
+//! playing event is assigned a voice, which owns a preallocated ring buffer that the background
+//! thread keeps filled ahead of the playback position. The audio thread only reads from those
+//! buffers, so it never blocks or allocates. Since every voice needs its own decoder and buffer,
+//! only a limited number of events can overlap.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rodio::Sample;
+use rodio::source::{SeekError, Source, UniformSourceIterator};
+use rtsan_standalone::nonblocking;
+
+#[cfg(feature = "profiler")]
+use time_graph::instrument;
+
+use crate::queue::BoundedQueue;
+use crate::strip::ChannelStrip;
+use crate::tempo::{BeatRepeat, TempoMap};
+use crate::{
+    EVENT_NOTIFICATION_QUEUE_CAPACITY, EventId, EventNotification, LATE_EVENT_QUEUE_CAPACITY,
+    LateEvent, LateEventPolicy, PlaybackEvent, ResolvedParameters, SCHEDULE_CAPACITY, SampleType,
+    SchedulerError, next_frame, set_capacity,
+};
+
+/// The default number of events that can play at the same time.
+pub const DEFAULT_VOICES: usize = 4;
+
+/// The default duration of audio buffered ahead of the playback position for every voice.
+pub const DEFAULT_BUFFER_DURATION: Duration = Duration::from_secs(1);
+
+/// How often the background thread refills the voice buffers while a voice is streaming.
+const DECODER_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// The number of samples the background thread decodes at a time while probing the length of a
+/// source that doesn't report its duration.
+const PROBE_CHUNK: usize = 1 << 16;
+
+/// The voice is not assigned to any event.
+const VOICE_IDLE: u8 = 0;
+/// The audio thread assigned an event to the voice, and is waiting for a decoder to be created.
+const VOICE_REQUESTED: u8 = 1;
+/// The background thread is decoding the source into the voice buffer.
+const VOICE_STREAMING: u8 = 2;
+/// The audio thread is done with the voice, and is waiting for the background thread to reset it.
+const VOICE_RELEASED: u8 = 3;
+
+/// A single-producer, single-consumer ring buffer of samples.
+///
+/// Samples are stored as the bits of an `f32`, so the buffer can be shared without locks.
+struct SampleRing {
+    buffer: Box<[AtomicU32]>,
+    mask: usize,
+    /// Position of the next sample to be read, only written by the consumer.
+    head: AtomicUsize,
+    /// Position of the next sample to be written, only written by the producer.
+    tail: AtomicUsize,
+}
+
+impl SampleRing {
+    fn with_capacity(capacity: usize) -> SampleRing {
+        let capacity = capacity.max(2).next_power_of_two();
+
+        SampleRing {
+            buffer: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            mask: capacity - 1,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of samples that can be pushed before the buffer is full.
+    #[inline]
+    fn free(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Relaxed);
+
+        self.buffer.len() - tail.wrapping_sub(head)
+    }
+
+    /// Pushes a sample. Must only be called by the producer, after checking there is room for it.
+    #[inline]
+    fn push(&self, sample: Sample) {
+        let tail = self.tail.load(Ordering::Relaxed);
+
+        self.buffer[tail & self.mask].store(sample.to_bits(), Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Pops a sample. Must only be called by the consumer.
+    #[inline]
+    fn pop(&self) -> Option<Sample> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        let sample = Sample::from_bits(self.buffer[head & self.mask].load(Ordering::Relaxed));
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+
+        Some(sample)
+    }
+
+    /// Empties the buffer. Must only be called while neither side is using it.
+    #[inline]
+    fn reset(&self) {
+        self.head.store(0, Ordering::Relaxed);
+        self.tail.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The state of a voice shared between the audio thread and the background thread.
+struct SharedVoice {
+    /// One of the `VOICE_*` states.
+    state: AtomicU8,
+    ring: SampleRing,
+    /// Set by the background thread once the source has been fully decoded into the buffer.
+    finished: AtomicBool,
+    /// The number of samples to skip from the start of the source, set when the voice is requested.
+    start_offset: AtomicU64,
+}
+
+/// State shared with the background thread.
+struct StreamShared {
+    voices: Box<[SharedVoice]>,
+    /// The number of samples the source plays for, or `SampleType::MAX` while it is unknown.
+    source_length: AtomicU64,
+    shutdown: AtomicBool,
+}
+
+/// The audio thread side of a voice.
+#[derive(Clone, Copy)]
+struct Voice {
+    /// The event this voice is playing, if any.
+    event_id: Option<EventId>,
+    /// The sample at which the event starts.
+    start: SampleType,
+    /// The frame the event starts on, until its start has been reported.
+    unreported_start: Option<SampleType>,
+    parameters: ResolvedParameters,
+    /// Samples that were missed because the buffer ran dry, which are skipped once it is refilled
+    /// to stay in sync.
+    underrun: SampleType,
+}
+
+const SILENT_VOICE: Voice = Voice {
+    event_id: None,
+    start: 0,
+    unreported_start: None,
+    parameters: ResolvedParameters {
+        gain: 1.0,
+        pan: 0.0,
+        speed: 1.0,
+    },
+    underrun: 0,
+};
+
+/// A boxed function that creates a new instance of the streamed source.
+type SourceFactory = Box<dyn Fn() -> Box<dyn Source + Send> + Send>;
+
+/// A source that schedules playback for a single audio source at precise timestamps, decoding
+/// it on demand instead of loading it in memory.
+///
+/// The source is created by a factory function every time an event starts playing, so long
+/// clips like vocal stems or backing tracks can be scheduled without decoding them up front.
+/// Decoding happens on a background thread, which keeps a buffer of samples ready for every
+/// voice. Events are assigned a voice half a buffer before they start, so there is time to fill
+/// it. If every voice is busy, the event waits for one to be released, and starts late, in sync
+/// with where it should be.
+///
+/// Streaming events can't be played at a different speed, so events with a `speed` other than
+/// `1.0` are rejected with [`SchedulerError::UnsupportedSpeed`]. Every other parameter, and the
+/// [`LateEventPolicy`], work like they do on a
+/// [`SingleSourceScheduler`](crate::SingleSourceScheduler).
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use rodio::Decoder;
+/// use rodio_scheduler::{PlaybackEvent, StreamingSourceScheduler};
+///
+/// # fn main() {
+///    let mut scheduler = StreamingSourceScheduler::new(
+///        || Decoder::new(File::open("assets/metronome.wav").unwrap()).unwrap(),
+///        48000,
+///        2,
+///    )
+///    .unwrap();
+///
+///    scheduler.schedule_event(PlaybackEvent {
+///        timestamp: 48000 * 2,
+///        ..Default::default()
+///    });
+/// # }
+/// ```
+pub struct StreamingSourceScheduler {
+    /// The target channel count.
+    channels: u16,
+
+    /// The target sample rate.
+    sample_rate: u32,
+
+    /// The identifier of this stream in its parent `Scheduler`, which is part of the identifier
+    /// of every event it schedules.
+    stream_id: usize,
+
+    /// The playback position of each event scheduled for this source, in samples.
+    playback_schedule: Vec<SampleType>,
+
+    /// The identifier of each event in `playback_schedule`.
+    event_ids: Vec<EventId>,
+
+    /// The playback parameters of each event in `playback_schedule`.
+    event_parameters: Vec<ResolvedParameters>,
+
+    /// Counter used to generate event identifiers.
+    event_counter: Arc<AtomicU64>,
+
+    /// What to do with events scheduled before the playback position.
+    late_event_policy: LateEventPolicy,
+
+    /// Reports of the late events this source has scheduled.
+    late_events: Arc<BoundedQueue<LateEvent>>,
+
+    /// Notifications of the events of this source that started or stopped playing.
+    notifications: Arc<BoundedQueue<EventNotification>>,
+
+    /// The sample the scheduler was last moved to. Events that started before it were already
+    /// playing there, so their start is not reported.
+    seek_position: SampleType,
+
+    /// The index of the next event in `playback_schedule` that has not been assigned a voice.
+    next_event: usize,
+
+    /// The number of playbacks `playback_schedule` holds before started events are reclaimed.
+    schedule_capacity: usize,
+
+    /// The audio thread side of every voice.
+    voices: Box<[Voice]>,
+
+    /// State shared with the background thread.
+    shared: Arc<StreamShared>,
+
+    /// The background thread, which parks itself while no voice needs it.
+    decoder: Option<JoinHandle<()>>,
+
+    /// How long before its start an event is assigned a voice, in samples.
+    preroll: SampleType,
+
+    /// The volume, mute and solo of this source.
+    strip: ChannelStrip,
+
+    /// Number of samples counted.
+    samples_counted: SampleType,
+}
+
+impl StreamingSourceScheduler {
+    /// Creates a new `StreamingSourceScheduler`, with the default number of voices and buffer
+    /// duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `factory`: A function that creates a new instance of the audio source to be scheduled.
+    /// * `sample_rate`: The sample rate of the output audio.
+    /// * `channels`: The number of channels in the output audio.
+    ///
+    /// Fails with [`SchedulerError::SpawnDecoder`] if the background thread can't be spawned.
+    #[inline]
+    pub fn new<F, S>(
+        factory: F,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<StreamingSourceScheduler, SchedulerError>
+    where
+        F: Fn() -> S + Send + 'static,
+        S: Source + Send + 'static,
+    {
+        StreamingSourceScheduler::with_voices(
+            factory,
+            sample_rate,
+            channels,
+            DEFAULT_VOICES,
+            DEFAULT_BUFFER_DURATION,
+        )
+    }
+
+    /// Creates a new `StreamingSourceScheduler`.
+    ///
+    /// # Arguments
+    ///
+    /// * `factory`: A function that creates a new instance of the audio source to be scheduled.
+    /// * `sample_rate`: The sample rate of the output audio.
+    /// * `channels`: The number of channels in the output audio.
+    /// * `voices`: The number of events that can play at the same time.
+    /// * `buffer_duration`: The duration of audio buffered ahead of the playback position for
+    ///   every voice.
+    ///
+    /// The background thread creates the source once when it starts, to find out how long events
+    /// play for when seeking. Most decoders report their duration, see [`Source::total_duration`],
+    /// but sources that don't are decoded in full to count their samples. That costs as much
+    /// decoding as playing the source once, though it is done in chunks, so voices are still
+    /// streamed in the meantime.
+    ///
+    /// Fails with [`SchedulerError::SpawnDecoder`] if the background thread can't be spawned.
+    pub fn with_voices<F, S>(
+        factory: F,
+        sample_rate: u32,
+        channels: u16,
+        voices: usize,
+        buffer_duration: Duration,
+    ) -> Result<StreamingSourceScheduler, SchedulerError>
+    where
+        F: Fn() -> S + Send + 'static,
+        S: Source + Send + 'static,
+    {
+        let buffer_frames = crate::time::duration_to_frames(buffer_duration, sample_rate).max(1);
+        let buffer_len =
+            usize::try_from(buffer_frames * channels as SampleType).unwrap_or(usize::MAX);
+
+        let shared = Arc::new(StreamShared {
+            voices: (0..voices)
+                .map(|_| SharedVoice {
+                    state: AtomicU8::new(VOICE_IDLE),
+                    ring: SampleRing::with_capacity(buffer_len),
+                    finished: AtomicBool::new(false),
+                    start_offset: AtomicU64::new(0),
+                })
+                .collect(),
+            source_length: AtomicU64::new(SampleType::MAX),
+            shutdown: AtomicBool::new(false),
+        });
+
+        let factory: SourceFactory = Box::new(move || {
+            Box::new(UniformSourceIterator::new(factory(), channels, sample_rate))
+        });
+
+        let decoder_shared = Arc::clone(&shared);
+        let decoder = thread::Builder::new()
+            .name("rodio_scheduler streaming".to_string())
+            .spawn(move || decode_voices(decoder_shared, factory, sample_rate, channels))
+            .map_err(SchedulerError::SpawnDecoder)?;
+
+        Ok(StreamingSourceScheduler {
+            channels,
+            sample_rate,
+            stream_id: 0,
+            playback_schedule: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_ids: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_parameters: Vec::with_capacity(SCHEDULE_CAPACITY),
+            event_counter: Arc::new(AtomicU64::new(0)),
+            late_event_policy: LateEventPolicy::Drop,
+            late_events: Arc::new(BoundedQueue::with_capacity(LATE_EVENT_QUEUE_CAPACITY)),
+            notifications: Arc::new(BoundedQueue::with_capacity(
+                EVENT_NOTIFICATION_QUEUE_CAPACITY,
+            )),
+            seek_position: 0,
+            next_event: 0,
+            schedule_capacity: SCHEDULE_CAPACITY,
+            voices: vec![SILENT_VOICE; voices].into_boxed_slice(),
+            shared,
+            decoder: Some(decoder),
+            preroll: buffer_frames / 2 * channels as SampleType,
+            strip: ChannelStrip::new(sample_rate, channels),
+            samples_counted: 0,
+        })
+    }
+
+    /// Schedules a `PlaybackEvent` for this source.
+    ///
+    /// Works like [`SingleSourceScheduler::schedule_event`](crate::SingleSourceScheduler::schedule_event),
+    /// including the [`LateEventPolicy`]. The identifier of the new event refers to this stream,
+    /// see [`EventId::is_streamed`], and can be used to cancel it. Fails with
+    /// [`SchedulerError::UnsupportedSpeed`] if the event doesn't play at its original speed.
+    #[inline]
+    pub fn schedule_event(&mut self, event: PlaybackEvent) -> Result<EventId, SchedulerError> {
+        let id = self.new_event_id();
+
+        self.add_event(id, event)?;
+
+        Ok(id)
+    }
+
+    /// Schedules a `PlaybackEvent` on a beat of a `TempoMap`.
+    ///
+    /// Works like [`SingleSourceScheduler::schedule_beat`](crate::SingleSourceScheduler::schedule_beat),
+    /// and fails like [`StreamingSourceScheduler::schedule_event`].
+    #[inline]
+    pub fn schedule_beat(
+        &mut self,
+        tempo_map: &TempoMap,
+        beat: f64,
+        event: PlaybackEvent,
+        repeat: Option<BeatRepeat>,
+    ) -> Result<EventId, SchedulerError> {
+        let sample_rate = self.sample_rate;
+        let Some(BeatRepeat {
+            ticks,
+            count: repeat_count,
+        }) = repeat
+        else {
+            return self.schedule_event(PlaybackEvent {
+                timestamp: tempo_map.beat_to_frame(beat, sample_rate),
+                ..event
+            });
+        };
+
+        if event.repeat.is_some() {
+            return Err(SchedulerError::ConflictingRepeat);
+        }
+
+        let channels = self.channels as SampleType;
+        let parameters = ResolvedParameters::new(&event);
+        if parameters.speed != 1.0 {
+            return Err(SchedulerError::UnsupportedSpeed(parameters.speed as f32));
+        }
+
+        let sample_of = |repetition: SampleType| {
+            let beat = beat + tempo_map.ticks_to_beats(ticks.saturating_mul(repetition));
+
+            tempo_map
+                .beat_to_frame(beat, sample_rate)
+                .checked_mul(channels)
+                .ok_or(SchedulerError::TimestampOverflow)
+        };
+
+        // Repetitions are played in order, so checking the first and last ones is enough.
+        let first = sample_of(0)?;
+        let delay = self.late_event_delay(first / channels)? * channels;
+        sample_of(repeat_count)?
+            .checked_add(delay)
+            .ok_or(SchedulerError::TimestampOverflow)?;
+
+        let id = self.new_event_id();
+
+        for repetition in 0..=repeat_count {
+            let sample = sample_of(repetition)? + delay;
+
+            self.insert_timestamp(id, sample, parameters);
+        }
+
+        self.report_if_late(id, first / channels);
+
+        Ok(id)
+    }
+
+    /// Checks that an event can be scheduled, following the late event policy.
+    #[inline]
+    pub(crate) fn check_event(&self, event: &PlaybackEvent) -> Result<(), SchedulerError> {
+        let speed = ResolvedParameters::new(event).speed;
+        if speed != 1.0 {
+            return Err(SchedulerError::UnsupportedSpeed(speed as f32));
+        }
+
+        event.check_timestamps(self.channels)?;
+
+        let delay = self.late_event_delay(event.timestamp)?;
+        if delay > 0 {
+            PlaybackEvent {
+                timestamp: event.timestamp + delay,
+                ..*event
+            }
+            .check_timestamps(self.channels)?;
+        }
+
+        Ok(())
+    }
+
+    /// Schedules an event with a known identifier, following the late event policy.
+    ///
+    /// Late events that are dropped are returned as an error, and not reported.
+    #[inline]
+    pub(crate) fn add_event(
+        &mut self,
+        id: EventId,
+        event: PlaybackEvent,
+    ) -> Result<(), SchedulerError> {
+        self.check_event(&event)?;
+        self.insert_event(id, event);
+
+        Ok(())
+    }
+
+    /// Generates a new, unique identifier for an event of this stream.
+    #[inline]
+    pub(crate) fn new_event_id(&self) -> EventId {
+        EventId {
+            source_id: self.stream_id,
+            serial: self.event_counter.fetch_add(1, Ordering::Relaxed),
+            streamed: true,
+        }
+    }
+
+    /// Removes the events that already have a voice if the schedule can't hold `event`, and
+    /// returns `true` if it fits in the schedule without reallocating it.
+    #[inline]
+    pub(crate) fn make_room_for(&mut self, event: &PlaybackEvent) -> bool {
+        let playbacks = event.playback_count();
+        self.reclaim_for(playbacks);
+
+        let capacity = self
+            .playback_schedule
+            .capacity()
+            .min(self.event_ids.capacity())
+            .min(self.event_parameters.capacity());
+
+        playbacks <= capacity - self.playback_schedule.len()
+    }
+
+    /// Removes the events that already have a voice from the schedule if it can't hold
+    /// `playbacks` more playbacks.
+    #[inline]
+    fn reclaim_for(&mut self, playbacks: usize) {
+        if self.playback_schedule.len().saturating_add(playbacks) > self.schedule_capacity {
+            // Events that have been assigned a voice are no longer needed to play them.
+            self.remove_range(0, self.next_event);
+        }
+    }
+
+    /// Inserts an event and all of its repetitions into the schedule, following the late event
+    /// policy, and reports it if it is late.
+    ///
+    /// The event must have been checked first, see [`StreamingSourceScheduler::check_event`].
+    #[inline]
+    pub(crate) fn insert_event(&mut self, id: EventId, event: PlaybackEvent) {
+        self.reclaim_for(event.playback_count());
+
+        let delay = self.late_event_delay(event.timestamp).unwrap_or(0);
+        let parameters = ResolvedParameters::new(&event);
+        let delayed = PlaybackEvent {
+            timestamp: event.timestamp + delay,
+            ..event
+        };
+
+        for sample in delayed.playback_samples(self.channels) {
+            self.insert_timestamp(id, sample, parameters);
+        }
+
+        self.report_if_late(id, event.timestamp);
+    }
+
+    /// Returns the number of frames an event starting on the given frame must be delayed by,
+    /// following the late event policy.
+    #[inline]
+    fn late_event_delay(&self, timestamp: SampleType) -> Result<SampleType, SchedulerError> {
+        let position = next_frame(self.samples_counted, self.channels);
+
+        self.late_event_policy.delay(timestamp, position)
+    }
+
+    /// Reports an event if it starts on a frame that has already been played.
+    #[inline]
+    fn report_if_late(&self, id: EventId, timestamp: SampleType) {
+        let position = next_frame(self.samples_counted, self.channels);
+        if timestamp >= position {
+            return;
+        }
+
+        self.report_late_event(LateEvent {
+            id,
+            timestamp,
+            position,
+            policy: self.late_event_policy,
+        });
+    }
+
+    /// Adds a report to the late event queue, dropping it if the queue is full.
+    #[inline]
+    fn report_late_event(&self, late_event: LateEvent) {
+        let _ = self.late_events.push(late_event);
+    }
+
+    /// Sets what this scheduler does with events scheduled before its playback position.
+    ///
+    /// See [`LateEventPolicy`]. Truncated events resume from the sample they would be playing,
+    /// like they do after a seek.
+    #[inline]
+    pub fn set_late_event_policy(&mut self, policy: LateEventPolicy) {
+        self.late_event_policy = policy;
+    }
+
+    /// Returns what this scheduler does with events scheduled before its playback position.
+    #[inline]
+    pub fn late_event_policy(&self) -> LateEventPolicy {
+        self.late_event_policy
+    }
+
+    /// Returns the oldest report of a late event that hasn't been read yet.
+    ///
+    /// See [`SingleSourceScheduler::pop_late_event`](crate::SingleSourceScheduler::pop_late_event).
+    #[inline]
+    pub fn pop_late_event(&self) -> Option<LateEvent> {
+        self.late_events.pop()
+    }
+
+    /// Returns the oldest notification of an event starting or stopping that hasn't been read
+    /// yet.
+    ///
+    /// See [`SingleSourceScheduler::pop_event_notification`](crate::SingleSourceScheduler::pop_event_notification).
+    /// Events that wait for a free voice report the frame they were scheduled on, even though
+    /// they start late.
+    #[inline]
+    pub fn pop_event_notification(&self) -> Option<EventNotification> {
+        self.notifications.pop()
+    }
+
+    /// Cancels a scheduled event and all of its repetitions.
+    ///
+    /// If the event is already playing, it is stopped immediately. Returns `true` if the event was
+    /// found in the playback schedule.
+    #[inline]
+    pub fn cancel_event(&mut self, id: EventId) -> bool {
+        let mut found = false;
+        let mut index = 0;
+
+        while index < self.event_ids.len() {
+            if self.event_ids[index] == id {
+                self.remove_range(index, index + 1);
+
+                found = true;
+            } else {
+                index += 1;
+            }
+        }
+
+        for voice in 0..self.voices.len() {
+            if self.voices[voice].event_id == Some(id) {
+                self.stop_voice(voice, self.samples_counted);
+            }
+        }
+
+        found
+    }
+
+    /// Cancels every event scheduled to start between `start` (inclusive) and `end` (exclusive).
+    ///
+    /// Works like [`SingleSourceScheduler::cancel_range`](crate::SingleSourceScheduler::cancel_range).
+    #[inline]
+    pub fn cancel_range(&mut self, start: SampleType, end: SampleType) -> usize {
+        let channels = self.channels as SampleType;
+        let start = start.saturating_mul(channels);
+        let end = end.saturating_mul(channels);
+
+        let first = self.playback_schedule.partition_point(|&t| t < start);
+        let last = self
+            .playback_schedule
+            .partition_point(|&t| t < end)
+            .max(first);
+
+        self.remove_range(first, last);
+
+        for voice in 0..self.voices.len() {
+            let playing = self.voices[voice];
+
+            if playing.event_id.is_some() && (start..end).contains(&playing.start) {
+                self.stop_voice(voice, self.samples_counted);
+            }
+        }
+
+        last - first
+    }
+
+    /// Cancels every scheduled event, stopping the ones that are already playing.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.playback_schedule.clear();
+        self.event_ids.clear();
+        self.event_parameters.clear();
+        self.next_event = 0;
+
+        for voice in 0..self.voices.len() {
+            if self.voices[voice].event_id.is_some() {
+                self.stop_voice(voice, self.samples_counted);
+            }
+        }
+    }
+
+    /// Sets the number of playbacks the schedule can hold, including repetitions, before
+    /// reallocating.
+    ///
+    /// Works like
+    /// [`SingleSourceScheduler::set_schedule_capacity`](crate::SingleSourceScheduler::set_schedule_capacity),
+    /// except that events are removed from the schedule as soon as they start playing.
+    pub fn set_schedule_capacity(&mut self, capacity: usize) {
+        let capacity = capacity.max(self.playback_schedule.len());
+
+        self.schedule_capacity = capacity;
+
+        set_capacity(&mut self.playback_schedule, capacity);
+        set_capacity(&mut self.event_ids, capacity);
+        set_capacity(&mut self.event_parameters, capacity);
+    }
+
+    /// Returns the number of playbacks the schedule can hold before reallocating.
+    #[inline]
+    pub fn schedule_capacity(&self) -> usize {
+        self.schedule_capacity
+    }
+
+    /// Sets the volume of this source.
+    ///
+    /// Works like [`SingleSourceScheduler::set_volume`](crate::SingleSourceScheduler::set_volume).
+    #[inline]
+    pub fn set_volume(&mut self, volume: f32) {
+        self.strip.set_volume(volume, self.samples_counted);
+    }
+
+    /// Returns the volume of this source.
+    #[inline]
+    pub fn volume(&self) -> f32 {
+        self.strip.volume()
+    }
+
+    /// Mutes or unmutes this source.
+    ///
+    /// Works like [`SingleSourceScheduler::set_muted`](crate::SingleSourceScheduler::set_muted).
+    #[inline]
+    pub fn set_muted(&mut self, muted: bool) {
+        self.strip.set_muted(muted, self.samples_counted);
+    }
+
+    /// Returns `true` if this source is muted.
+    #[inline]
+    pub fn is_muted(&self) -> bool {
+        self.strip.is_muted()
+    }
+
+    /// Solos or unsolos this source.
+    ///
+    /// Works like [`SingleSourceScheduler::set_soloed`](crate::SingleSourceScheduler::set_soloed).
+    #[inline]
+    pub fn set_soloed(&mut self, soloed: bool) {
+        self.strip.set_soloed(soloed, self.samples_counted);
+    }
+
+    /// Returns `true` if this source is soloed.
+    #[inline]
+    pub fn is_soloed(&self) -> bool {
+        self.strip.is_soloed()
+    }
+
+    /// Silences this source because another source is soloed, or brings it back.
+    #[inline]
+    pub(crate) fn set_silenced_by_solo(&mut self, silenced: bool) {
+        self.strip.set_silenced(silenced, self.samples_counted);
+    }
+
+    /// Shares an event identifier counter with this scheduler.
+    #[inline]
+    pub(crate) fn share_event_counter(&mut self, event_counter: Arc<AtomicU64>) {
+        self.event_counter = event_counter;
+    }
+
+    /// Returns the identifier of this stream in its parent `Scheduler`, or `0` if it is played on
+    /// its own.
+    #[inline]
+    pub fn stream_id(&self) -> usize {
+        self.stream_id
+    }
+
+    /// Sets the identifier of this stream in its parent `Scheduler`.
+    #[inline]
+    pub(crate) fn set_stream_id(&mut self, stream_id: usize) {
+        self.stream_id = stream_id;
+    }
+
+    /// Shares a late event report queue with this scheduler.
+    #[inline]
+    pub(crate) fn share_late_events(&mut self, late_events: Arc<BoundedQueue<LateEvent>>) {
+        self.late_events = late_events;
+    }
+
+    /// Shares an event notification queue with this scheduler.
+    #[inline]
+    pub(crate) fn share_event_notifications(
+        &mut self,
+        notifications: Arc<BoundedQueue<EventNotification>>,
+    ) {
+        self.notifications = notifications;
+    }
+
+    /// Adds a notification to the queue, dropping it if the queue is full.
+    #[inline]
+    fn notify(&self, notification: EventNotification) {
+        let _ = self.notifications.push(notification);
+    }
+
+    /// Moves the scheduler to a new sample index.
+    ///
+    /// Events that are playing at that point are assigned a voice again, and resume from the
+    /// right sample if the length of the source is known.
+    #[inline]
+    pub(crate) fn set_position(&mut self, sample: SampleType) {
+        self.release_all_voices();
+
+        let source_length = self.shared.source_length.load(Ordering::Relaxed);
+        self.next_event = self
+            .playback_schedule
+            .partition_point(|&t| t.saturating_add(source_length) <= sample);
+
+        self.samples_counted = sample;
+        self.seek_position = sample;
+    }
+
+    #[inline]
+    fn insert_timestamp(
+        &mut self,
+        id: EventId,
+        timestamp: SampleType,
+        parameters: ResolvedParameters,
+    ) {
+        let index = self.playback_schedule.partition_point(|&t| t <= timestamp);
+
+        self.playback_schedule.insert(index, timestamp);
+        self.event_ids.insert(index, id);
+        self.event_parameters.insert(index, parameters);
+
+        // Events inserted before the ones that already have a voice are picked up right away.
+        self.next_event = self.next_event.min(index);
+    }
+
+    #[inline]
+    fn remove_range(&mut self, first: usize, last: usize) {
+        self.playback_schedule.drain(first..last);
+        self.event_ids.drain(first..last);
+        self.event_parameters.drain(first..last);
+
+        if self.next_event >= last {
+            self.next_event -= last - first;
+        } else {
+            self.next_event = self.next_event.min(first);
+        }
+    }
+
+    /// Returns `true` if no event is playing, and none is left to play.
+    #[inline]
+    pub(crate) fn is_idle(&self) -> bool {
+        self.next_event == self.playback_schedule.len()
+            && self.voices.iter().all(|voice| voice.event_id.is_none())
+    }
+
+    /// Stops the event a voice is playing, and reports its end if it has started, with `sample`
+    /// as the first sample it doesn't play.
+    #[inline]
+    fn stop_voice(&mut self, voice: usize, sample: SampleType) {
+        let playing = self.voices[voice];
+
+        if let Some(id) = playing.event_id
+            && playing.unreported_start.is_none()
+        {
+            self.notify(EventNotification::EventEnded {
+                id,
+                source_id: id.source_id(),
+                frame: next_frame(sample, self.channels),
+            });
+        }
+
+        self.release_voice(voice);
+    }
+
+    #[inline]
+    fn release_voice(&mut self, voice: usize) {
+        self.voices[voice] = SILENT_VOICE;
+
+        let state = &self.shared.voices[voice].state;
+        if state.load(Ordering::Acquire) != VOICE_IDLE {
+            state.store(VOICE_RELEASED, Ordering::Release);
+            self.wake_decoder();
+        }
+    }
+
+    /// Wakes the background thread up if it is parked, so it handles a voice that changed state.
+    #[inline]
+    fn wake_decoder(&self) {
+        if let Some(decoder) = &self.decoder {
+            decoder.thread().unpark();
+        }
+    }
+
+    #[inline]
+    fn release_all_voices(&mut self) {
+        for voice in 0..self.voices.len() {
+            if self.voices[voice].event_id.is_some() {
+                self.release_voice(voice);
+            }
+        }
+    }
+
+    /// Assigns voices to the events that start within the preroll window.
+    #[inline]
+    fn assign_voices(&mut self, s: SampleType) {
+        let source_length = self.shared.source_length.load(Ordering::Relaxed);
+
+        while self.next_event < self.playback_schedule.len() {
+            let start = self.playback_schedule[self.next_event];
+            if start > s.saturating_add(self.preroll) {
+                break;
+            }
+
+            // Skip events that were missed entirely.
+            if start.saturating_add(source_length) <= s {
+                self.next_event += 1;
+
+                continue;
+            }
+
+            let Some(voice) = (0..self.voices.len()).find(|&voice| {
+                self.voices[voice].event_id.is_none()
+                    && self.shared.voices[voice].state.load(Ordering::Acquire) == VOICE_IDLE
+            }) else {
+                break;
+            };
+
+            // Events that already started are resumed from where they should be.
+            let shared = &self.shared.voices[voice];
+            shared
+                .start_offset
+                .store(s.saturating_sub(start), Ordering::Relaxed);
+            shared.state.store(VOICE_REQUESTED, Ordering::Release);
+            self.wake_decoder();
+
+            self.voices[voice] = Voice {
+                event_id: Some(self.event_ids[self.next_event]),
+                start: start.max(s),
+                unreported_start: (start >= self.seek_position)
+                    .then_some(start / self.channels as SampleType),
+                parameters: self.event_parameters[self.next_event],
+                underrun: 0,
+            };
+
+            self.next_event += 1;
+        }
+    }
+}
+
+impl Iterator for StreamingSourceScheduler {
+    type Item = Sample;
+
+    #[inline]
+    #[nonblocking]
+    #[cfg_attr(
+        feature = "profiler",
+        instrument(name = "StreamingSourceScheduler::next")
+    )]
+    fn next(&mut self) -> Option<Sample> {
+        // Cache the sample index for this sample
+        let s = self.samples_counted;
+
+        // Set the sample index for the next sample
+        self.samples_counted += 1;
+
+        self.assign_voices(s);
+
+        let channel = (s % self.channels as SampleType) as u16;
+        let mut output = None;
+
+        for voice in 0..self.voices.len() {
+            let playing = self.voices[voice];
+            let Some(id) = playing.event_id else {
+                continue;
+            };
+            if playing.start > s {
+                continue;
+            }
+
+            if let Some(frame) = playing.unreported_start {
+                self.voices[voice].unreported_start = None;
+                self.notify(EventNotification::EventStarted {
+                    id,
+                    source_id: id.source_id(),
+                    frame,
+                });
+            }
+
+            let shared = &self.shared.voices[voice];
+
+            // Skip the samples we missed while the buffer was empty.
+            let mut sample = shared.ring.pop();
+            while sample.is_some() && self.voices[voice].underrun > 0 {
+                self.voices[voice].underrun -= 1;
+                sample = shared.ring.pop();
+            }
+
+            match sample {
+                Some(sample) => {
+                    let gain = playing.parameters.channel_gain(channel, self.channels);
+
+                    output = Some(output.unwrap_or(0.0) + sample * gain);
+                }
+                // The decoder sets the finished flag after pushing its last sample, so the buffer
+                // must be checked again to avoid missing it.
+                None if shared.finished.load(Ordering::Acquire) => match shared.ring.pop() {
+                    Some(sample) if self.voices[voice].underrun == 0 => {
+                        let gain = playing.parameters.channel_gain(channel, self.channels);
+
+                        output = Some(output.unwrap_or(0.0) + sample * gain);
+                    }
+                    _ => self.stop_voice(voice, s),
+                },
+                None => {
+                    self.voices[voice].underrun += 1;
+                    output = Some(output.unwrap_or(0.0));
+                }
+            }
+        }
+
+        let gain = self.strip.gain_at(s);
+
+        output.map(|sample| sample * gain)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+impl Source for StreamingSourceScheduler {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = self.channels as SampleType;
+
+        // Keep the current channel, so the next sample is still for the channel the output expects.
+        let channel = self.samples_counted % channels;
+        let frame = crate::time::duration_to_frames(pos, self.sample_rate);
+
+        self.set_position(frame.saturating_mul(channels).saturating_add(channel));
+
+        Ok(())
+    }
+}
+
+impl Drop for StreamingSourceScheduler {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+
+        if let Some(decoder) = self.decoder.take() {
+            decoder.thread().unpark();
+            let _ = decoder.join();
+        }
+    }
+}
+
+/// The background thread that decodes the source into the buffer of every active voice.
+///
+/// The thread parks itself while no voice is streaming, and is unparked by the audio thread
+/// whenever a voice is requested or released.
+fn decode_voices(
+    shared: Arc<StreamShared>,
+    factory: SourceFactory,
+    sample_rate: u32,
+    channels: u16,
+) {
+    let mut decoders: Vec<Option<Box<dyn Source + Send>>> =
+        (0..shared.voices.len()).map(|_| None).collect();
+    let mut decoded: Vec<SampleType> = vec![0; shared.voices.len()];
+
+    // Probe the source once, so we know how long events play for when seeking.
+    let source = factory();
+    let mut probe = match source.total_duration() {
+        Some(duration) => {
+            let frames = crate::time::duration_to_frames(duration, sample_rate);
+            shared
+                .source_length
+                .store(frames * channels as SampleType, Ordering::Relaxed);
+
+            None
+        }
+        None => Some((source, 0)),
+    };
+
+    while !shared.shutdown.load(Ordering::Acquire) {
+        let mut streaming = false;
+
+        // Sources that don't know their duration are counted a chunk at a time, between two
+        // refills of the voice buffers.
+        let probing = match probe.as_mut() {
+            // A voice that reached the end of the source found its length first.
+            Some(_) if shared.source_length.load(Ordering::Relaxed) != SampleType::MAX => false,
+            Some((source, length)) => {
+                let chunk = source.by_ref().take(PROBE_CHUNK).count();
+                *length += chunk as SampleType;
+
+                if chunk < PROBE_CHUNK {
+                    shared.source_length.store(*length, Ordering::Relaxed);
+                }
+
+                chunk == PROBE_CHUNK
+            }
+            None => false,
+        };
+        if !probing {
+            probe = None;
+        }
+
+        for (voice, (decoder, decoded)) in shared
+            .voices
+            .iter()
+            .zip(decoders.iter_mut().zip(decoded.iter_mut()))
+        {
+            match voice.state.load(Ordering::Acquire) {
+                VOICE_REQUESTED => {
+                    let mut source = factory();
+
+                    let offset = voice.start_offset.load(Ordering::Relaxed);
+                    if offset > 0 {
+                        skip_samples(&mut source, offset, sample_rate, channels);
+                    }
+
+                    *decoder = Some(source);
+                    *decoded = offset;
+
+                    // The audio thread may have released the voice in the meantime.
+                    let _ = voice.state.compare_exchange(
+                        VOICE_REQUESTED,
+                        VOICE_STREAMING,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    );
+                    streaming = true;
+                }
+                VOICE_STREAMING => {
+                    let Some(source) = decoder else {
+                        continue;
+                    };
+                    streaming = true;
+
+                    for _ in 0..voice.ring.free() {
+                        match source.next() {
+                            Some(sample) => {
+                                voice.ring.push(sample);
+                                *decoded += 1;
+                            }
+                            None => {
+                                shared.source_length.store(*decoded, Ordering::Relaxed);
+                                voice.finished.store(true, Ordering::Release);
+                                *decoder = None;
+
+                                break;
+                            }
+                        }
+                    }
+                }
+                VOICE_RELEASED => {
+                    *decoder = None;
+                    voice.ring.reset();
+                    voice.finished.store(false, Ordering::Relaxed);
+                    voice.state.store(VOICE_IDLE, Ordering::Release);
+                }
+                _ => {}
+            }
+        }
+
+        // The probe keeps the thread busy on its own until it is done.
+        if probing {
+            continue;
+        }
+
+        // A request or release that came in since the voices were checked unparks the thread
+        // right away, so it is never missed.
+        if streaming {
+            thread::sleep(DECODER_POLL_INTERVAL);
+        } else {
+            thread::park();
+        }
+    }
+}
+
+/// Skips the first `offset` samples of a source, seeking if the source supports it.
+fn skip_samples(
+    source: &mut Box<dyn Source + Send>,
+    offset: SampleType,
+    sample_rate: u32,
+    channels: u16,
+) {
+    let channels = channels as SampleType;
+    let frames = offset / channels;
+
+    // Round up, so sources that truncate the position still land on the right frame.
+    let nanos = (frames as u128 * 1_000_000_000).div_ceil(sample_rate as u128);
+    let position = Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX));
+
+    let remaining = match source.try_seek(position) {
+        // Seeking is done in whole frames, so only the channel offset is left to skip.
+        Ok(()) => offset % channels,
+        Err(_) => offset,
+    };
+
+    for _ in 0..remaining {
+        if source.next().is_none() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! This module provides the channel strip of a scheduled source, which sets how loud it plays.
+//!
+//! Every change to the volume, mute or solo of a source is ramped over [`GAIN_RAMP_DURATION`]
+//! instead of applied at once, since jumping to a new gain in the middle of a sound clicks.
+
+use std::time::Duration;
+
+use crate::{SampleType, time};
+
+/// How long a change to the gain of a source takes to be fully applied.
+pub const GAIN_RAMP_DURATION: Duration = Duration::from_millis(5);
+
+/// The volume, mute and solo of a source, and the gain ramp between their settings.
+///
+/// The ramp is stored as the sample it started on instead of a running gain, so the gain of any
+/// sample can be computed again after the owner rewinds or renders ahead.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ChannelStrip {
+    volume: f32,
+    muted: bool,
+    soloed: bool,
+    /// Whether another source is soloed, which silences this one.
+    silenced: bool,
+    /// The length of a ramp, in samples.
+    ramp_length: SampleType,
+    /// The sample the last ramp started on, and the gain it started from.
+    ramp_start: SampleType,
+    ramp_from: f32,
+}
+
+impl ChannelStrip {
+    pub(crate) fn new(sample_rate: u32, channels: u16) -> ChannelStrip {
+        let ramp_frames = time::duration_to_frames(GAIN_RAMP_DURATION, sample_rate);
+
+        ChannelStrip {
+            volume: 1.0,
+            muted: false,
+            soloed: false,
+            silenced: false,
+            ramp_length: (ramp_frames * channels.max(1) as SampleType).max(1),
+            ramp_start: 0,
+            ramp_from: 1.0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    #[inline]
+    pub(crate) fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    #[inline]
+    pub(crate) fn is_soloed(&self) -> bool {
+        self.soloed
+    }
+
+    /// Sets the volume, ramping to it from the given sample. Negative volumes are treated as
+    /// silence.
+    #[inline]
+    pub(crate) fn set_volume(&mut self, volume: f32, sample: SampleType) {
+        let volume = volume.max(0.0);
+        if volume != self.volume {
+            self.change(sample, |strip| strip.volume = volume);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_muted(&mut self, muted: bool, sample: SampleType) {
+        if muted != self.muted {
+            self.change(sample, |strip| strip.muted = muted);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_soloed(&mut self, soloed: bool, sample: SampleType) {
+        if soloed != self.soloed {
+            self.change(sample, |strip| strip.soloed = soloed);
+        }
+    }
+
+    /// Silences the strip because another one is soloed, or brings it back.
+    #[inline]
+    pub(crate) fn set_silenced(&mut self, silenced: bool, sample: SampleType) {
+        if silenced != self.silenced {
+            self.change(sample, |strip| strip.silenced = silenced);
+        }
+    }
+
+    /// Applies a change to the settings, and starts a ramp from the gain of the given sample to
+    /// the new one.
+    #[inline]
+    fn change(&mut self, sample: SampleType, apply: impl FnOnce(&mut ChannelStrip)) {
+        let gain = self.gain_at(sample);
+
+        apply(self);
+
+        self.ramp_start = sample;
+        self.ramp_from = gain;
+    }
+
+    /// Returns `true` if the settings ramp to silence.
+    #[inline]
+    pub(crate) fn is_silent(&self) -> bool {
+        self.target() == 0.0
+    }
+
+    /// Returns the gain the settings ramp to.
+    #[inline]
+    fn target(&self) -> f32 {
+        if self.muted || self.silenced {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+
+    /// Returns the gain of the given sample.
+    ///
+    /// Samples before the last ramp, which are only played again after seeking back, use the
+    /// current settings.
+    #[inline]
+    pub(crate) fn gain_at(&self, sample: SampleType) -> f32 {
+        let target = self.target();
+        let elapsed = sample.wrapping_sub(self.ramp_start);
+
+        if sample < self.ramp_start || elapsed >= self.ramp_length {
+            return target;
+        }
+
+        self.ramp_from + (target - self.ramp_from) * (elapsed as f32 / self.ramp_length as f32)
+    }
+
+    /// Returns the first sample from the given one on which the gain stops changing.
+    #[inline]
+    pub(crate) fn ramp_end_after(&self, sample: SampleType) -> SampleType {
+        let end = self.ramp_start.saturating_add(self.ramp_length);
+
+        if sample < self.ramp_start || self.ramp_from == self.target() {
+            sample
+        } else {
+            end.max(sample)
+        }
+    }
+}
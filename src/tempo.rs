@@ -0,0 +1,449 @@
+//! This module provides a tempo map, used to schedule events in beats instead of frames.
+//!
+//! A [`TempoMap`] is a list of tempo changes, each of which either holds its tempo until the next
+//! change, or ramps linearly towards it. Beats are quarter notes, and can be subdivided into
+//! ticks for charts and MIDI-like data. The map also tracks time signature changes, so positions
+//! can be expressed in bars.
+//!
+//! Conversions to and from frames round to the nearest frame. The frame of every tempo change is
+//! converted back to the exact beat of the change, so events scheduled on a tempo change always
+//! land on the same frame.
+
+use crate::time::seconds_to_frames;
+use crate::{PlaybackEvent, SampleType, SourceId};
+
+/// The default number of ticks per beat.
+pub const DEFAULT_PPQ: u32 = 960;
+
+/// How the tempo changes between a tempo change and the next one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TempoCurve {
+    /// The tempo is held until the next change.
+    #[default]
+    Constant,
+
+    /// The tempo ramps linearly, in beats, towards the tempo of the next change. If there is no
+    /// next change, the tempo is held.
+    Linear,
+}
+
+/// A time signature, such as 4/4 or 6/8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    /// The number of notes in a bar.
+    pub numerator: u32,
+
+    /// The note value of each note in a bar, where 4 is a quarter note and 8 is an eighth note.
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    /// Returns the length of a bar, in beats.
+    #[inline]
+    pub fn bar_length(&self) -> f64 {
+        self.numerator as f64 * self.note_length()
+    }
+
+    /// Returns the length of a single note of the time signature, in beats.
+    #[inline]
+    pub fn note_length(&self) -> f64 {
+        4.0 / self.denominator as f64
+    }
+}
+
+impl Default for TimeSignature {
+    #[inline]
+    fn default() -> TimeSignature {
+        TimeSignature {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+/// A repeat configuration measured in ticks of a [`TempoMap`], used by
+/// [`Scheduler::schedule_beat`](crate::Scheduler::schedule_beat).
+///
+/// Unlike [`PlaybackEvent::repeat`], which spaces repetitions in frames, the repetitions are
+/// spaced in ticks, so they follow the tempo changes of the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BeatRepeat {
+    /// The number of ticks between two repetitions.
+    pub ticks: SampleType,
+
+    /// The number of times the event is repeated, after the original event.
+    pub count: SampleType,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TempoPoint {
+    beat: f64,
+    bpm: f64,
+    curve: TempoCurve,
+    /// The time at which this tempo change happens, in seconds.
+    seconds: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TimeSignatureChange {
+    bar: u32,
+    signature: TimeSignature,
+    /// The beat at which this time signature change happens.
+    beat: f64,
+}
+
+/// A map of the tempo and time signature changes of a song.
+///
+/// # Example
+///
+/// ```
+/// use rodio_scheduler::tempo::{TempoCurve, TempoMap};
+///
+/// let mut tempo_map = TempoMap::new(120.0);
+///
+/// // Speed up from 120 to 180 BPM between beats 8 and 16, and stay there.
+/// tempo_map.set_tempo(8.0, 120.0, TempoCurve::Linear);
+/// tempo_map.set_tempo(16.0, 180.0, TempoCurve::Constant);
+///
+/// // Beat 8 is 4 seconds in.
+/// assert_eq!(tempo_map.beat_to_frame(8.0, 48000), 4 * 48000);
+/// assert_eq!(tempo_map.frame_to_beat(4 * 48000, 48000), 8.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct TempoMap {
+    /// Tempo changes, sorted by beat. The first one is always at beat 0.
+    points: Vec<TempoPoint>,
+
+    /// Time signature changes, sorted by bar. The first one is always at bar 0.
+    time_signatures: Vec<TimeSignatureChange>,
+
+    /// The number of ticks per beat.
+    ppq: u32,
+}
+
+impl TempoMap {
+    /// Creates a new `TempoMap` with a constant tempo, a 4/4 time signature, and the default
+    /// number of ticks per beat.
+    ///
+    /// # Arguments
+    ///
+    /// * `bpm`: The initial tempo, in beats per minute.
+    #[inline]
+    pub fn new(bpm: f64) -> TempoMap {
+        TempoMap::with_ppq(bpm, DEFAULT_PPQ)
+    }
+
+    /// Creates a new `TempoMap` with a constant tempo, a 4/4 time signature, and a specified
+    /// number of ticks per beat.
+    ///
+    /// # Arguments
+    ///
+    /// * `bpm`: The initial tempo, in beats per minute.
+    /// * `ppq`: The number of ticks per beat.
+    #[inline]
+    pub fn with_ppq(bpm: f64, ppq: u32) -> TempoMap {
+        let bpm = if is_valid_tempo(bpm) { bpm } else { 120.0 };
+
+        TempoMap {
+            points: vec![TempoPoint {
+                beat: 0.0,
+                bpm,
+                curve: TempoCurve::Constant,
+                seconds: 0.0,
+            }],
+            time_signatures: vec![TimeSignatureChange {
+                bar: 0,
+                signature: TimeSignature::default(),
+                beat: 0.0,
+            }],
+            ppq: ppq.max(1),
+        }
+    }
+
+    /// Returns the number of ticks per beat.
+    #[inline]
+    pub fn ppq(&self) -> u32 {
+        self.ppq
+    }
+
+    /// Sets the tempo at a beat, replacing any tempo change already on that beat.
+    ///
+    /// Tempos that are not positive, and negative beats, are ignored.
+    pub fn set_tempo(&mut self, beat: f64, bpm: f64, curve: TempoCurve) {
+        if !is_valid_tempo(bpm) || !(beat >= 0.0 && beat.is_finite()) {
+            return;
+        }
+
+        let point = TempoPoint {
+            beat,
+            bpm,
+            curve,
+            seconds: 0.0,
+        };
+
+        let index = self.points.partition_point(|point| point.beat < beat);
+        match self.points.get_mut(index) {
+            Some(existing) if existing.beat == beat => *existing = point,
+            _ => self.points.insert(index, point),
+        }
+
+        // Every tempo change after this one has moved.
+        for index in index.max(1)..self.points.len() {
+            let previous = &self.points[index - 1];
+            let seconds = previous.seconds
+                + self.segment_seconds(index - 1, self.points[index].beat - previous.beat);
+
+            self.points[index].seconds = seconds;
+        }
+    }
+
+    /// Sets the time signature from a bar onwards, replacing any time signature change already
+    /// on that bar.
+    ///
+    /// Time signatures with a numerator or denominator of 0 are ignored.
+    pub fn set_time_signature(&mut self, bar: u32, signature: TimeSignature) {
+        if signature.numerator == 0 || signature.denominator == 0 {
+            return;
+        }
+
+        let change = TimeSignatureChange {
+            bar,
+            signature,
+            beat: 0.0,
+        };
+
+        let index = self
+            .time_signatures
+            .partition_point(|change| change.bar < bar);
+        match self.time_signatures.get_mut(index) {
+            Some(existing) if existing.bar == bar => *existing = change,
+            _ => self.time_signatures.insert(index, change),
+        }
+
+        for index in index.max(1)..self.time_signatures.len() {
+            let previous = self.time_signatures[index - 1];
+            let bars = self.time_signatures[index].bar - previous.bar;
+
+            self.time_signatures[index].beat =
+                previous.beat + bars as f64 * previous.signature.bar_length();
+        }
+    }
+
+    /// Returns the tempo at a beat, in beats per minute.
+    #[inline]
+    pub fn tempo_at(&self, beat: f64) -> f64 {
+        let index = self.point_at_beat(beat);
+        let point = &self.points[index];
+
+        point.bpm + self.segment_slope(index) * (beat.max(0.0) - point.beat)
+    }
+
+    /// Returns the time signature at a beat.
+    #[inline]
+    pub fn time_signature_at(&self, beat: f64) -> TimeSignature {
+        let index = self
+            .time_signatures
+            .partition_point(|change| change.beat <= beat)
+            .max(1);
+
+        self.time_signatures[index - 1].signature
+    }
+
+    /// Converts a beat to the time it happens at, in seconds.
+    ///
+    /// Negative beats are converted to 0 seconds.
+    #[inline]
+    pub fn beat_to_seconds(&self, beat: f64) -> f64 {
+        let index = self.point_at_beat(beat);
+        let point = &self.points[index];
+
+        point.seconds + self.segment_seconds(index, beat.max(0.0) - point.beat)
+    }
+
+    /// Converts a time, in seconds, to the beat that happens at that time.
+    ///
+    /// Negative times are converted to beat 0.
+    #[inline]
+    pub fn seconds_to_beat(&self, seconds: f64) -> f64 {
+        let index = self
+            .points
+            .partition_point(|point| point.seconds <= seconds)
+            .max(1)
+            - 1;
+        let point = &self.points[index];
+
+        point.beat + self.segment_beats(index, seconds.max(0.0) - point.seconds)
+    }
+
+    /// Converts a beat to a frame at the given sample rate, rounding to the nearest frame.
+    ///
+    /// Negative beats are converted to frame 0.
+    #[inline]
+    pub fn beat_to_frame(&self, beat: f64, sample_rate: u32) -> SampleType {
+        seconds_to_frames(self.beat_to_seconds(beat), sample_rate)
+    }
+
+    /// Converts a frame at the given sample rate to a beat.
+    ///
+    /// The frames of tempo changes are converted to the exact beat of the change.
+    #[inline]
+    pub fn frame_to_beat(&self, frame: SampleType, sample_rate: u32) -> f64 {
+        let index = self
+            .points
+            .partition_point(|point| seconds_to_frames(point.seconds, sample_rate) <= frame)
+            .max(1)
+            - 1;
+        let point = &self.points[index];
+
+        if seconds_to_frames(point.seconds, sample_rate) == frame {
+            return point.beat;
+        }
+
+        let seconds = frame as f64 / sample_rate as f64;
+
+        point.beat + self.segment_beats(index, seconds - point.seconds)
+    }
+
+    /// Converts a number of ticks to beats.
+    #[inline]
+    pub fn ticks_to_beats(&self, ticks: SampleType) -> f64 {
+        ticks as f64 / self.ppq as f64
+    }
+
+    /// Converts beats to the nearest number of ticks.
+    ///
+    /// Negative beats are converted to tick 0.
+    #[inline]
+    pub fn beats_to_ticks(&self, beats: f64) -> SampleType {
+        // Float to integer casts saturate, and convert NaN to 0.
+        (beats * self.ppq as f64).round() as SampleType
+    }
+
+    /// Converts a tick to a frame at the given sample rate, rounding to the nearest frame.
+    #[inline]
+    pub fn tick_to_frame(&self, tick: SampleType, sample_rate: u32) -> SampleType {
+        self.beat_to_frame(self.ticks_to_beats(tick), sample_rate)
+    }
+
+    /// Converts a frame at the given sample rate to the nearest tick.
+    #[inline]
+    pub fn frame_to_tick(&self, frame: SampleType, sample_rate: u32) -> SampleType {
+        self.beats_to_ticks(self.frame_to_beat(frame, sample_rate))
+    }
+
+    /// Converts a position in bars to a beat.
+    ///
+    /// `note` is the position inside the bar, in notes of the time signature of the bar, so note
+    /// 1.5 of a bar in 6/8 is three sixteenth notes in.
+    #[inline]
+    pub fn bar_to_beat(&self, bar: u32, note: f64) -> f64 {
+        let index = self
+            .time_signatures
+            .partition_point(|change| change.bar <= bar)
+            .max(1)
+            - 1;
+        let change = &self.time_signatures[index];
+
+        change.beat
+            + (bar - change.bar) as f64 * change.signature.bar_length()
+            + note * change.signature.note_length()
+    }
+
+    /// Converts a beat to a position in bars, returning the bar and the position inside it in
+    /// notes of its time signature.
+    ///
+    /// Negative beats are converted to the start of bar 0.
+    #[inline]
+    pub fn beat_to_bar(&self, beat: f64) -> (u32, f64) {
+        let beat = beat.max(0.0);
+        let index = self
+            .time_signatures
+            .partition_point(|change| change.beat <= beat)
+            .max(1)
+            - 1;
+        let change = &self.time_signatures[index];
+
+        let beats = beat - change.beat;
+        let bar_length = change.signature.bar_length();
+        let bars = (beats / bar_length).floor();
+
+        (
+            change.bar.saturating_add(bars as u32),
+            (beats - bars * bar_length) / change.signature.note_length(),
+        )
+    }
+
+    /// Creates an event that plays a source on the given beat, at the given sample rate.
+    #[inline]
+    pub fn event_at_beat(
+        &self,
+        source_id: impl Into<SourceId>,
+        beat: f64,
+        sample_rate: u32,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_frame(source_id, self.beat_to_frame(beat, sample_rate))
+    }
+
+    /// Creates an event that plays a source on the given tick, at the given sample rate.
+    #[inline]
+    pub fn event_at_tick(
+        &self,
+        source_id: impl Into<SourceId>,
+        tick: SampleType,
+        sample_rate: u32,
+    ) -> PlaybackEvent {
+        PlaybackEvent::at_frame(source_id, self.tick_to_frame(tick, sample_rate))
+    }
+
+    /// Returns the index of the tempo change in effect at a beat.
+    #[inline]
+    fn point_at_beat(&self, beat: f64) -> usize {
+        self.points
+            .partition_point(|point| point.beat <= beat)
+            .max(1)
+            - 1
+    }
+
+    /// Returns how much the tempo changes per beat after a tempo change.
+    #[inline]
+    fn segment_slope(&self, index: usize) -> f64 {
+        let point = &self.points[index];
+
+        match (point.curve, self.points.get(index + 1)) {
+            (TempoCurve::Linear, Some(next)) => (next.bpm - point.bpm) / (next.beat - point.beat),
+            _ => 0.0,
+        }
+    }
+
+    /// Returns the number of seconds it takes to play `beats` beats after a tempo change.
+    #[inline]
+    fn segment_seconds(&self, index: usize, beats: f64) -> f64 {
+        let bpm = self.points[index].bpm;
+        let slope = self.segment_slope(index);
+
+        if slope == 0.0 {
+            return beats * 60.0 / bpm;
+        }
+
+        // The integral of 60 / (bpm + slope * beat) over the beats played.
+        60.0 / slope * ((bpm + slope * beats) / bpm).ln()
+    }
+
+    /// Returns the number of beats played in `seconds` seconds after a tempo change.
+    #[inline]
+    fn segment_beats(&self, index: usize, seconds: f64) -> f64 {
+        let bpm = self.points[index].bpm;
+        let slope = self.segment_slope(index);
+
+        if slope == 0.0 {
+            return seconds * bpm / 60.0;
+        }
+
+        bpm * ((slope * seconds / 60.0).exp() - 1.0) / slope
+    }
+}
+
+#[inline]
+fn is_valid_tempo(bpm: f64) -> bool {
+    bpm.is_finite() && bpm > 0.0
+}
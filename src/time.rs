@@ -0,0 +1,48 @@
+//! This module provides conversions between time units and frames.
+//!
+//! A frame holds one sample for every channel, so a frame index is independent of the channel
+//! count. Timestamps in this crate are measured in frames. Every conversion rounds to the nearest
+//! frame, so a position converted back and forth always lands on the same frame.
+
+use std::time::Duration;
+
+use crate::SampleType;
+
+/// Converts a duration to a number of frames at the given sample rate, rounding to the nearest
+/// frame.
+#[inline]
+pub fn duration_to_frames(duration: Duration, sample_rate: u32) -> SampleType {
+    let frames = (duration.as_nanos() * sample_rate as u128 + 500_000_000) / 1_000_000_000;
+
+    frames.try_into().unwrap_or(SampleType::MAX)
+}
+
+/// Converts a number of seconds to a number of frames at the given sample rate, rounding to the
+/// nearest frame.
+///
+/// Negative and NaN values are converted to frame 0.
+#[inline]
+pub fn seconds_to_frames(seconds: f64, sample_rate: u32) -> SampleType {
+    // Float to integer casts saturate, and convert NaN to 0.
+    (seconds * sample_rate as f64).round() as SampleType
+}
+
+/// Converts a beat index at a constant tempo to a number of frames at the given sample rate,
+/// rounding to the nearest frame.
+///
+/// Beat 0 is at frame 0, and beats can be fractional, so `0.5` is the eighth note after the first
+/// beat.
+#[inline]
+pub fn beats_to_frames(bpm: f64, beat: f64, sample_rate: u32) -> SampleType {
+    (beat * 60.0 * sample_rate as f64 / bpm).round() as SampleType
+}
+
+/// Converts a number of frames at the given sample rate to a duration, rounding to the nearest
+/// nanosecond.
+#[inline]
+pub fn frames_to_duration(frames: SampleType, sample_rate: u32) -> Duration {
+    let sample_rate = sample_rate as u128;
+    let nanos = (frames as u128 * 1_000_000_000 + sample_rate / 2) / sample_rate;
+
+    Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+}
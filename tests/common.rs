@@ -1,6 +1,23 @@
+#![allow(dead_code)]
+
+use rodio::buffer::SamplesBuffer;
 use rodio::source::Source;
 use std::time::Duration;
 
+/// Creates a source that plays `value` on every sample for `duration` samples per channel.
+pub fn constant_source(
+    sample_rate: u32,
+    channels: u16,
+    duration: u64,
+    value: f32,
+) -> SamplesBuffer {
+    SamplesBuffer::new(
+        channels,
+        sample_rate,
+        vec![value; duration as usize * channels as usize],
+    )
+}
+
 #[derive(Clone)]
 pub struct DummySource {
     sample_rate: u32,
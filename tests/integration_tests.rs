@@ -1,6 +1,15 @@
 mod common;
 
-use rodio_scheduler::{PlaybackEvent, SingleSourceScheduler};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio_scheduler::tempo::TempoCurve;
+use rodio_scheduler::{
+    AutomationCurve, BeatRepeat, Ducking, DuckingKey, EndPolicy, EventId, EventNotification,
+    HandleError, LateEvent, LateEventPolicy, MasterStage, PlaybackEvent, PlaybackState,
+    SampleBuffer, Scheduler, SchedulerError, SingleSourceScheduler, SoftClipCurve, SourceId,
+    StreamingSourceScheduler, TempoMap, VoiceStealPolicy, ducking, master,
+};
 
 #[test]
 fn test_single_source_scheduler_basic_playback() {
@@ -15,11 +24,12 @@ fn test_single_source_scheduler_basic_playback() {
 
     // Schedule an event to play at 0.5 seconds
     let event = PlaybackEvent {
-        source_id: 0, // This is ignored for SingleSourceScheduler
+        source_id: 0.into(), // This is ignored for SingleSourceScheduler
         timestamp: scheduled_time,
         repeat: None,
+        ..Default::default()
     };
-    scheduler.schedule_event(event);
+    scheduler.schedule_event(event).unwrap();
 
     // Consume samples and check if the scheduled sound plays
     let mut samples_played = 0;
@@ -84,3 +94,2879 @@ fn test_single_source_scheduler_basic_playback() {
         "An incorrect number of samples was played (Expected {expected_sample_count}, found {samples_played})."
     );
 }
+
+/// Consumes `frames` frames from the scheduler and returns the first channel of each frame.
+fn collect_frames(
+    scheduler: &mut impl Iterator<Item = f32>,
+    channels: u16,
+    frames: u64,
+) -> Vec<f32> {
+    let mut output = Vec::with_capacity(frames as usize);
+
+    for _ in 0..frames {
+        // The scheduler returns None while nothing is playing, so we can't rely on take() here.
+        let first_channel = scheduler.next().unwrap_or(0.0);
+        for _ in 1..channels {
+            scheduler.next();
+        }
+
+        output.push(first_channel);
+    }
+
+    output
+}
+
+#[test]
+fn test_single_source_scheduler_repeat_count() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+    let value = 0.5f32;
+
+    let dummy_source = common::DummySource::new(sample_rate, channels, 10, value);
+    let mut scheduler = SingleSourceScheduler::new(dummy_source, sample_rate, channels);
+
+    let event = PlaybackEvent {
+        source_id: 0.into(),
+        timestamp: 100,
+        repeat: Some((50, 3)),
+        ..Default::default()
+    };
+    scheduler.schedule_event(event).unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 400);
+    let hits: Vec<usize> = frames
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| **sample != 0.0)
+        .map(|(frame, _)| frame)
+        .collect();
+
+    // The original event plus 3 repetitions.
+    assert_eq!(hits, vec![100, 150, 200, 250]);
+    assert!(hits.iter().all(|&frame| frames[frame] == value));
+}
+
+#[test]
+fn test_single_source_scheduler_repeat_spacing_with_long_source() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+    let value = 0.25f32;
+
+    // The source is longer than the beat, so every repetition overlaps the previous ones.
+    let dummy_source = common::DummySource::new(sample_rate, channels, 1000, value);
+    let mut scheduler = SingleSourceScheduler::new(dummy_source, sample_rate, channels);
+
+    let event = PlaybackEvent {
+        source_id: 0.into(),
+        timestamp: 7,
+        repeat: Some((13, 5)),
+        ..Default::default()
+    };
+    scheduler.schedule_event(event).unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 200);
+    let hits: Vec<usize> = frames
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| **sample != 0.0)
+        .map(|(frame, _)| frame)
+        .collect();
+
+    assert_eq!(hits, vec![7, 20, 33, 46, 59, 72]);
+    assert!(
+        hits.windows(2).all(|pair| pair[1] - pair[0] == 13),
+        "Repetitions were not evenly spaced: {hits:?}"
+    );
+}
+
+#[test]
+fn test_single_source_scheduler_repeat_overlaps_other_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+    let value = 0.25f32;
+
+    let dummy_source = common::DummySource::new(sample_rate, channels, 100, value);
+    let mut scheduler = SingleSourceScheduler::new(dummy_source, sample_rate, channels);
+
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 0,
+            repeat: Some((20, 3)),
+            ..Default::default()
+        })
+        .unwrap();
+
+    // One event coincides with a repetition, and the other one lands between two of them.
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 40,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 50,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+
+    assert_eq!(frames[0], value);
+    assert_eq!(frames[20], value);
+    assert_eq!(frames[40], 2.0 * value);
+    assert_eq!(frames[50], value);
+    assert_eq!(frames[60], value);
+    assert_eq!(frames.iter().filter(|sample| **sample != 0.0).count(), 5);
+}
+
+/// Returns the frames in which a sample was played.
+fn find_hits(frames: &[f32]) -> Vec<usize> {
+    frames
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| **sample != 0.0)
+        .map(|(frame, _)| frame)
+        .collect()
+}
+
+/// Creates a stereo `Scheduler` at 48 kHz with a constant input of `input.0` frames at `input.1`,
+/// and every `(source, timestamps)` pair added as a source scheduled on each of its timestamps.
+///
+/// The sources are added in order, so their identifiers are their indices.
+fn scheduler_with(
+    input: (u64, f32),
+    sources: impl IntoIterator<Item = (rodio::buffer::SamplesBuffer, Vec<u64>)>,
+) -> Scheduler<rodio::buffer::SamplesBuffer> {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, input.0, input.1);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+    for (source, timestamps) in sources {
+        let source_id = scheduler.add_source(source);
+
+        for timestamp in timestamps {
+            scheduler
+                .schedule(PlaybackEvent::at_frame(source_id, timestamp))
+                .unwrap();
+        }
+    }
+
+    scheduler
+}
+
+#[test]
+fn test_scheduler_handle_schedules_and_cancels_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+    let value = 0.5f32;
+
+    let input = common::DummySource::new(sample_rate, channels, 1000, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 1);
+    let source_id =
+        scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, value));
+
+    let handle = scheduler.handle();
+    handle
+        .schedule_event(PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: 10,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+    let cancelled = handle
+        .schedule_event(PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: 20,
+            repeat: Some((10, 2)),
+            ..Default::default()
+        })
+        .unwrap();
+    handle.cancel_event(cancelled).unwrap();
+    assert_eq!(cancelled.source_id(), source_id);
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert_eq!(find_hits(&frames), vec![10]);
+
+    // Events can be scheduled in the middle of playback, relative to the start of the scheduler.
+    handle
+        .schedule_event(PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: 150,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert_eq!(find_hits(&frames), vec![50]);
+}
+
+#[test]
+fn test_event_ids_refer_to_the_scheduling_source() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::DummySource::new(sample_rate, channels, 1000, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 2);
+    scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, 0.5));
+    let source_id =
+        scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, 0.5));
+    let handle = scheduler.handle();
+
+    // The source id of the event is ignored when it is scheduled on the source directly.
+    let source = scheduler.get_scheduler(source_id).unwrap();
+    assert_eq!(source.source_id(), source_id);
+    let id = source
+        .schedule_event(PlaybackEvent::at_frame(0, 10))
+        .unwrap();
+    assert_eq!(id.source_id(), source_id);
+
+    handle.cancel_event(id).unwrap();
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert!(find_hits(&frames).is_empty());
+}
+
+#[test]
+fn test_scheduler_handle_adds_sources() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::DummySource::new(sample_rate, channels, 1000, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 2);
+    let first_id = scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, 0.25));
+
+    let handle = scheduler.handle();
+    let second_id = handle
+        .add_source(common::DummySource::new(sample_rate, channels, 10, 0.5))
+        .unwrap();
+    assert_ne!(first_id, second_id);
+
+    // The scheduler was created with room for 2 sources only.
+    let error = handle
+        .add_source(common::DummySource::new(sample_rate, channels, 10, 1.0))
+        .unwrap_err();
+    assert_eq!(error, HandleError::SourceCapacityExceeded);
+
+    for (source_id, timestamp) in [(first_id, 5), (second_id, 15), (second_id, 25)] {
+        handle
+            .schedule_event(PlaybackEvent {
+                source_id: source_id.into(),
+                timestamp,
+                repeat: None,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    let frames = collect_frames(&mut scheduler, channels, 50);
+    assert_eq!(find_hits(&frames), vec![5, 15, 25]);
+    assert_eq!(frames[5], 0.25);
+    assert_eq!(frames[15], 0.5);
+
+    // Sources added directly keep their identifiers in sync with the ones added through handles.
+    let third_id = scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, 1.0));
+    assert_eq!(third_id, 2);
+}
+
+#[test]
+fn test_scheduler_handle_clears_schedule() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::DummySource::new(sample_rate, channels, 1000, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, 0.5));
+
+    let handle = scheduler.handle();
+    handle
+        .schedule_event(PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: 0,
+            repeat: Some((10, 10)),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 25);
+    assert_eq!(find_hits(&frames), vec![0, 10, 20]);
+
+    handle.clear_schedule().unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert!(find_hits(&frames).is_empty());
+}
+
+#[test]
+fn test_scheduler_handle_from_another_thread() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::DummySource::new(sample_rate, channels, 1000, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::DummySource::new(sample_rate, channels, 10, 0.5));
+
+    let handle = scheduler.handle();
+    let (sent_tx, sent_rx) = std::sync::mpsc::channel();
+
+    let audio_thread = std::thread::spawn(move || {
+        let mut frames = collect_frames(&mut scheduler, channels, 50);
+
+        // Keep playing once every event has been sent.
+        sent_rx.recv().unwrap();
+        frames.extend(collect_frames(&mut scheduler, channels, 450));
+
+        frames
+    });
+
+    let senders: Vec<_> = [100, 200, 300]
+        .into_iter()
+        .map(|timestamp| {
+            let handle = handle.clone();
+
+            std::thread::spawn(move || {
+                handle
+                    .schedule_event(PlaybackEvent {
+                        source_id: source_id.into(),
+                        timestamp,
+                        repeat: None,
+                        ..Default::default()
+                    })
+                    .unwrap();
+            })
+        })
+        .collect();
+
+    for sender in senders {
+        sender.join().unwrap();
+    }
+    sent_tx.send(()).unwrap();
+
+    let frames = audio_thread.join().unwrap();
+    assert_eq!(find_hits(&frames), vec![100, 200, 300]);
+}
+
+#[test]
+fn test_single_source_scheduler_cancel_event() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+    let value = 0.5f32;
+
+    let dummy_source = common::DummySource::new(sample_rate, channels, 10, value);
+    let mut scheduler = SingleSourceScheduler::new(dummy_source, sample_rate, channels);
+
+    let kept = scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 10,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+    let cancelled = scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 20,
+            repeat: Some((10, 3)),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_ne!(kept, cancelled);
+
+    // The repetitions of another event are interleaved with the cancelled ones.
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 25,
+            repeat: Some((10, 2)),
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert!(scheduler.cancel_event(cancelled));
+    assert!(!scheduler.cancel_event(cancelled));
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert_eq!(find_hits(&frames), vec![10, 25, 35, 45]);
+}
+
+#[test]
+fn test_single_source_scheduler_cancel_sounding_event() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 100, 0.25);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+
+    let first = scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 0,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 50,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 60);
+    assert_eq!(frames[10], 0.25);
+    assert_eq!(frames[55], 0.5);
+
+    // Both events are sounding, cancel the oldest one.
+    assert!(scheduler.cancel_event(first));
+
+    let frames = collect_frames(&mut scheduler, channels, 140);
+    assert!(frames[..90].iter().all(|sample| *sample == 0.25));
+    assert!(frames[90..].iter().all(|sample| *sample == 0.0));
+
+    // The playback window is still usable for new events.
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 250,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert!(frames[..50].iter().all(|sample| *sample == 0.0));
+    assert!(frames[50..].iter().all(|sample| *sample == 0.25));
+}
+
+#[test]
+fn test_single_source_scheduler_cancel_range() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 15, 0.25);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 0,
+            repeat: Some((20, 9)),
+            ..Default::default()
+        })
+        .unwrap();
+
+    // Move into the second repetition, so it is sounding when the range is cancelled.
+    let frames = collect_frames(&mut scheduler, channels, 25);
+    assert_eq!(frames[0], 0.25);
+    assert_eq!(frames[24], 0.25);
+
+    // Cancels the repetitions at 20, 40 and 60.
+    assert_eq!(scheduler.cancel_range(15, 61), 3);
+
+    let frames = collect_frames(&mut scheduler, channels, 175);
+    let playing: Vec<usize> = find_hits(&frames)
+        .into_iter()
+        .map(|frame| frame + 25)
+        .collect();
+    let expected: Vec<usize> = (80..200)
+        .step_by(20)
+        .flat_map(|start| start..start + 15)
+        .collect();
+
+    assert_eq!(playing, expected);
+}
+
+#[test]
+fn test_single_source_scheduler_clear() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 50, 0.25);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 0,
+            repeat: Some((10, 10)),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 30);
+    assert_eq!(frames[25], 0.75);
+
+    scheduler.clear();
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert!(find_hits(&frames).is_empty());
+
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 140,
+            repeat: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 20);
+    assert_eq!(find_hits(&frames), (10..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_single_source_scheduler_event_gain_and_pan() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 10, 0.5);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 0,
+            gain: Some(0.5),
+            ..Default::default()
+        })
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 20,
+            pan: Some(1.0),
+            ..Default::default()
+        })
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 40,
+            gain: Some(2.0),
+            pan: Some(-0.5),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let samples: Vec<f32> = (0..100).map(|_| scheduler.next().unwrap_or(0.0)).collect();
+    let frame = |frame: usize| (samples[frame * 2], samples[frame * 2 + 1]);
+
+    assert_eq!(frame(5), (0.25, 0.25));
+
+    // Panned hard right.
+    assert_eq!(frame(25), (0.0, 0.5));
+
+    // Panned halfway left, after the gain has been applied.
+    assert_eq!(frame(45), (1.0, 0.5));
+}
+
+#[test]
+fn test_single_source_scheduler_event_speed() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 100, 0.25);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 0,
+            speed: Some(2.0),
+            ..Default::default()
+        })
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 100,
+            speed: Some(0.5),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 400);
+    let playing = find_hits(&frames);
+
+    // At twice the speed the source lasts half as long, and at half the speed it lasts twice as long.
+    let expected: Vec<usize> = (0..50).chain(100..300).collect();
+    assert_eq!(playing.len(), expected.len());
+    assert_eq!(playing[..50], expected[..50]);
+
+    // The last frame at half speed fades into silence, since it interpolates past the end of the
+    // source.
+    assert_eq!(playing[50..], expected[50..]);
+    assert!(frames[..50].iter().all(|sample| *sample == 0.25));
+    assert!(frames[100..298].iter().all(|sample| *sample == 0.25));
+}
+
+/// Creates a source that plays the index of each frame divided by 1000, on every channel.
+fn ramp_source(sample_rate: u32, channels: u16, duration: u64) -> rodio::buffer::SamplesBuffer {
+    let samples: Vec<f32> = (0..duration)
+        .flat_map(|frame| std::iter::repeat_n(frame as f32 / 1000.0, channels as usize))
+        .collect();
+
+    rodio::buffer::SamplesBuffer::new(channels, sample_rate, samples)
+}
+
+/// Returns the duration of `frames` frames at `sample_rate`.
+fn frames_to_duration(frames: u64, sample_rate: u32) -> Duration {
+    Duration::from_nanos(frames * 1_000_000_000 / sample_rate as u64)
+}
+
+#[test]
+fn test_scheduler_seek_forward() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let ramp = ramp_source(sample_rate, channels, 100);
+    let mut scheduler = scheduler_with((10 * sample_rate as u64, 0.0), [(ramp, vec![1000, 2000])]);
+
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert!(find_hits(&frames).is_empty());
+
+    // Skip the first event entirely.
+    scheduler
+        .try_seek(frames_to_duration(1500, sample_rate))
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 1000);
+    let playing: Vec<usize> = find_hits(&frames)
+        .iter()
+        .map(|frame| frame + 1500)
+        .collect();
+
+    // The first frame of the ramp is silent.
+    assert_eq!(playing, (2001..2100).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_scheduler_seek_backward() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let ramp = ramp_source(sample_rate, channels, 100);
+    let mut scheduler = scheduler_with((10 * sample_rate as u64, 0.0), [(ramp, vec![1000, 2000])]);
+
+    let frames = collect_frames(&mut scheduler, channels, 2500);
+    assert_eq!(find_hits(&frames).len(), 2 * 99);
+
+    scheduler
+        .try_seek(frames_to_duration(990, sample_rate))
+        .unwrap();
+
+    // Both events play again.
+    let frames = collect_frames(&mut scheduler, channels, 1200);
+    let playing: Vec<usize> = find_hits(&frames).iter().map(|frame| frame + 990).collect();
+    let expected: Vec<usize> = (1001..1100).chain(2001..2100).collect();
+
+    assert_eq!(playing, expected);
+}
+
+#[test]
+fn test_scheduler_seek_into_event() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let ramp = ramp_source(sample_rate, channels, 100);
+    let mut scheduler = scheduler_with((10 * sample_rate as u64, 0.0), [(ramp, vec![1000, 2000])]);
+
+    scheduler
+        .try_seek(frames_to_duration(1037, sample_rate))
+        .unwrap();
+
+    // The event resumes from the sample it would be playing at this point.
+    let samples: Vec<f32> = (0..4).map(|_| scheduler.next().unwrap()).collect();
+    assert_eq!(samples, vec![0.037, 0.037, 0.038, 0.038]);
+
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    let playing: Vec<usize> = find_hits(&frames)
+        .iter()
+        .map(|frame| frame + 1039)
+        .collect();
+    assert_eq!(playing, (1039..1100).collect::<Vec<_>>());
+
+    // Seeking backwards into the middle of an event that already finished plays it again.
+    scheduler
+        .try_seek(frames_to_duration(1090, sample_rate))
+        .unwrap();
+    assert_eq!(scheduler.next(), Some(0.09));
+}
+
+#[test]
+fn test_scheduler_seek_keeps_handle_sources_in_sync() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 1);
+    let handle = scheduler.handle();
+
+    let frames = collect_frames(&mut scheduler, channels, 500);
+    assert!(find_hits(&frames).is_empty());
+
+    // This source is added halfway through playback, but its events still use the scheduler's clock.
+    let source_id = handle
+        .add_source(ramp_source(sample_rate, channels, 100))
+        .unwrap();
+    handle
+        .schedule_event(PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: 1000,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 1000);
+    let playing: Vec<usize> = find_hits(&frames).iter().map(|frame| frame + 500).collect();
+    assert_eq!(playing, (1001..1100).collect::<Vec<_>>());
+
+    scheduler
+        .try_seek(frames_to_duration(1050, sample_rate))
+        .unwrap();
+    assert_eq!(scheduler.next(), Some(0.05));
+}
+
+/// Gives the background thread of a streaming scheduler time to fill its buffers.
+fn wait_for_decoder() {
+    std::thread::sleep(Duration::from_millis(100));
+}
+
+/// Returns the expected output of a ramp source scheduled at `start`, for the frames between
+/// `from` and `to`.
+fn ramp_frames(start: u64, length: u64, from: u64, to: u64) -> Vec<f32> {
+    (from..to)
+        .map(|frame| match frame.checked_sub(start) {
+            Some(offset) if offset < length => offset as f32 / 1000.0,
+            _ => 0.0,
+        })
+        .collect()
+}
+
+#[test]
+fn test_streaming_source_scheduler_plays_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let mut scheduler = StreamingSourceScheduler::new(
+        move || ramp_source(sample_rate, channels, 2000),
+        sample_rate,
+        channels,
+    )
+    .unwrap();
+    for timestamp in [1000, 5000] {
+        scheduler
+            .schedule_event(PlaybackEvent {
+                source_id: 0.into(),
+                timestamp,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    // Both events are assigned a voice ahead of time, so their buffers are ready when they start.
+    let mut frames = collect_frames(&mut scheduler, channels, 500);
+    wait_for_decoder();
+    frames.extend(collect_frames(&mut scheduler, channels, 7500));
+
+    let expected: Vec<f32> = ramp_frames(1000, 2000, 0, 8000)
+        .iter()
+        .zip(ramp_frames(5000, 2000, 0, 8000))
+        .map(|(first, second)| first + second)
+        .collect();
+    assert_eq!(frames, expected);
+}
+
+#[test]
+fn test_streaming_source_scheduler_voice_limit() {
+    let sample_rate = 48000_u32;
+    let channels = 1;
+
+    let mut scheduler = StreamingSourceScheduler::with_voices(
+        move || ramp_source(sample_rate, channels, 2000),
+        sample_rate,
+        channels,
+        1,
+        Duration::from_secs(1),
+    )
+    .unwrap();
+    for timestamp in [1000, 2000] {
+        scheduler
+            .schedule_event(PlaybackEvent {
+                source_id: 0.into(),
+                timestamp,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    let mut frames = collect_frames(&mut scheduler, channels, 500);
+    wait_for_decoder();
+    frames.extend(collect_frames(&mut scheduler, channels, 2501));
+    assert_eq!(frames, ramp_frames(1000, 2000, 0, 3001));
+
+    // The second event only gets a voice once the first one is done, and starts late.
+    wait_for_decoder();
+    frames = collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+    frames.extend(collect_frames(&mut scheduler, channels, 2000));
+
+    // Samples missed while the buffer was being filled are skipped to stay in sync.
+    assert_eq!(&frames[10..], &ramp_frames(2000, 2000, 3011, 5002)[..]);
+}
+
+#[test]
+fn test_streaming_source_scheduler_cancel_event() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let mut scheduler = StreamingSourceScheduler::new(
+        move || ramp_source(sample_rate, channels, 2000),
+        sample_rate,
+        channels,
+    )
+    .unwrap();
+    let id = scheduler
+        .schedule_event(PlaybackEvent {
+            source_id: 0.into(),
+            timestamp: 1000,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut frames = collect_frames(&mut scheduler, channels, 500);
+    wait_for_decoder();
+    frames.extend(collect_frames(&mut scheduler, channels, 1000));
+    assert_eq!(frames, ramp_frames(1000, 2000, 0, 1500));
+
+    // Cancelling an event that is playing stops it immediately.
+    assert!(scheduler.cancel_event(id));
+    assert!(!scheduler.cancel_event(id));
+
+    let frames = collect_frames(&mut scheduler, channels, 1000);
+    assert!(find_hits(&frames).is_empty());
+}
+
+#[test]
+fn test_streaming_source_scheduler_speed_and_late_events() {
+    let sample_rate = 48000_u32;
+    let channels = 1;
+
+    let mut scheduler = StreamingSourceScheduler::new(
+        move || ramp_source(sample_rate, channels, 2000),
+        sample_rate,
+        channels,
+    )
+    .unwrap();
+
+    // Streams can't be resampled, so a different speed is an error instead of being ignored.
+    let result = scheduler.schedule_event(PlaybackEvent {
+        speed: Some(2.0),
+        ..PlaybackEvent::at_frame(0, 1000)
+    });
+    assert!(matches!(result, Err(SchedulerError::UnsupportedSpeed(2.0))));
+
+    collect_frames(&mut scheduler, channels, 100);
+    assert!(matches!(
+        scheduler.schedule_event(PlaybackEvent::at_frame(0, 50)),
+        Err(SchedulerError::EventInPast { .. })
+    ));
+
+    // Late events follow the policy of the stream, like on a `SingleSourceScheduler`.
+    scheduler.set_late_event_policy(LateEventPolicy::Truncate);
+    let id = scheduler
+        .schedule_event(PlaybackEvent::at_frame(0, 50))
+        .unwrap();
+    assert_eq!(
+        scheduler.pop_late_event(),
+        Some(LateEvent {
+            id,
+            timestamp: 50,
+            position: 100,
+            policy: LateEventPolicy::Truncate,
+        })
+    );
+
+    // The event resumes in sync once its voice has been filled.
+    collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert_eq!(frames, ramp_frames(50, 2000, 101, 201));
+}
+
+#[test]
+fn test_scheduler_streaming_source_seek() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+    let stream_id = scheduler
+        .add_streaming_source(move || ramp_source(sample_rate, channels, 2000))
+        .unwrap();
+    scheduler
+        .schedule(PlaybackEvent {
+            source_id: stream_id,
+            timestamp: 1000,
+            ..Default::default()
+        })
+        .unwrap();
+
+    // Seeking into the event resumes it from the right sample.
+    scheduler
+        .try_seek(frames_to_duration(2000, sample_rate))
+        .unwrap();
+    collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+
+    let frames = collect_frames(&mut scheduler, channels, 2000);
+    assert_eq!(&frames[10..], &ramp_frames(1000, 2000, 2011, 4001)[..]);
+}
+
+#[test]
+fn test_scheduler_handle_cancels_streamed_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let handle = scheduler.handle();
+
+    // The first source and the first stream have the same number, but their events don't.
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    let stream_id = scheduler
+        .add_streaming_source(move || common::constant_source(sample_rate, channels, 1, 0.5))
+        .unwrap();
+    assert_eq!(source_id, stream_id.index());
+
+    let source_event = scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 100))
+        .unwrap();
+    let stream_event = scheduler
+        .schedule(PlaybackEvent::at_frame(stream_id, 50))
+        .unwrap();
+    assert!(!source_event.is_streamed());
+    assert!(stream_event.is_streamed());
+    assert_eq!(stream_event.source_id(), stream_id.index());
+
+    handle.cancel_event(stream_event).unwrap();
+
+    let mut frames = collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+    frames.extend(collect_frames(&mut scheduler, channels, 199));
+    assert_eq!(find_hits(&frames), vec![100]);
+}
+
+#[test]
+fn test_scheduler_handle_clears_streamed_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let handle = scheduler.handle();
+
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    let stream_id = scheduler
+        .add_streaming_source(move || common::constant_source(sample_rate, channels, 1, 0.5))
+        .unwrap();
+    scheduler
+        .schedule_many([
+            PlaybackEvent::at_frame(source_id, 100),
+            PlaybackEvent::at_frame(stream_id, 50),
+        ])
+        .unwrap();
+
+    let mut frames = collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+    handle.clear_schedule().unwrap();
+    frames.extend(collect_frames(&mut scheduler, channels, 199));
+    assert!(find_hits(&frames).is_empty());
+}
+
+#[test]
+fn test_scheduler_handle_schedules_streamed_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let handle = scheduler.handle();
+
+    let stream_id = scheduler
+        .add_streaming_source(move || common::constant_source(sample_rate, channels, 1, 0.5))
+        .unwrap();
+
+    let event = handle
+        .schedule_event(PlaybackEvent::at_frame(stream_id, 50))
+        .unwrap();
+    assert!(event.is_streamed());
+
+    // Events on a stream that doesn't exist are dropped, instead of going to a regular source.
+    for _ in 0..2 {
+        scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    }
+    let unknown = handle
+        .schedule_event(PlaybackEvent::at_frame(SourceId::Streamed(1), 100))
+        .unwrap();
+
+    let mut frames = collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+    frames.extend(collect_frames(&mut scheduler, channels, 199));
+    assert_eq!(find_hits(&frames), vec![50]);
+    assert_eq!(
+        scheduler.pop_event_notification(),
+        Some(EventNotification::EventDropped {
+            id: unknown,
+            source_id: 1,
+            frame: 100,
+        })
+    );
+}
+
+#[test]
+fn test_scheduler_shared_sources() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 3);
+    let handle = scheduler.handle();
+
+    let buffer = SampleBuffer::new(
+        ramp_source(sample_rate, channels, 100),
+        sample_rate,
+        channels,
+    );
+
+    // Every source plays the same samples with its own schedule.
+    let first_id = scheduler.add_shared_source(buffer.clone());
+    let second_id = scheduler.add_shared_source(buffer.clone());
+    let third_id = handle.add_shared_source(buffer).unwrap();
+
+    for (source_id, timestamp) in [(first_id, 1000), (second_id, 2000), (third_id, 3000)] {
+        handle
+            .schedule_event(PlaybackEvent {
+                source_id: source_id.into(),
+                timestamp,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    let frames = collect_frames(&mut scheduler, channels, 4000);
+    let expected: Vec<f32> = [1000, 2000, 3000]
+        .iter()
+        .map(|&start| ramp_frames(start, 100, 0, 4000))
+        .reduce(|a, b| a.iter().zip(b).map(|(a, b)| a + b).collect())
+        .unwrap();
+    assert_eq!(frames, expected);
+}
+
+#[test]
+fn test_scheduler_shared_source_is_converted() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+    // A mono buffer is converted to the channel count of the scheduler.
+    let buffer = SampleBuffer::new(
+        common::constant_source(sample_rate, 1, 100, 0.5),
+        sample_rate,
+        1,
+    );
+    assert_eq!(buffer.channels(), 1);
+    assert_eq!(buffer.len(), 100);
+
+    let source_id = scheduler.add_shared_source(buffer);
+    scheduler
+        .get_scheduler(source_id)
+        .unwrap()
+        .schedule_event(PlaybackEvent {
+            source_id: source_id.into(),
+            timestamp: 10,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let samples: Vec<f32> = (0..240).map(|_| scheduler.next().unwrap()).collect();
+    assert!(samples[..20].iter().all(|sample| *sample == 0.0));
+    assert!(samples[20..220].iter().all(|sample| *sample == 0.5));
+    assert!(samples[220..].iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn test_scheduler_time_conversions() {
+    let sample_rate = 44100_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10, 0.0);
+    let scheduler = Scheduler::new(input, sample_rate, channels);
+
+    assert_eq!(
+        scheduler.duration_to_frames(Duration::from_millis(1500)),
+        66150
+    );
+    assert_eq!(
+        scheduler.frames_to_duration(66150),
+        Duration::from_millis(1500)
+    );
+    assert_eq!(scheduler.samples_to_frames(132300), 66150);
+
+    let event = scheduler.event_at_duration(3, Duration::from_micros(22_680));
+    assert_eq!(event, PlaybackEvent::at_frame(3, 1000));
+
+    let event = scheduler.event_at_seconds(3, 0.5);
+    assert_eq!(event.timestamp, 22050);
+
+    let event = scheduler.event_at_beat(3, 90.0, 3.0);
+    assert_eq!(event.timestamp, 88200);
+}
+
+#[test]
+fn test_scheduler_event_at_duration_plays_on_frame() {
+    let sample_rate = 44100_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 10, 0.5));
+
+    // 25ms is 1102.5 frames, which rounds to frame 1103.
+    let event = scheduler.event_at_duration(source_id, Duration::from_millis(25));
+    scheduler
+        .get_scheduler(source_id)
+        .unwrap()
+        .schedule_event(event)
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 2000);
+    assert_eq!(find_hits(&frames), (1103..1113).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_scheduler_schedule_beat() {
+    let sample_rate = 4800_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10 * sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+
+    // Slow down from 120 to 60 BPM on beat 2.
+    let mut tempo_map = TempoMap::new(120.0);
+    tempo_map.set_tempo(2.0, 60.0, TempoCurve::Constant);
+
+    // Beat repetitions are spaced in ticks, so they follow the tempo change.
+    let ppq = tempo_map.ppq() as u64;
+    let repeat = BeatRepeat {
+        ticks: ppq,
+        count: 2,
+    };
+    scheduler
+        .schedule_beat(&tempo_map, 1.0, PlaybackEvent::at_frame(source_id, 0), Some(repeat))
+        .unwrap();
+    scheduler
+        .schedule_beat(&tempo_map, 3.5, PlaybackEvent::at_frame(source_id, 0), None)
+        .unwrap();
+
+    // Frame repetitions are still spaced in frames.
+    let event = PlaybackEvent {
+        source_id: source_id.into(),
+        repeat: Some((100, 1)),
+        ..Default::default()
+    };
+    scheduler.schedule_beat(&tempo_map, 4.5, event, None).unwrap();
+
+    assert!(matches!(
+        scheduler.schedule_beat(&tempo_map, 4.5, event, Some(repeat)),
+        Err(SchedulerError::ConflictingRepeat)
+    ));
+    assert!(matches!(
+        scheduler.schedule_beat(&tempo_map, 0.0, PlaybackEvent::at_frame(source_id + 1, 0), None),
+        Err(SchedulerError::UnknownSource(id)) if id == source_id + 1
+    ));
+
+    let frames = collect_frames(&mut scheduler, channels, 20000);
+    assert_eq!(find_hits(&frames), vec![2400, 4800, 9600, 12000, 16800, 16900]);
+}
+
+#[test]
+fn test_single_source_scheduler_voice_limit_steals_oldest() {
+    let source = common::constant_source(48000, 2, 100, 0.25);
+    let mut scheduler = SingleSourceScheduler::new(source, 48000, 2);
+    scheduler.set_voice_limit(Some(2), VoiceStealPolicy::Oldest);
+    for timestamp in [0, 10, 20] {
+        scheduler
+            .schedule_event(PlaybackEvent::at_frame(0, timestamp))
+            .unwrap();
+    }
+
+    let frames = collect_frames(&mut scheduler, 2, 130);
+    assert!(frames[..10].iter().all(|sample| *sample == 0.25));
+    assert!(frames[10..110].iter().all(|sample| *sample == 0.5));
+    assert!(frames[110..120].iter().all(|sample| *sample == 0.25));
+    assert!(frames[120..].iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn test_single_source_scheduler_voice_limit_steals_quietest() {
+    let source = common::constant_source(48000, 2, 100, 0.25);
+    let mut scheduler = SingleSourceScheduler::new(source, 48000, 2);
+    scheduler.set_voice_limit(Some(2), VoiceStealPolicy::Quietest);
+    for (timestamp, gain) in [(0, 1.0), (10, 0.5), (20, 1.0)] {
+        scheduler
+            .schedule_event(PlaybackEvent {
+                gain: Some(gain),
+                ..PlaybackEvent::at_frame(0, timestamp)
+            })
+            .unwrap();
+    }
+
+    let frames = collect_frames(&mut scheduler, 2, 130);
+    assert!(frames[..10].iter().all(|sample| *sample == 0.25));
+    assert!(frames[10..20].iter().all(|sample| *sample == 0.375));
+    assert!(frames[20..100].iter().all(|sample| *sample == 0.5));
+    assert!(frames[100..120].iter().all(|sample| *sample == 0.25));
+    assert!(frames[120..].iter().all(|sample| *sample == 0.0));
+
+    // Seeking back before the voice was stolen plays it again.
+    scheduler.try_seek(frames_to_duration(15, 48000)).unwrap();
+    let frames = collect_frames(&mut scheduler, 2, 10);
+    assert!(frames[..5].iter().all(|sample| *sample == 0.375));
+    assert!(frames[5..].iter().all(|sample| *sample == 0.5));
+}
+
+#[test]
+fn test_scheduler_choke_groups() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+    let open_hihat =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 1000, 0.25));
+    let closed_hihat =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 10, 0.5));
+    for (source_id, timestamp) in [(open_hihat, 0), (closed_hihat, 100)] {
+        let source = scheduler.get_scheduler(source_id).unwrap();
+        source.set_choke_group(Some(1));
+        source
+            .schedule_event(PlaybackEvent::at_frame(source_id, timestamp))
+            .unwrap();
+    }
+
+    // The open hi-hat stops on the exact sample the closed one starts.
+    let samples: Vec<f32> = (0..240).map(|_| scheduler.next().unwrap()).collect();
+    assert!(samples[..200].iter().all(|sample| *sample == 0.25));
+    assert!(samples[200..220].iter().all(|sample| *sample == 0.5));
+    assert!(samples[220..].iter().all(|sample| *sample == 0.0));
+
+    // Seeking back plays the open hi-hat until it is choked again.
+    scheduler
+        .try_seek(frames_to_duration(50, sample_rate))
+        .unwrap();
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert!(frames[..50].iter().all(|sample| *sample == 0.25));
+    assert!(frames[50..60].iter().all(|sample| *sample == 0.5));
+    assert!(frames[60..].iter().all(|sample| *sample == 0.0));
+
+    // Seeking past the choke keeps the open hi-hat silent.
+    scheduler
+        .try_seek(frames_to_duration(500, sample_rate))
+        .unwrap();
+    let frames = collect_frames(&mut scheduler, channels, 100);
+    assert!(find_hits(&frames).is_empty());
+}
+
+#[test]
+fn test_scheduler_schedule_routes_by_source_id() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 2);
+    let handle = scheduler.handle();
+
+    let quiet_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.25));
+    let loud_id = handle
+        .add_source(common::constant_source(sample_rate, channels, 1, 0.5))
+        .unwrap();
+
+    // Sources added by a handle can be scheduled right away.
+    let id = scheduler
+        .schedule(PlaybackEvent::at_frame(loud_id, 20))
+        .unwrap();
+    assert_eq!(id.source_id(), loud_id);
+
+    let ids = scheduler
+        .schedule_many([
+            PlaybackEvent::at_frame(quiet_id, 10),
+            PlaybackEvent::at_frame(loud_id, 30),
+        ])
+        .unwrap();
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids[0].source_id(), quiet_id);
+    assert_eq!(ids[1].source_id(), loud_id);
+
+    // Scheduling in bulk on the source itself ignores the source ids of the events.
+    let ids = scheduler
+        .get_scheduler(loud_id)
+        .unwrap()
+        .schedule_events([PlaybackEvent::at_frame(quiet_id, 35)])
+        .unwrap();
+    assert_eq!(ids[0].source_id(), loud_id);
+
+    let frames = collect_frames(&mut scheduler, channels, 40);
+    let hits: Vec<(usize, f32)> = find_hits(&frames)
+        .into_iter()
+        .map(|frame| (frame, frames[frame]))
+        .collect();
+    assert_eq!(hits, vec![(10, 0.25), (20, 0.5), (30, 0.5), (35, 0.5)]);
+}
+
+#[test]
+fn test_scheduler_schedule_unknown_source() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+
+    assert!(matches!(
+        scheduler.schedule(PlaybackEvent::at_frame(7, 0)),
+        Err(SchedulerError::UnknownSource(7))
+    ));
+
+    // A single unknown source rejects the whole batch.
+    assert!(matches!(
+        scheduler.schedule_many([
+            PlaybackEvent::at_frame(source_id, 10),
+            PlaybackEvent::at_frame(source_id + 1, 20),
+        ]),
+        Err(SchedulerError::UnknownSource(id)) if id == source_id + 1
+    ));
+
+    let frames = collect_frames(&mut scheduler, channels, 40);
+    assert!(find_hits(&frames).is_empty());
+}
+
+#[test]
+fn test_schedule_errors() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+
+    assert!(matches!(
+        scheduler.schedule(PlaybackEvent::at_frame(source_id, u64::MAX)),
+        Err(SchedulerError::TimestampOverflow)
+    ));
+
+    // Only the last repetition overflows.
+    let repeating = PlaybackEvent {
+        repeat: Some((u64::MAX / 4, 2)),
+        ..PlaybackEvent::at_frame(source_id, 10)
+    };
+    assert!(matches!(
+        scheduler.schedule(repeating),
+        Err(SchedulerError::TimestampOverflow)
+    ));
+
+    collect_frames(&mut scheduler, channels, 20);
+
+    assert!(matches!(
+        scheduler.schedule(PlaybackEvent::at_frame(source_id, 19)),
+        Err(SchedulerError::EventInPast {
+            timestamp: 19,
+            position: 20
+        })
+    ));
+
+    // A batch with an event in the past is rejected as a whole.
+    assert!(matches!(
+        scheduler.schedule_many([
+            PlaybackEvent::at_frame(source_id, 30),
+            PlaybackEvent::at_frame(source_id, 5),
+        ]),
+        Err(SchedulerError::EventInPast { timestamp: 5, .. })
+    ));
+
+    scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 20))
+        .unwrap();
+
+    assert!(matches!(
+        scheduler.get_scheduler(source_id + 1),
+        Err(SchedulerError::UnknownSource(id)) if id == source_id + 1
+    ));
+    assert!(matches!(
+        scheduler.get_streaming_scheduler(0),
+        Err(SchedulerError::UnknownSource(0))
+    ));
+    assert!(matches!(
+        scheduler.schedule(PlaybackEvent::at_frame(SourceId::Streamed(0), 20)),
+        Err(SchedulerError::UnknownSource(0))
+    ));
+    assert!(matches!(
+        scheduler.schedule_many([
+            PlaybackEvent::at_frame(source_id, 30),
+            PlaybackEvent::at_frame(SourceId::Streamed(0), 30),
+        ]),
+        Err(SchedulerError::UnknownSource(0))
+    ));
+
+    let frames = collect_frames(&mut scheduler, channels, 20);
+    assert_eq!(find_hits(&frames), vec![0]);
+}
+
+#[test]
+fn test_empty_and_undecodable_sources() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+    assert!(matches!(
+        scheduler.try_add_source(common::constant_source(sample_rate, channels, 0, 0.5)),
+        Err(SchedulerError::EmptySource)
+    ));
+    assert!(matches!(
+        scheduler.try_add_source(common::DummySource::new(sample_rate, 0, 10, 0.5)),
+        Err(SchedulerError::NoChannels)
+    ));
+    assert!(matches!(
+        SampleBuffer::try_new(
+            common::constant_source(sample_rate, channels, 1, 0.5),
+            sample_rate,
+            0
+        ),
+        Err(SchedulerError::NoChannels)
+    ));
+    assert_eq!(
+        scheduler
+            .try_add_source(common::constant_source(sample_rate, channels, 1, 0.5))
+            .unwrap(),
+        0
+    );
+
+    let garbage = std::io::Cursor::new(vec![0_u8; 64]);
+    assert!(matches!(
+        SampleBuffer::decode(garbage, sample_rate, channels),
+        Err(SchedulerError::Decode(_))
+    ));
+
+    let note_hit = std::fs::File::open("assets/note_hit.wav").unwrap();
+    let buffer = SampleBuffer::decode(note_hit, sample_rate, channels).unwrap();
+    assert!(!buffer.is_empty());
+
+    // An empty schedule has nothing left to play.
+    let source = SingleSourceScheduler::from_buffer(buffer);
+    assert_eq!(source.size_hint(), (0, None));
+}
+
+#[test]
+fn test_unsupported_seek() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::DummySource::new(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 10))
+        .unwrap();
+
+    assert!(matches!(
+        scheduler.seek(frames_to_duration(20, sample_rate)),
+        Err(SchedulerError::UnsupportedSeek { .. })
+    ));
+
+    // The scheduled sources are left where they were.
+    let frames = collect_frames(&mut scheduler, channels, 20);
+    assert_eq!(find_hits(&frames), vec![10]);
+}
+
+#[test]
+fn test_single_source_scheduler_late_event_policies() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let mut scheduler = SingleSourceScheduler::new(
+        ramp_source(sample_rate, channels, 10),
+        sample_rate,
+        channels,
+    );
+    collect_frames(&mut scheduler, channels, 5);
+
+    // Late events are dropped by default.
+    assert_eq!(scheduler.late_event_policy(), LateEventPolicy::Drop);
+    assert!(matches!(
+        scheduler.schedule_event(PlaybackEvent::at_frame(0, 3)),
+        Err(SchedulerError::EventInPast {
+            timestamp: 3,
+            position: 5
+        })
+    ));
+    assert_eq!(scheduler.pop_late_event(), None);
+
+    scheduler.set_late_event_policy(LateEventPolicy::Truncate);
+    let truncated = scheduler
+        .schedule_event(PlaybackEvent::at_frame(0, 3))
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert_eq!(frames, ramp_frames(3, 10, 5, 15));
+
+    let report = scheduler.pop_late_event().unwrap();
+    assert_eq!(
+        report,
+        LateEvent {
+            id: truncated,
+            timestamp: 3,
+            position: 5,
+            policy: LateEventPolicy::Truncate,
+        }
+    );
+    assert_eq!(report.lateness(), 2);
+
+    scheduler.set_late_event_policy(LateEventPolicy::PlayFromStart);
+    let delayed = PlaybackEvent {
+        repeat: Some((20, 1)),
+        ..PlaybackEvent::at_frame(0, 12)
+    };
+    scheduler.schedule_event(delayed).unwrap();
+
+    // The repetition is delayed as well.
+    let frames = collect_frames(&mut scheduler, channels, 30);
+    let expected: Vec<f32> = ramp_frames(15, 10, 15, 45)
+        .into_iter()
+        .zip(ramp_frames(35, 10, 15, 45))
+        .map(|(first, second)| first + second)
+        .collect();
+    assert_eq!(frames, expected);
+
+    let report = scheduler.pop_late_event().unwrap();
+    assert_eq!(report.policy, LateEventPolicy::PlayFromStart);
+    assert_eq!(report.lateness(), 3);
+    assert_eq!(scheduler.pop_late_event(), None);
+}
+
+#[test]
+fn test_scheduler_handle_reports_late_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    let handle = scheduler.handle();
+
+    collect_frames(&mut scheduler, channels, 10);
+
+    let dropped = handle
+        .schedule_event(PlaybackEvent::at_frame(source_id, 4))
+        .unwrap();
+    handle
+        .set_late_event_policy(source_id, LateEventPolicy::PlayFromStart)
+        .unwrap();
+    let delayed = handle
+        .schedule_event(PlaybackEvent::at_frame(source_id, 6))
+        .unwrap();
+
+    // The commands are applied before frame 10 is played, so the delayed event starts on it.
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert_eq!(find_hits(&frames), vec![0]);
+
+    let report = handle.pop_late_event().unwrap();
+    assert_eq!(report.id, dropped);
+    assert_eq!(report.policy, LateEventPolicy::Drop);
+    assert_eq!((report.timestamp, report.position), (4, 10));
+
+    let report = handle.pop_late_event().unwrap();
+    assert_eq!(report.id, delayed);
+    assert_eq!(report.policy, LateEventPolicy::PlayFromStart);
+    assert_eq!((report.timestamp, report.position), (6, 10));
+
+    assert_eq!(scheduler.pop_late_event(), None);
+}
+
+#[test]
+fn test_single_source_scheduler_schedule_events_in_bulk() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let events: Vec<PlaybackEvent> = [40, 10, 30, 10, 20]
+        .into_iter()
+        .enumerate()
+        .map(|(index, frame)| PlaybackEvent {
+            gain: Some(index as f32 + 1.0),
+            repeat: (frame == 20).then_some((5, 1)),
+            ..PlaybackEvent::at_frame(0, frame)
+        })
+        .collect();
+
+    let source = || common::constant_source(sample_rate, channels, 1, 0.5);
+    let mut one_by_one = SingleSourceScheduler::new(source(), sample_rate, channels);
+    for event in events.iter() {
+        one_by_one.schedule_event(*event).unwrap();
+    }
+
+    // The second batch is merged with the events already scheduled.
+    let mut bulk = SingleSourceScheduler::new(source(), sample_rate, channels);
+    let mut ids = bulk.schedule_events(events[..3].iter().copied()).unwrap();
+    ids.extend(bulk.schedule_events(events[3..].iter().copied()).unwrap());
+    assert_eq!(ids.len(), events.len());
+
+    let expected = collect_frames(&mut one_by_one, channels, 50);
+    let frames = collect_frames(&mut bulk, channels, 50);
+    assert_eq!(frames, expected);
+    assert_eq!(find_hits(&frames), vec![10, 20, 25, 30, 40]);
+    assert_eq!(frames[10], 1.0 + 2.0);
+
+    // Events are still cancelled by identifier.
+    assert!(bulk.cancel_event(ids[4]));
+
+    // A single event that can't be scheduled rejects the whole batch.
+    assert!(matches!(
+        bulk.schedule_events([
+            PlaybackEvent::at_frame(0, 60),
+            PlaybackEvent::at_frame(0, 30),
+        ]),
+        Err(SchedulerError::EventInPast { timestamp: 30, .. })
+    ));
+
+    // Late events in a batch follow the late event policy.
+    bulk.set_late_event_policy(LateEventPolicy::Truncate);
+    let ids = bulk
+        .schedule_events([
+            PlaybackEvent::at_frame(0, 60),
+            PlaybackEvent::at_frame(0, 49),
+        ])
+        .unwrap();
+
+    let frames = collect_frames(&mut bulk, channels, 20);
+    assert_eq!(find_hits(&frames), vec![10]);
+
+    let report = bulk.pop_late_event().unwrap();
+    assert_eq!(report.id, ids[1]);
+    assert_eq!(report.policy, LateEventPolicy::Truncate);
+}
+
+#[test]
+fn test_single_source_scheduler_reclaims_finished_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 1, 0.5);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+    scheduler.set_schedule_capacity(2);
+    assert_eq!(scheduler.schedule_capacity(), 2);
+
+    let first = scheduler
+        .schedule_event(PlaybackEvent::at_frame(0, 0))
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent::at_frame(0, 2))
+        .unwrap();
+    collect_frames(&mut scheduler, channels, 10);
+
+    // The schedule is full, so the finished events make room for the new ones.
+    scheduler
+        .schedule_events([
+            PlaybackEvent::at_frame(0, 12),
+            PlaybackEvent::at_frame(0, 14),
+        ])
+        .unwrap();
+    assert!(!scheduler.cancel_event(first));
+
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert_eq!(find_hits(&frames), vec![2, 4]);
+}
+
+#[test]
+fn test_scheduler_handle_events_reuse_reclaimed_capacity() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    scheduler
+        .get_scheduler(source_id)
+        .unwrap()
+        .set_schedule_capacity(2);
+    let handle = scheduler.handle();
+
+    for frame in [0, 2] {
+        handle
+            .schedule_event(PlaybackEvent::at_frame(source_id, frame))
+            .unwrap();
+    }
+    collect_frames(&mut scheduler, channels, 10);
+
+    // The first two events finished, but nothing has finished to make room for the third one.
+    let ids: Vec<EventId> = [12, 14, 16]
+        .into_iter()
+        .map(|frame| {
+            handle
+                .schedule_event(PlaybackEvent::at_frame(source_id, frame))
+                .unwrap()
+        })
+        .collect();
+
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert_eq!(find_hits(&frames), vec![2, 4]);
+
+    // The dropped event is reported, since the handle couldn't return an error.
+    let dropped: Vec<EventNotification> = std::iter::from_fn(|| handle.pop_event_notification())
+        .filter(|notification| matches!(notification, EventNotification::EventDropped { .. }))
+        .collect();
+    assert_eq!(
+        dropped,
+        vec![EventNotification::EventDropped {
+            id: ids[2],
+            source_id,
+            frame: 16,
+        }]
+    );
+}
+
+/// Creates a `SingleSourceScheduler` playing a ramp source with many overlapping events, at
+/// different gains, pans and speeds.
+fn busy_single_source_scheduler(channels: u16) -> SingleSourceScheduler {
+    let sample_rate = 48000_u32;
+
+    let source = ramp_source(sample_rate, channels, 300);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+    scheduler.set_voice_limit(Some(6), VoiceStealPolicy::Quietest);
+
+    let events = (0..40).map(|index| PlaybackEvent {
+        timestamp: index * 37 % 500,
+        gain: Some(0.25 + (index % 4) as f32 * 0.25),
+        pan: Some((index % 3) as f32 * 0.5 - 0.5),
+        speed: Some([1.0, 2.0, 0.5, 1.0, 1.5][index as usize % 5]),
+        ..Default::default()
+    });
+    scheduler.schedule_events(events).unwrap();
+
+    scheduler
+}
+
+/// Asserts that two renders of the same audio are equal, up to rounding errors from mixing the
+/// events in a different order.
+fn assert_same_samples(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+
+    for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "sample {index}: {actual} != {expected}"
+        );
+    }
+}
+
+#[test]
+fn test_single_source_scheduler_fill_buffer_matches_iterator() {
+    // Three channels can't be mixed with a repeating SIMD gain pattern, so they use the scalar path.
+    for channels in [1, 2, 3] {
+        let mut scheduler = busy_single_source_scheduler(channels);
+        let expected: Vec<f32> = (0..2000 * channels as usize)
+            .map(|_| scheduler.next().unwrap_or(0.0))
+            .collect();
+
+        // Blocks of any size start and end between events.
+        let mut scheduler = busy_single_source_scheduler(channels);
+        let mut samples = vec![0.0; expected.len()];
+        let mut offset = 0;
+        for block_size in [1, 7, 64, 333].into_iter().cycle() {
+            let end = samples.len().min(offset + block_size);
+            scheduler.fill_buffer(&mut samples[offset..end]);
+
+            offset = end;
+            if offset == samples.len() {
+                break;
+            }
+        }
+
+        assert_same_samples(&samples, &expected);
+    }
+}
+
+#[test]
+fn test_scheduler_fill_buffer_matches_iterator() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let scheduler = || {
+        let input = ramp_source(sample_rate, channels, 3000);
+        let mut scheduler = Scheduler::new(input, sample_rate, channels);
+
+        let open_hihat =
+            scheduler.add_source(common::constant_source(sample_rate, channels, 1000, 0.25));
+        let closed_hihat =
+            scheduler.add_source(common::constant_source(sample_rate, channels, 10, 0.5));
+        let ramp = scheduler.add_source(ramp_source(sample_rate, channels, 300));
+
+        for source_id in [open_hihat, closed_hihat] {
+            scheduler
+                .get_scheduler(source_id)
+                .unwrap()
+                .set_choke_group(Some(1));
+        }
+
+        let events = (0..30).flat_map(|index| {
+            [
+                PlaybackEvent::at_frame(open_hihat, index * 97),
+                PlaybackEvent::at_frame(closed_hihat, index * 97 + 41),
+                PlaybackEvent {
+                    speed: Some(1.5),
+                    ..PlaybackEvent::at_frame(ramp, index * 61)
+                },
+            ]
+        });
+        scheduler.schedule_many(events).unwrap();
+
+        scheduler
+    };
+
+    let mut expected_scheduler = scheduler();
+    let expected = collect_frames(&mut expected_scheduler, channels, 3000);
+
+    let mut scheduler = scheduler();
+    let mut samples = vec![0.0; 3000 * channels as usize];
+    for block in samples.chunks_mut(250) {
+        scheduler.fill_buffer(block);
+    }
+    let frames: Vec<f32> = samples.iter().step_by(channels as usize).copied().collect();
+
+    assert_same_samples(&frames, &expected);
+}
+
+#[test]
+fn test_scheduler_events_scheduled_during_a_block_are_sample_accurate() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    let handle = scheduler.handle();
+
+    // The scheduler has rendered a block ahead, but new events still play on their frame.
+    collect_frames(&mut scheduler, channels, 10);
+    scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 15))
+        .unwrap();
+    handle
+        .schedule_event(PlaybackEvent::at_frame(source_id, 17))
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert_eq!(find_hits(&frames), vec![5, 7]);
+}
+
+#[test]
+fn test_scheduler_handle_command_bursts_are_sample_accurate() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 2);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 1, 0.5));
+    let handle = scheduler.handle();
+
+    // Events inside and past the rendered block, and a source added in between, all apply
+    // together before the next sample.
+    collect_frames(&mut scheduler, channels, 10);
+    for frame in [2000, 12, 600, 14] {
+        handle
+            .schedule_event(PlaybackEvent::at_frame(source_id, frame))
+            .unwrap();
+    }
+    let added_id = handle
+        .add_source(common::constant_source(sample_rate, channels, 1, 0.5))
+        .unwrap();
+    handle
+        .schedule_event(PlaybackEvent::at_frame(added_id, 16))
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, channels, 2000);
+    assert_eq!(find_hits(&frames), vec![2, 4, 6, 590, 1990]);
+}
+
+#[test]
+fn test_scheduler_master_stage_clips() {
+    // The input plays 0.5, and a source adds 0.75 from frame 100 to 200.
+    let loud_scheduler = |stage: MasterStage| {
+        let source = common::constant_source(48000, 2, 100, 0.75);
+        let mut scheduler = scheduler_with((48000, 0.5), [(source, vec![100])]);
+        scheduler.set_master_stage(stage);
+
+        scheduler
+    };
+
+    let mut scheduler = loud_scheduler(MasterStage::Off);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert_eq!(frames[150], 1.25);
+    assert_eq!(scheduler.gain_reduction(), 0.0);
+
+    // The ceiling is lowered to leave 6 dB of headroom.
+    let ceiling = master::decibels_to_gain(-6.0);
+    let mut scheduler = loud_scheduler(MasterStage::HardClip { ceiling });
+    let frames = collect_frames(&mut scheduler, 2, 150);
+    assert_eq!(frames[50], 0.5);
+    assert_eq!(frames[149], ceiling);
+    assert!((scheduler.gain_reduction() - master::gain_to_decibels(1.25 / ceiling)).abs() < 1e-4);
+
+    let tanh = MasterStage::SoftClip {
+        curve: SoftClipCurve::Tanh,
+        ceiling: 1.0,
+    };
+    let mut scheduler = loud_scheduler(tanh);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert_eq!(frames[50], 0.5_f32.tanh());
+    assert_eq!(frames[150], 1.25_f32.tanh());
+    assert_eq!(frames[250], 0.5_f32.tanh());
+
+    // Unlike the hyperbolic tangent, the cubic curve reaches the ceiling.
+    let cubic = MasterStage::SoftClip {
+        curve: SoftClipCurve::Cubic,
+        ceiling: 1.0,
+    };
+    let mut scheduler = loud_scheduler(cubic);
+    let frames = collect_frames(&mut scheduler, 2, 150);
+    assert_eq!(frames[50], 0.6875);
+    assert_eq!(frames[149], 1.0);
+}
+
+#[test]
+fn test_scheduler_master_limiter_keeps_peaks_under_ceiling() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let limiter = MasterStage::Limiter {
+        ceiling: 1.0,
+        lookahead: frames_to_duration(20, sample_rate),
+        release: frames_to_duration(10, sample_rate),
+    };
+    let source = common::constant_source(sample_rate, channels, 100, 0.75);
+    let mut scheduler = scheduler_with((48000, 0.5), [(source, vec![100])]);
+    scheduler.set_master_stage(limiter);
+    let handle = scheduler.handle();
+    assert_eq!(scheduler.master_stage(), limiter);
+
+    let samples: Vec<f32> = (0..800).map(|_| scheduler.next().unwrap()).collect();
+    assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+
+    // The output is delayed by the lookahead.
+    let frames: Vec<f32> = samples.iter().step_by(2).copied().collect();
+    assert!(frames[..20].iter().all(|sample| *sample == 0.0));
+    assert!(frames[20..100].iter().all(|sample| *sample == 0.5));
+
+    // The gain ramps down before the peak, holds it on the ceiling, and recovers after it.
+    assert!(frames[101..120].windows(2).all(|pair| pair[1] < pair[0]));
+    assert!(frames[120..220].iter().all(|sample| *sample == 1.0));
+    assert!(frames[221..].windows(2).all(|pair| pair[1] >= pair[0]));
+    assert!(frames[221] < 0.5 && frames[399] > 0.49);
+
+    // The gain reduction can be read from a handle, while the limiter is working.
+    handle
+        .set_master_stage(MasterStage::Limiter {
+            ceiling: 0.25,
+            lookahead: Duration::ZERO,
+            release: Duration::ZERO,
+        })
+        .unwrap();
+    collect_frames(&mut scheduler, channels, 10);
+    assert!((handle.gain_reduction() - 6.0206).abs() < 1e-3);
+
+    let frames = collect_frames(&mut scheduler, channels, 10);
+    assert!(frames.iter().all(|sample| *sample == 0.25));
+}
+
+#[test]
+fn test_scheduler_source_volume_and_mute() {
+    let (quiet, loud) = (0, 1);
+    let mut scheduler = scheduler_with(
+        (10000, 0.125),
+        [
+            (common::constant_source(48000, 2, 10000, 0.25), vec![0]),
+            (common::constant_source(48000, 2, 10000, 0.5), vec![0]),
+        ],
+    );
+    // Gain changes are ramped over 5 ms, which is 240 frames.
+    let ramp = 240;
+
+    let frames = collect_frames(&mut scheduler, 2, 10);
+    assert!(frames.iter().all(|sample| *sample == 0.875));
+
+    let source = scheduler.get_scheduler(quiet).unwrap();
+    source.set_volume(0.5);
+    assert_eq!(source.volume(), 0.5);
+
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert_eq!(frames[0], 0.875);
+    assert!(frames[..ramp].windows(2).all(|pair| pair[1] < pair[0]));
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.75));
+
+    scheduler.get_scheduler(loud).unwrap().set_muted(true);
+    assert!(scheduler.get_scheduler(loud).unwrap().is_muted());
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.25));
+
+    // Unmuting restores the volume the source had.
+    let source = scheduler.get_scheduler(loud).unwrap();
+    source.set_volume(0.5);
+    source.set_muted(false);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[..ramp].windows(2).all(|pair| pair[1] > pair[0]));
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.5));
+
+    scheduler.set_input_volume(0.0);
+    assert_eq!(scheduler.input_volume(), 0.0);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.375));
+}
+
+#[test]
+fn test_scheduler_source_solo() {
+    let (quiet, loud) = (0, 1);
+    let mut scheduler = scheduler_with(
+        (10000, 0.125),
+        [
+            (common::constant_source(48000, 2, 10000, 0.25), vec![0]),
+            (common::constant_source(48000, 2, 10000, 0.5), vec![0]),
+        ],
+    );
+    let ramp = 240;
+
+    // Soloing a source silences the input and the other sources.
+    scheduler.get_scheduler(quiet).unwrap().set_soloed(true);
+    assert!(scheduler.get_scheduler(quiet).unwrap().is_soloed());
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[..ramp].windows(2).all(|pair| pair[1] < pair[0]));
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.25));
+
+    scheduler.get_scheduler(loud).unwrap().set_soloed(true);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.75));
+
+    // A muted source stays silent while it is soloed.
+    scheduler.get_scheduler(loud).unwrap().set_muted(true);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.25));
+
+    scheduler.get_scheduler(quiet).unwrap().set_soloed(false);
+    scheduler.get_scheduler(loud).unwrap().set_soloed(false);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.375));
+}
+
+#[test]
+fn test_scheduler_handle_controls_channel_strip() {
+    let (quiet, loud) = (0, 1);
+    let mut scheduler = scheduler_with(
+        (10000, 0.125),
+        [
+            (common::constant_source(48000, 2, 10000, 0.25), vec![0]),
+            (common::constant_source(48000, 2, 10000, 0.5), vec![0]),
+        ],
+    );
+    let handle = scheduler.handle();
+    let ramp = 240;
+
+    collect_frames(&mut scheduler, 2, 100);
+
+    handle.set_volume(quiet, 0.0).unwrap();
+    handle.set_input_volume(2.0).unwrap();
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert_eq!(frames[0], 0.875);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.75));
+
+    handle.set_soloed(loud, true).unwrap();
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.5));
+
+    handle.set_muted(loud, true).unwrap();
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.0));
+
+    // Commands for unknown sources are ignored.
+    handle.set_volume(10, 0.5).unwrap();
+    handle.set_soloed(loud, false).unwrap();
+    handle.set_muted(loud, false).unwrap();
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[ramp..].iter().all(|sample| *sample == 0.75));
+}
+
+#[test]
+fn test_single_source_scheduler_volume_ramp_matches_iterator() {
+    let mut scheduler = busy_single_source_scheduler(2);
+    let mut expected = vec![0.0; 4000];
+    for (index, sample) in expected.iter_mut().enumerate() {
+        if index == 901 {
+            scheduler.set_volume(0.25);
+        }
+        if index == 1500 {
+            scheduler.set_muted(true);
+        }
+
+        *sample = scheduler.next().unwrap_or(0.0);
+    }
+
+    let mut scheduler = busy_single_source_scheduler(2);
+    let mut samples = vec![0.0; expected.len()];
+    scheduler.fill_buffer(&mut samples[..901]);
+    scheduler.set_volume(0.25);
+    scheduler.fill_buffer(&mut samples[901..1500]);
+    scheduler.set_muted(true);
+    scheduler.fill_buffer(&mut samples[1500..]);
+
+    assert_same_samples(&samples, &expected);
+    assert!(samples[2000..].iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn test_single_source_scheduler_gain_automation() {
+    let automated_scheduler = || {
+        let source = common::constant_source(48000, 1, 1000, 0.5);
+        let mut scheduler = SingleSourceScheduler::new(source, 48000, 1);
+        scheduler
+            .schedule_event(PlaybackEvent::at_frame(0, 0))
+            .unwrap();
+
+        let lane = scheduler.gain_automation_mut();
+        lane.set_point(100, 1.0, AutomationCurve::Linear);
+        lane.set_point(200, 0.0, AutomationCurve::Step);
+        lane.set_point(300, 0.25, AutomationCurve::Exponential);
+        lane.set_point(400, 1.0, AutomationCurve::Step);
+
+        scheduler
+    };
+
+    let mut scheduler = automated_scheduler();
+    assert_eq!(scheduler.gain_automation().points().len(), 4);
+
+    let frames = collect_frames(&mut scheduler, 1, 600);
+    assert!(frames[..=100].iter().all(|sample| *sample == 0.5));
+    assert!(frames[100..200].windows(2).all(|pair| pair[1] < pair[0]));
+    assert_eq!(frames[150], 0.25);
+    assert!(frames[200..300].iter().all(|sample| *sample == 0.0));
+    assert_eq!(frames[300], 0.125);
+    assert!((frames[350] - 0.25).abs() < 1e-6);
+    assert!(frames[400..].iter().all(|sample| *sample == 0.5));
+
+    // Rendering in blocks changes the gain on the same samples.
+    let mut scheduler = automated_scheduler();
+    let mut samples = vec![0.0; frames.len()];
+    for block in samples.chunks_mut(77) {
+        scheduler.fill_buffer(block);
+    }
+    assert_same_samples(&samples, &frames);
+}
+
+#[test]
+fn test_scheduler_gain_automation_follows_events() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 1000, 0.5);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 1000, 0.25));
+
+    // The input cuts out as the source fades in.
+    let event = PlaybackEvent::at_frame(source_id, 100);
+    scheduler.schedule(event).unwrap();
+    let lane = scheduler.input_gain_automation_mut();
+    lane.set_point(99, 1.0, AutomationCurve::Step);
+    lane.set_point(100, 0.0, AutomationCurve::Step);
+    assert_eq!(scheduler.input_gain_automation().points().len(), 2);
+
+    let lane = scheduler
+        .get_scheduler(source_id)
+        .unwrap()
+        .gain_automation_mut();
+    lane.set_point(event.timestamp, 0.0, AutomationCurve::Linear);
+    lane.set_point(event.timestamp + 100, 1.0, AutomationCurve::Step);
+
+    let frames = collect_frames(&mut scheduler, channels, 300);
+    assert!(frames[..100].iter().all(|sample| *sample == 0.5));
+    assert_eq!(frames[100], 0.0);
+    assert_eq!(frames[150], 0.125);
+    assert!(frames[200..].iter().all(|sample| *sample == 0.25));
+
+    // The lanes are evaluated on the same frames after seeking.
+    scheduler
+        .seek(frames_to_duration(150, sample_rate))
+        .unwrap();
+    let frames = collect_frames(&mut scheduler, channels, 1);
+    assert_eq!(frames[0], 0.125);
+}
+
+/// Ducking settings that turn sounds down to a quarter at once.
+fn instant_ducking(key: DuckingKey) -> Ducking {
+    Ducking {
+        depth: 0.25,
+        attack: Duration::ZERO,
+        release: Duration::ZERO,
+        key,
+    }
+}
+
+#[test]
+fn test_scheduler_ducking_on_events() {
+    // The input plays 0.5, and a voice with a ducking priority of 1 plays 0.25 from frame 100 to
+    // 200.
+    let ducking_scheduler = |ducking: Ducking| {
+        let voice = common::constant_source(48000, 2, 100, 0.25);
+        let mut scheduler = scheduler_with((10000, 0.5), [(voice, vec![100])]);
+        scheduler.set_ducking(Some(ducking));
+        scheduler
+            .get_scheduler(0)
+            .unwrap()
+            .set_ducking_priority(Some(1));
+
+        scheduler
+    };
+
+    let mut scheduler = ducking_scheduler(instant_ducking(DuckingKey::Events));
+
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[..100].iter().all(|sample| *sample == 0.5));
+    assert!(frames[100..200].iter().all(|sample| *sample == 0.375));
+    assert!(frames[200..].iter().all(|sample| *sample == 0.5));
+
+    // The gain moves smoothly with an attack and a release.
+    let ducking = Ducking {
+        attack: Duration::from_micros(500),
+        release: Duration::from_micros(500),
+        ..instant_ducking(DuckingKey::Events)
+    };
+    let mut scheduler = ducking_scheduler(ducking);
+    assert_eq!(scheduler.ducking(), Some(ducking));
+
+    // The envelope moves once per frame, so both channels get the same gain, and the attack of
+    // 24 frames goes two thirds of the way down in 24 frames.
+    let samples: Vec<f32> = (0..800).map(|_| scheduler.next().unwrap()).collect();
+    assert!(samples.chunks(2).all(|frame| frame[0] == frame[1]));
+    let frames: Vec<f32> = samples.iter().step_by(2).copied().collect();
+    let attack_gain = 1.0 - 0.75 * (1.0 - (-1.0_f32).exp());
+    assert!((frames[123] - (0.5 * attack_gain + 0.25)).abs() < 1e-3);
+    assert!(frames[100..200].windows(2).all(|pair| pair[1] < pair[0]));
+    assert!((frames[199] - 0.375).abs() < 0.01);
+    assert!(frames[200..].windows(2).all(|pair| pair[1] > pair[0]));
+    assert!((frames[399] - 0.5).abs() < 0.01);
+
+    // Muted sources don't duck, even while they fade out.
+    let mut scheduler = ducking_scheduler(instant_ducking(DuckingKey::Events));
+    scheduler.get_scheduler(0).unwrap().set_muted(true);
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames.iter().all(|sample| *sample >= 0.5));
+
+    scheduler.set_ducking(None);
+    assert_eq!(scheduler.ducking(), None);
+}
+
+#[test]
+fn test_scheduler_ducking_on_level() {
+    // The input plays 0.5, and a voice with a ducking priority of 1 plays 0.25 from frame 100 to
+    // 200.
+    let ducking_scheduler = |ducking: Ducking| {
+        let voice = common::constant_source(48000, 2, 100, 0.25);
+        let mut scheduler = scheduler_with((10000, 0.5), [(voice, vec![100])]);
+        scheduler.set_ducking(Some(ducking));
+        scheduler
+            .get_scheduler(0)
+            .unwrap()
+            .set_ducking_priority(Some(1));
+
+        scheduler
+    };
+
+    let mut scheduler =
+        ducking_scheduler(instant_ducking(DuckingKey::Level { threshold: 0.1 }));
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[..100].iter().all(|sample| *sample == 0.5));
+    assert!(frames[100..200].iter().all(|sample| *sample == 0.375));
+    assert!(frames[200..].iter().all(|sample| *sample == 0.5));
+
+    // The voice is too quiet to duck.
+    let mut scheduler =
+        ducking_scheduler(instant_ducking(DuckingKey::Level { threshold: 0.3 }));
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[100..200].iter().all(|sample| *sample == 0.75));
+}
+
+#[test]
+fn test_scheduler_ducking_priorities() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    // The input plays 0.5, and the voice plays 0.25 from frame 100 to 200.
+    let voice = common::constant_source(sample_rate, channels, 100, 0.25);
+    let mut scheduler = scheduler_with((10000, 0.5), [(voice, vec![100])]);
+    scheduler.set_ducking(Some(instant_ducking(DuckingKey::Events)));
+    let source = scheduler.get_scheduler(0).unwrap();
+    source.set_ducking_priority(Some(1));
+    assert_eq!(source.ducking_priority(), Some(1));
+
+    // Hitsounds duck the music, and are ducked by the voice.
+    let hitsounds =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 1000, 0.125));
+    scheduler
+        .get_scheduler(hitsounds)
+        .unwrap()
+        .set_ducking_priority(Some(0));
+    scheduler
+        .schedule(PlaybackEvent::at_frame(hitsounds, 50))
+        .unwrap();
+
+    // Sources without a priority are left alone.
+    let drums = scheduler.add_source(common::constant_source(sample_rate, channels, 1000, 0.0625));
+    scheduler
+        .schedule(PlaybackEvent::at_frame(drums, 0))
+        .unwrap();
+
+    let frames = collect_frames(&mut scheduler, 2, 300);
+    assert!(frames[..50].iter().all(|sample| *sample == 0.5625));
+    assert!(frames[50..100].iter().all(|sample| *sample == 0.3125));
+    assert!(frames[100..200].iter().all(|sample| *sample == 0.46875));
+    assert!(frames[200..].iter().all(|sample| *sample == 0.3125));
+
+    // Priorities above the highest level are lowered to it.
+    let source = scheduler.get_scheduler(drums).unwrap();
+    source.set_ducking_priority(Some(u8::MAX));
+    assert_eq!(
+        source.ducking_priority(),
+        Some(ducking::DUCKING_LEVELS as u8 - 1)
+    );
+}
+
+#[test]
+fn test_scheduler_ducking_rewind_keeps_idle_levels() {
+    let ducking = Ducking {
+        release: Duration::from_millis(1),
+        ..instant_ducking(DuckingKey::Events)
+    };
+
+    // The voice ducks the pad from frame 100 to 200, and the pad ducks the input while it is in
+    // a level. Sounds recover over 48 frames.
+    let (voice, pad) = (0, 1);
+    let mut scheduler = scheduler_with(
+        (10000, 0.5),
+        [
+            (common::constant_source(48000, 2, 100, 0.25), vec![100]),
+            (common::constant_source(48000, 2, 10000, 0.125), vec![0]),
+        ],
+    );
+    scheduler.set_ducking(Some(ducking));
+    for (source_id, priority) in [(voice, 1), (pad, 0)] {
+        scheduler
+            .get_scheduler(source_id)
+            .unwrap()
+            .set_ducking_priority(Some(priority));
+    }
+    let release = |frames: f32| 1.0 - 0.75 * (-frames / 48.0).exp();
+
+    // The pad leaves its level halfway through its release, which holds its gain.
+    collect_frames(&mut scheduler, 2, 250);
+    let source = scheduler.get_scheduler(pad).unwrap();
+    source.set_ducking_priority(None);
+
+    // Rewinding the next render doesn't bring back the gains the level had before.
+    collect_frames(&mut scheduler, 2, 106);
+    let source = scheduler.get_scheduler(pad).unwrap();
+    source.set_ducking_priority(Some(0));
+
+    let frames = collect_frames(&mut scheduler, 2, 1);
+    let expected = 0.5 * 0.25 + 0.125 * release(51.0);
+    assert!((frames[0] - expected).abs() < 1e-3);
+}
+
+#[test]
+fn test_scheduler_ducking_fill_buffer_matches_iterator() {
+    // The input plays 0.5, and a voice with a ducking priority of 1 plays 0.25 from frame 100 to
+    // 200.
+    let ducking_scheduler = |ducking: Ducking| {
+        let voice = common::constant_source(48000, 2, 100, 0.25);
+        let mut scheduler = scheduler_with((10000, 0.5), [(voice, vec![100])]);
+        scheduler.set_ducking(Some(ducking));
+        scheduler
+            .get_scheduler(0)
+            .unwrap()
+            .set_ducking_priority(Some(1));
+
+        scheduler
+    };
+
+    let ducking = Ducking {
+        attack: Duration::from_millis(1),
+        release: Duration::from_millis(3),
+        ..Default::default()
+    };
+    let deeper = Ducking {
+        depth: 0.1,
+        ..ducking
+    };
+
+    // The settings change in the middle of a block rendered ahead, which rewinds the envelopes.
+    let mut scheduler = ducking_scheduler(ducking);
+    let handle = scheduler.handle();
+    let mut expected = vec![0.0; 1000];
+    for (index, sample) in expected.iter_mut().enumerate() {
+        if index == 301 {
+            handle.set_ducking(Some(deeper)).unwrap();
+        }
+
+        *sample = scheduler.next().unwrap();
+    }
+
+    let mut scheduler = ducking_scheduler(ducking);
+    let handle = scheduler.handle();
+    let mut samples = vec![0.0; expected.len()];
+    scheduler.fill_buffer(&mut samples[..301]);
+    handle.set_ducking(Some(deeper)).unwrap();
+    scheduler.fill_buffer(&mut samples[301..]);
+
+    assert_same_samples(&samples, &expected);
+}
+
+#[test]
+fn test_scheduler_end_policy_never() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::Never);
+    assert_eq!(scheduler.end_policy(), EndPolicy::Never);
+
+    assert_eq!(scheduler.size_hint(), (200, None));
+    assert_eq!(scheduler.total_duration(), None);
+
+    let samples: Vec<f32> = scheduler.by_ref().take(2000).collect();
+    assert_eq!(samples.len(), 2000);
+    assert_eq!(samples[199], 0.5);
+    assert_eq!(samples[400], 0.25);
+    assert!(samples[500..].iter().all(|&sample| sample == 0.0));
+    assert!(!scheduler.has_ended());
+}
+
+#[test]
+fn test_scheduler_end_policy_with_input() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::WithInput);
+
+    assert_eq!(scheduler.size_hint(), (200, Some(200)));
+    assert_eq!(
+        scheduler.total_duration(),
+        Some(scheduler.frames_to_duration(100))
+    );
+
+    assert_eq!(scheduler.by_ref().count(), 200);
+    assert!(scheduler.has_ended());
+    assert_eq!(scheduler.next(), None);
+
+    // Seeking back plays the input again.
+    scheduler.try_seek(Duration::ZERO).unwrap();
+    assert!(!scheduler.has_ended());
+    assert_eq!(scheduler.count(), 200);
+}
+
+#[test]
+fn test_scheduler_end_policy_after_last_event() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::AfterLastEvent);
+
+    assert_eq!(scheduler.size_hint(), (500, Some(500)));
+    assert_eq!(
+        scheduler.total_duration(),
+        Some(scheduler.frames_to_duration(250))
+    );
+
+    let samples: Vec<f32> = scheduler.by_ref().collect();
+    assert_eq!(samples.len(), 500);
+    assert_eq!(samples[499], 0.25);
+    assert!(scheduler.has_ended());
+    assert_eq!(scheduler.size_hint(), (0, Some(0)));
+
+    // Scheduling another event moves the end.
+    scheduler.schedule(PlaybackEvent::at_frame(0, 300)).unwrap();
+    assert!(!scheduler.has_ended());
+    assert_eq!(scheduler.size_hint(), (200, Some(200)));
+    assert_eq!(scheduler.by_ref().count(), 200);
+
+    scheduler.try_seek(Duration::ZERO).unwrap();
+    assert_eq!(scheduler.count(), 700);
+}
+
+#[test]
+fn test_scheduler_end_policy_after_last_event_follows_handle() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::AfterLastEvent);
+    let handle = scheduler.handle();
+
+    // Play past the end of the input, so the end of the events has been looked up.
+    assert_eq!(scheduler.by_ref().take(300).count(), 300);
+
+    // Events scheduled and cancelled through a handle move the end as well.
+    let id = handle
+        .schedule_event(PlaybackEvent::at_frame(0, 400))
+        .unwrap();
+    handle
+        .schedule_event(PlaybackEvent::at_frame(0, 300))
+        .unwrap();
+    assert_eq!(scheduler.by_ref().take(100).count(), 100);
+
+    handle.cancel_event(id).unwrap();
+    assert_eq!(scheduler.count(), 300);
+}
+
+#[test]
+fn test_scheduler_fill_buffer_follows_end_policy() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::AfterLastEvent);
+    let playhead = scheduler.playhead();
+
+    // The buffer ends past the last event, so its end is left silent.
+    let mut buffer = vec![1.0; 600];
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(buffer[..200], [0.5; 200]);
+    assert_eq!(buffer[400..500], [0.25; 100]);
+    assert_eq!(buffer[500..], [0.0; 100]);
+    assert!(scheduler.has_ended());
+    assert_eq!(playhead.state(), PlaybackState::Ended);
+    assert_eq!(playhead.frame(), 250);
+    assert_eq!(scheduler.next(), None);
+
+    // The scheduler stays on its end, so a new event plays on its own frame.
+    scheduler.schedule(PlaybackEvent::at_frame(0, 260)).unwrap();
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(buffer[..20], [0.0; 20]);
+    assert_eq!(buffer[20..120], [0.25; 100]);
+    assert_eq!(buffer[120..], [0.0; 480]);
+
+    // The input ends the scheduler with `EndPolicy::WithInput`.
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::WithInput);
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(buffer[..200], [0.5; 200]);
+    assert_eq!(buffer[200..], [0.0; 400]);
+    assert!(scheduler.has_ended());
+}
+
+#[test]
+fn test_scheduler_end_policy_after_last_event_waits_for_limiter() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::AfterLastEvent);
+    scheduler.set_master_stage(MasterStage::Limiter {
+        ceiling: 1.0,
+        lookahead: Duration::from_millis(1),
+        release: Duration::from_millis(50),
+    });
+
+    // The limiter delays the output by 48 frames, so the event ends 48 frames later.
+    assert_eq!(
+        scheduler.total_duration(),
+        Some(scheduler.frames_to_duration(298))
+    );
+
+    let samples: Vec<f32> = scheduler.collect();
+    assert_eq!(samples.len(), 596);
+    assert_eq!(samples[595], 0.25);
+}
+
+#[test]
+fn test_scheduler_end_policy_after_last_event_waits_for_input() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 1000, 0.5);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let handle = scheduler.handle();
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 50, 0.25));
+    scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 200))
+        .unwrap();
+
+    handle.set_end_policy(EndPolicy::AfterLastEvent).unwrap();
+    let samples: Vec<f32> = scheduler.by_ref().collect();
+    assert_eq!(samples.len(), 2000);
+    assert_eq!(scheduler.end_policy(), EndPolicy::AfterLastEvent);
+}
+
+#[test]
+fn test_playhead_follows_scheduler() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::AfterLastEvent);
+    let playhead = scheduler.playhead();
+    assert_eq!(playhead.sample_rate(), 48000);
+    assert_eq!(playhead.channels(), 2);
+    assert_eq!(playhead.state(), PlaybackState::Idle);
+    assert_eq!(playhead.position(), 0);
+
+    scheduler.by_ref().take(101).for_each(drop);
+    assert_eq!(playhead.state(), PlaybackState::Playing);
+    assert_eq!(playhead.position(), 101);
+    assert_eq!(playhead.frame(), 50);
+    assert_eq!(playhead.time(), scheduler.frames_to_duration(50));
+
+    let mut buffer = vec![0.0; 99];
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(playhead.position(), 200);
+
+    scheduler.by_ref().for_each(drop);
+    assert_eq!(playhead.state(), PlaybackState::Ended);
+    assert_eq!(playhead.frame(), 250);
+
+    scheduler
+        .try_seek(scheduler.frames_to_duration(10))
+        .unwrap();
+    assert_eq!(playhead.frame(), 10);
+    scheduler.next();
+    assert_eq!(playhead.state(), PlaybackState::Playing);
+}
+
+#[test]
+fn test_playhead_takes_out_limiter_delay() {
+    let source = common::constant_source(48000, 2, 50, 0.25);
+    let mut scheduler = scheduler_with((100, 0.5), [(source, vec![200])]);
+    scheduler.set_end_policy(EndPolicy::AfterLastEvent);
+    let playhead = scheduler.handle().playhead();
+    scheduler.set_master_stage(MasterStage::Limiter {
+        ceiling: 1.0,
+        lookahead: Duration::from_millis(1),
+        release: Duration::from_millis(50),
+    });
+
+    // The limiter delays the output by 48 frames, so the event starts on frame 248 of the output.
+    let samples: Vec<f32> = scheduler.by_ref().take(497).collect();
+    assert_eq!(samples[495], 0.0);
+    assert_eq!(samples[496], 0.25);
+    assert_eq!(playhead.frame(), 200);
+
+    // Read the playhead from another thread, as a renderer would.
+    let frame = std::thread::spawn(move || playhead.frame()).join().unwrap();
+    assert_eq!(frame, 200);
+}
+
+/// Returns every event notification waiting to be read from a scheduler.
+fn drain_notifications(pop: impl Fn() -> Option<EventNotification>) -> Vec<(bool, usize, u64)> {
+    std::iter::from_fn(pop)
+        .map(|notification| match notification {
+            EventNotification::EventStarted {
+                source_id, frame, ..
+            } => (true, source_id, frame),
+            EventNotification::EventEnded {
+                source_id, frame, ..
+            } => (false, source_id, frame),
+            EventNotification::EventDropped { id, .. } => panic!("event {id:?} was dropped"),
+        })
+        .collect()
+}
+
+#[test]
+fn test_single_source_scheduler_event_notifications() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 10, 0.5);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+    let first = scheduler
+        .schedule_event(PlaybackEvent::at_frame(0, 5))
+        .unwrap();
+    scheduler
+        .schedule_event(PlaybackEvent::at_frame(0, 100))
+        .unwrap();
+
+    // Events are reported on the sample they start or stop on.
+    for _ in 0..10 {
+        scheduler.next();
+    }
+    assert_eq!(scheduler.pop_event_notification(), None);
+    scheduler.next();
+    assert_eq!(
+        scheduler.pop_event_notification(),
+        Some(EventNotification::EventStarted {
+            id: first,
+            source_id: 0,
+            frame: 5,
+        })
+    );
+
+    for _ in 0..289 {
+        scheduler.next();
+    }
+    assert_eq!(
+        drain_notifications(|| scheduler.pop_event_notification()),
+        [(false, 0, 15), (true, 0, 100), (false, 0, 110)]
+    );
+
+    // Rendering a buffer reports the same events, and seeking back reports them again.
+    scheduler.try_seek(Duration::ZERO).unwrap();
+    let mut buffer = vec![0.0; 300];
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(
+        drain_notifications(|| scheduler.pop_event_notification()),
+        [
+            (true, 0, 5),
+            (false, 0, 15),
+            (true, 0, 100),
+            (false, 0, 110)
+        ]
+    );
+}
+
+#[test]
+fn test_scheduler_event_notifications() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10000, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let handle = scheduler.handle();
+
+    let open_hihat = scheduler.add_source(common::constant_source(sample_rate, channels, 100, 0.5));
+    let closed_hihat =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 10, 0.5));
+    for source_id in [open_hihat, closed_hihat] {
+        scheduler
+            .get_scheduler(source_id)
+            .unwrap()
+            .set_choke_group(Some(1));
+    }
+    scheduler
+        .schedule_many([
+            PlaybackEvent::at_frame(open_hihat, 10),
+            PlaybackEvent::at_frame(closed_hihat, 50),
+        ])
+        .unwrap();
+    let cancelled = scheduler
+        .schedule(PlaybackEvent::at_frame(open_hihat, 200))
+        .unwrap();
+
+    // The closed hi-hat chokes the open one.
+    let mut buffer = vec![0.0; 402];
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(
+        drain_notifications(|| handle.pop_event_notification()),
+        [
+            (true, open_hihat, 10),
+            (false, open_hihat, 50),
+            (true, open_hihat, 200),
+            (true, closed_hihat, 50),
+            (false, closed_hihat, 60),
+        ]
+    );
+
+    // Cancelling an event in the middle of a frame stops it on the next one.
+    scheduler.next();
+    handle.cancel_event(cancelled).unwrap();
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(
+        drain_notifications(|| scheduler.pop_event_notification()),
+        [(false, open_hihat, 202)]
+    );
+}
+
+#[test]
+fn test_scheduler_streamed_event_notifications() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10000, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let handle = scheduler.handle();
+
+    let stream_id = scheduler
+        .add_streaming_source(move || common::constant_source(sample_rate, channels, 10, 0.5))
+        .unwrap();
+    scheduler
+        .schedule(PlaybackEvent::at_frame(stream_id, 50))
+        .unwrap();
+    let cancelled = scheduler
+        .schedule(PlaybackEvent::at_frame(stream_id, 100))
+        .unwrap();
+
+    collect_frames(&mut scheduler, channels, 1);
+    wait_for_decoder();
+    collect_frames(&mut scheduler, channels, 104);
+    scheduler
+        .get_streaming_scheduler(stream_id.index())
+        .unwrap()
+        .cancel_event(cancelled);
+    collect_frames(&mut scheduler, channels, 95);
+
+    // Streams report their events to the same queue as the other sources.
+    let stream_id = stream_id.index();
+    assert_eq!(
+        drain_notifications(|| handle.pop_event_notification()),
+        [
+            (true, stream_id, 50),
+            (false, stream_id, 60),
+            (true, stream_id, 100),
+            (false, stream_id, 105),
+        ]
+    );
+}
+
+#[test]
+fn test_scheduler_event_notifications_survive_rewinds() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, 10000, 0.0);
+    let mut scheduler = Scheduler::new(input, sample_rate, channels);
+    let source_id = scheduler.add_source(common::constant_source(sample_rate, channels, 20, 0.5));
+    scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 10))
+        .unwrap();
+
+    // Scheduling rewinds the rendered block, which must not report its events twice, or report
+    // the events it rendered before they are played.
+    scheduler.by_ref().take(21).for_each(drop);
+    scheduler
+        .schedule(PlaybackEvent::at_frame(source_id, 100))
+        .unwrap();
+    assert_eq!(
+        drain_notifications(|| scheduler.pop_event_notification()),
+        [(true, source_id, 10)]
+    );
+
+    let mut buffer = vec![0.0; 1000];
+    scheduler.fill_buffer(&mut buffer);
+    assert_eq!(
+        drain_notifications(|| scheduler.pop_event_notification()),
+        [
+            (false, source_id, 30),
+            (true, source_id, 100),
+            (false, source_id, 120)
+        ]
+    );
+}
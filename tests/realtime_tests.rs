@@ -0,0 +1,288 @@
+//! Tests that the audio thread never allocates or blocks.
+//!
+//! Every test counts the allocations made by the audio thread while it renders. Running the tests
+//! with `RTSAN_ENABLE=1` also checks them with RealtimeSanitizer, which aborts on any allocation
+//! or blocking call made inside a `#[nonblocking]` function.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+use rodio_scheduler::{
+    AutomationCurve, Ducking, DuckingKey, EndPolicy, LateEventPolicy, MasterStage, PlaybackEvent,
+    Scheduler, SingleSourceScheduler, VoiceStealPolicy, simd,
+};
+
+/// An allocator that counts the allocations made by threads that are rendering audio.
+struct CountingAllocator;
+
+thread_local! {
+    static RENDERING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+impl CountingAllocator {
+    fn count(&self) {
+        let rendering = RENDERING.try_with(Cell::get).unwrap_or(false);
+
+        if rendering {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.count();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.count();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.count();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.count();
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Plays `samples` samples of a source, and returns their sum.
+fn render(source: &mut impl Iterator<Item = f32>, samples: usize) -> f32 {
+    (0..samples)
+        .map(|_| source.next().unwrap_or_default())
+        .sum()
+}
+
+/// Runs `render` as the audio thread would, and asserts that it didn't allocate.
+fn assert_realtime<R>(audio_thread: impl FnOnce() -> R) -> R {
+    ALLOCATIONS.set(0);
+    RENDERING.set(true);
+    rtsan_standalone::realtime_enter();
+
+    let result = audio_thread();
+
+    rtsan_standalone::realtime_exit();
+    RENDERING.set(false);
+
+    assert_eq!(ALLOCATIONS.get(), 0, "the audio thread allocated");
+
+    result
+}
+
+/// Creates a `Scheduler` with room for handles to add sources, and sources that use every
+/// playback feature: gains, pans, speeds, repeats, voice limits, choke groups, automation and
+/// ducking.
+fn busy_scheduler() -> Scheduler<rodio::buffer::SamplesBuffer> {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let input = common::constant_source(sample_rate, channels, sample_rate as u64, 0.0);
+    let mut scheduler = Scheduler::with_capacity(input, sample_rate, channels, 8);
+
+    let open_hihat =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 1000, 0.25));
+    let closed_hihat =
+        scheduler.add_source(common::constant_source(sample_rate, channels, 10, 0.5));
+    for source_id in [open_hihat, closed_hihat] {
+        scheduler
+            .get_scheduler(source_id)
+            .unwrap()
+            .set_choke_group(Some(1));
+    }
+
+    let chords = scheduler.add_source(common::constant_source(sample_rate, channels, 300, 0.1));
+    scheduler
+        .get_scheduler(chords)
+        .unwrap()
+        .set_voice_limit(Some(4), VoiceStealPolicy::Quietest);
+
+    let lane = scheduler
+        .get_scheduler(chords)
+        .unwrap()
+        .gain_automation_mut();
+    lane.set_point(0, 0.0, AutomationCurve::Linear);
+    lane.set_point(1000, 1.0, AutomationCurve::Exponential);
+    lane.set_point(2000, 0.5, AutomationCurve::Step);
+    // The chords duck the hi-hats, and both duck the input.
+    scheduler.set_ducking(Some(Ducking::default()));
+    for (source_id, priority) in [(open_hihat, 0), (closed_hihat, 0), (chords, 1)] {
+        scheduler
+            .get_scheduler(source_id)
+            .unwrap()
+            .set_ducking_priority(Some(priority));
+    }
+
+    let lane = scheduler.input_gain_automation_mut();
+    lane.set_point(500, 1.0, AutomationCurve::Linear);
+    lane.set_point(1500, 0.5, AutomationCurve::Step);
+
+    let events = (0..50).flat_map(|index| {
+        [
+            PlaybackEvent::at_frame(open_hihat, index * 97),
+            PlaybackEvent::at_frame(closed_hihat, index * 97 + 41),
+            PlaybackEvent {
+                gain: Some(0.5 + (index % 3) as f32 * 0.25),
+                pan: Some(-0.5),
+                speed: Some([1.0, 1.5, 0.5][index as usize % 3]),
+                repeat: Some((7, 2)),
+                ..PlaybackEvent::at_frame(chords, index * 61)
+            },
+        ]
+    });
+    scheduler.schedule_many(events).unwrap();
+
+    scheduler
+}
+
+#[test]
+fn test_scheduler_next_does_not_allocate() {
+    let mut scheduler = busy_scheduler();
+    let playhead = scheduler.playhead();
+
+    let rendered = assert_realtime(|| render(&mut scheduler, 20000));
+    assert!(rendered > 0.0);
+    assert_eq!(playhead.position(), 20000);
+}
+
+#[test]
+fn test_scheduler_fill_buffer_does_not_allocate() {
+    let mut scheduler = busy_scheduler();
+    let mut buffer = vec![0.0; 1000];
+
+    let rendered = assert_realtime(|| {
+        (0..20)
+            .map(|_| {
+                scheduler.fill_buffer(&mut buffer);
+
+                buffer.iter().sum::<f32>()
+            })
+            .sum::<f32>()
+    });
+    assert!(rendered > 0.0);
+}
+
+#[test]
+fn test_scheduler_commands_do_not_allocate() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let mut scheduler = busy_scheduler();
+    let handle = scheduler.handle();
+    assert_realtime(|| render(&mut scheduler, 1000));
+
+    // Sources are decoded by the handle, so adding them is free for the audio thread.
+    let source_id = handle
+        .add_source(common::constant_source(sample_rate, channels, 100, 0.5))
+        .unwrap();
+    handle
+        .set_late_event_policy(source_id, LateEventPolicy::Truncate)
+        .unwrap();
+    let cancelled = handle
+        .schedule_event(PlaybackEvent::at_frame(source_id, 700))
+        .unwrap();
+    for frame in [0, 600, 800] {
+        handle
+            .schedule_event(PlaybackEvent::at_frame(source_id, frame))
+            .unwrap();
+    }
+    handle.cancel_event(cancelled).unwrap();
+    handle
+        .set_master_stage(MasterStage::Limiter {
+            ceiling: 0.5,
+            lookahead: Duration::from_millis(5),
+            release: Duration::from_millis(50),
+        })
+        .unwrap();
+    handle.set_volume(source_id, 0.5).unwrap();
+    handle.set_soloed(source_id, true).unwrap();
+    handle.set_muted(0, true).unwrap();
+    handle.set_input_volume(0.25).unwrap();
+    handle
+        .set_ducking(Some(Ducking {
+            key: DuckingKey::Level { threshold: 0.1 },
+            ..Default::default()
+        }))
+        .unwrap();
+    handle.set_end_policy(EndPolicy::AfterLastEvent).unwrap();
+
+    assert_realtime(|| render(&mut scheduler, 1000));
+    assert!(handle.pop_late_event().is_some());
+    assert!(handle.pop_event_notification().is_some());
+    assert!(handle.gain_reduction() > 0.0);
+
+    handle.clear_schedule().unwrap();
+    assert_realtime(|| render(&mut scheduler, 1000));
+}
+
+#[test]
+fn test_scheduler_streaming_sources_do_not_allocate() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let mut scheduler = busy_scheduler();
+    let stream_id = scheduler
+        .add_streaming_source(move || common::constant_source(sample_rate, channels, 100, 0.5))
+        .unwrap();
+    for frame in [10, 200, 400] {
+        scheduler
+            .schedule(PlaybackEvent::at_frame(stream_id, frame))
+            .unwrap();
+    }
+
+    for _ in 0..10 {
+        assert_realtime(|| render(&mut scheduler, 200));
+
+        // Give the decoder thread time to fill the voices.
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn test_single_source_scheduler_does_not_allocate() {
+    let sample_rate = 48000_u32;
+    let channels = 2;
+
+    let source = common::constant_source(sample_rate, channels, 300, 0.1);
+    let mut scheduler = SingleSourceScheduler::new(source, sample_rate, channels);
+    scheduler.set_voice_limit(Some(4), VoiceStealPolicy::Oldest);
+    let events = (0..100).map(|index| PlaybackEvent {
+        speed: Some([1.0, 2.0][index as usize % 2]),
+        ..PlaybackEvent::at_frame(0, index * 23)
+    });
+    scheduler.schedule_events(events).unwrap();
+
+    let mut buffer = vec![0.0; 1000];
+    assert_realtime(|| {
+        render(&mut scheduler, 2000);
+        scheduler.fill_buffer(&mut buffer);
+    });
+}
+
+#[test]
+fn test_mixing_functions_do_not_allocate() {
+    let source: Vec<f32> = (0..100).map(|sample| sample as f32 / 100.0).collect();
+    let playback_schedule: Vec<u64> = (0..13).map(|event| event * 5).collect();
+    let samples = vec![0.1; 13];
+
+    assert_realtime(|| {
+        for sample_n in 0..200 {
+            let retrieved =
+                simd::retrieve_and_mix_samples(&source, &playback_schedule, (0, 13), sample_n);
+            let mixed = simd::mix_samples(&samples, retrieved);
+
+            assert!(mixed.is_some());
+        }
+    });
+}
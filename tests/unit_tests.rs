@@ -1,7 +1,11 @@
 // rodio_scheduler requires nightly rust, because portable-simd is not stabilized yet.
-#![feature(portable_simd)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
-use rodio_scheduler::simd;
+use std::time::Duration;
+
+use rodio_scheduler::automation::{AutomationCurve, AutomationLane, EXPONENTIAL_FLOOR};
+use rodio_scheduler::tempo::{TempoCurve, TempoMap, TimeSignature};
+use rodio_scheduler::{simd, time};
 
 #[test]
 fn test_mix_samples_some_input() {
@@ -43,6 +47,31 @@ fn test_mix_samples_clamping() {
     assert_eq!(result, Some(1.0f32));
 }
 
+#[test]
+fn test_sum_samples() {
+    let samples = vec![0.5f32, 1.0, 0.25, 1.0, 2.0];
+
+    assert_eq!(simd::sum_samples(&samples), 4.75f32);
+    assert_eq!(simd::sum_samples(&[]), 0.0f32);
+}
+
+#[test]
+fn test_mix_samples_with_gains() {
+    let samples = vec![1.0f32; 11];
+    let gains = [1.0f32, 0.5, 0.25, 0.0, 2.0, 1.0, 0.5, 0.25];
+    let mut output = vec![1.0f32; 13];
+
+    simd::mix_samples_with_gains(&mut output, &samples, gains);
+
+    // The pattern starts over on the 9th sample, and the samples past the input are untouched.
+    assert_eq!(
+        output,
+        vec![
+            2.0f32, 1.5, 1.25, 1.0, 3.0, 2.0, 1.5, 1.25, 2.0, 1.5, 1.25, 1.0, 1.0
+        ]
+    );
+}
+
 #[test]
 fn test_retrieve_and_mix_samples_basic() {
     let source = vec![0.1f32, 0.2, 0.3, 0.4, 0.5];
@@ -103,6 +132,353 @@ fn test_retrieve_and_mix_samples_scalar_out_of_bounds() {
     assert_eq!(result, Some(0.0f32));
 }
 
+#[test]
+fn test_retrieve_and_mix_samples_with_parameters_gain() {
+    let source = vec![0.1f32, 0.2, 0.3, 0.4, 0.5];
+    let playback_schedule = vec![0, 2, 4];
+    let parameters = simd::EventParameters {
+        gains: &[2.0, 0.5, 0.0],
+        speeds: &[1.0, 1.0, 1.0],
+        stops: &[u64::MAX; 3],
+        channels: 1,
+    };
+    let queue_index = (0, 3);
+    let sample_n = 4;
+
+    let result = simd::retrieve_and_mix_samples_with_parameters(
+        &source,
+        &playback_schedule,
+        parameters,
+        queue_index,
+        sample_n,
+    );
+
+    // The samples should be [0.5 * 2.0, 0.3 * 0.5, 0.1 * 0.0]
+    assert!((result.unwrap() - 1.15).abs() < 1e-6);
+}
+
+#[test]
+fn test_retrieve_and_mix_samples_with_parameters_unity() {
+    let source = vec![0.1f32, 0.2, 0.3, 0.4, 0.5];
+    let playback_schedule = vec![0, 2, 4];
+    let parameters = simd::EventParameters {
+        gains: &[1.0, 1.0, 1.0],
+        speeds: &[1.0, 1.0, 1.0],
+        stops: &[u64::MAX; 3],
+        channels: 1,
+    };
+    let queue_index = (0, 3);
+    let sample_n = 4;
+
+    let result = simd::retrieve_and_mix_samples_with_parameters(
+        &source,
+        &playback_schedule,
+        parameters,
+        queue_index,
+        sample_n,
+    );
+
+    // Unity parameters must match the plain mixing path exactly.
+    assert_eq!(
+        result,
+        simd::retrieve_and_mix_samples(&source, &playback_schedule, queue_index, sample_n)
+    );
+}
+
+#[test]
+fn test_retrieve_and_mix_samples_with_parameters_speed() {
+    let source = vec![0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+    let playback_schedule = vec![0];
+    let queue_index = (0, 1);
+
+    let mix = |speed: f64, sample_n: u64| {
+        let parameters = simd::EventParameters {
+            gains: &[1.0],
+            speeds: &[speed],
+            stops: &[u64::MAX; 1],
+            channels: 1,
+        };
+
+        simd::retrieve_and_mix_samples_with_parameters(
+            &source,
+            &playback_schedule,
+            parameters,
+            queue_index,
+            sample_n,
+        )
+    };
+
+    assert_eq!(mix(2.0, 3), Some(6.0));
+    assert_eq!(mix(2.0, 4), Some(0.0));
+
+    // Slower speeds interpolate between the source samples.
+    assert_eq!(mix(0.5, 3), Some(1.5));
+    assert_eq!(mix(0.25, 5), Some(1.25));
+}
+
+#[test]
+fn test_retrieve_and_mix_samples_with_parameters_interleaved() {
+    // Stereo source, where the right channel is 10 times the left channel.
+    let source = vec![1.0f32, 10.0, 2.0, 20.0, 3.0, 30.0, 4.0, 40.0];
+    let playback_schedule = vec![2];
+    let parameters = simd::EventParameters {
+        gains: &[1.0],
+        speeds: &[2.0],
+        stops: &[u64::MAX; 1],
+        channels: 2,
+    };
+    let queue_index = (0, 1);
+
+    // Frame 1 of the event, right channel. At twice the speed, it plays the frame 2 of the source.
+    let sample_n = 5;
+
+    let result = simd::retrieve_and_mix_samples_with_parameters(
+        &source,
+        &playback_schedule,
+        parameters,
+        queue_index,
+        sample_n,
+    );
+
+    assert_eq!(result, Some(30.0));
+}
+
+#[test]
+fn test_retrieve_and_mix_samples_with_parameters_empty_window() {
+    let source = vec![1.0f32, 0.0, -3.0, 0.2, 0.5];
+    let playback_schedule = vec![0];
+    let parameters = simd::EventParameters {
+        gains: &[1.0],
+        speeds: &[1.0],
+        stops: &[u64::MAX; 1],
+        channels: 1,
+    };
+
+    let result = simd::retrieve_and_mix_samples_with_parameters(
+        &source,
+        &playback_schedule,
+        parameters,
+        (1, 1),
+        4,
+    );
+
+    assert_eq!(result, None);
+}
+
+#[test]
+fn test_retrieve_and_mix_samples_with_parameters_stops() {
+    let source = vec![1.0f32; 8];
+    let playback_schedule = vec![0, 1, 2];
+    let parameters = simd::EventParameters {
+        gains: &[1.0, 1.0, 1.0],
+        speeds: &[1.0, 1.0, 1.0],
+        stops: &[3, u64::MAX, 2],
+        channels: 1,
+    };
+
+    let mix = |sample_n| {
+        simd::retrieve_and_mix_samples_with_parameters(
+            &source,
+            &playback_schedule,
+            parameters,
+            (0, 3),
+            sample_n,
+        )
+    };
+
+    // Events are silent from their stop sample on.
+    assert_eq!(mix(2), Some(2.0));
+    assert_eq!(mix(3), Some(1.0));
+}
+
+#[test]
+fn test_duration_to_frames_rounding() {
+    assert_eq!(
+        time::duration_to_frames(Duration::from_secs(2), 48000),
+        96000
+    );
+
+    // 1ms is 44.1 frames at 44.1kHz, and 1.5ms is 66.15 frames.
+    assert_eq!(
+        time::duration_to_frames(Duration::from_millis(1), 44100),
+        44
+    );
+    assert_eq!(
+        time::duration_to_frames(Duration::from_micros(1500), 44100),
+        66
+    );
+
+    // Half a frame rounds up.
+    assert_eq!(
+        time::duration_to_frames(Duration::from_micros(500), 1000),
+        1
+    );
+    assert_eq!(
+        time::duration_to_frames(Duration::from_nanos(499_999), 1000),
+        0
+    );
+}
+
+#[test]
+fn test_seconds_to_frames_rounding() {
+    assert_eq!(time::seconds_to_frames(1.5, 48000), 72000);
+    assert_eq!(time::seconds_to_frames(0.1, 44100), 4410);
+    assert_eq!(time::seconds_to_frames(1.0 / 3.0, 44100), 14700);
+    assert_eq!(time::seconds_to_frames(0.00001, 48000), 0);
+    assert_eq!(time::seconds_to_frames(-1.0, 48000), 0);
+    assert_eq!(time::seconds_to_frames(f64::NAN, 48000), 0);
+}
+
+#[test]
+fn test_beats_to_frames_rounding() {
+    assert_eq!(time::beats_to_frames(120.0, 0.0, 48000), 0);
+    assert_eq!(time::beats_to_frames(120.0, 4.0, 48000), 96000);
+
+    // A beat at 140 BPM is 18900 frames at 44.1kHz, and a sixteenth note is 4725 frames.
+    assert_eq!(time::beats_to_frames(140.0, 0.25, 44100), 4725);
+
+    // A beat at 130 BPM is 22153.85 frames at 48kHz.
+    assert_eq!(time::beats_to_frames(130.0, 1.0, 48000), 22154);
+    assert_eq!(time::beats_to_frames(130.0, 100.0, 48000), 2215385);
+}
+
+#[test]
+fn test_frames_to_duration_round_trip() {
+    for sample_rate in [22050, 44100, 48000, 96000] {
+        for frames in (0..100_000).step_by(7).chain([u32::MAX as u64]) {
+            let duration = time::frames_to_duration(frames, sample_rate);
+
+            assert_eq!(time::duration_to_frames(duration, sample_rate), frames);
+        }
+    }
+}
+
+#[test]
+fn test_tempo_map_constant_tempo() {
+    let tempo_map = TempoMap::with_ppq(120.0, 480);
+
+    assert_eq!(tempo_map.beat_to_frame(1.0, 48000), 24000);
+    assert_eq!(tempo_map.frame_to_beat(24000, 48000), 1.0);
+    assert_eq!(tempo_map.tick_to_frame(240, 48000), 12000);
+    assert_eq!(tempo_map.frame_to_tick(12000, 48000), 240);
+    assert_eq!(tempo_map.beat_to_frame(-1.0, 48000), 0);
+}
+
+#[test]
+fn test_tempo_map_tempo_changes() {
+    let mut tempo_map = TempoMap::new(120.0);
+    tempo_map.set_tempo(4.0, 60.0, TempoCurve::Constant);
+
+    assert_eq!(tempo_map.beat_to_frame(4.0, 48000), 96000);
+    assert_eq!(tempo_map.beat_to_frame(5.0, 48000), 144000);
+    assert_eq!(tempo_map.frame_to_beat(144000, 48000), 5.0);
+    assert_eq!(tempo_map.tempo_at(3.9), 120.0);
+    assert_eq!(tempo_map.tempo_at(4.0), 60.0);
+
+    // Changes can be added in any order.
+    tempo_map.set_tempo(2.0, 240.0, TempoCurve::Constant);
+    assert_eq!(tempo_map.beat_to_frame(4.0, 48000), 72000);
+    assert_eq!(tempo_map.beat_to_frame(5.0, 48000), 120000);
+
+    // Invalid tempos are ignored.
+    tempo_map.set_tempo(6.0, 0.0, TempoCurve::Constant);
+    tempo_map.set_tempo(6.0, f64::NAN, TempoCurve::Constant);
+    assert_eq!(tempo_map.tempo_at(6.0), 60.0);
+}
+
+#[test]
+fn test_tempo_map_linear_ramp() {
+    let mut tempo_map = TempoMap::new(60.0);
+    tempo_map.set_tempo(0.0, 60.0, TempoCurve::Linear);
+    tempo_map.set_tempo(4.0, 120.0, TempoCurve::Constant);
+
+    assert_eq!(tempo_map.tempo_at(2.0), 90.0);
+    assert_eq!(tempo_map.tempo_at(8.0), 120.0);
+
+    // Ramping from 60 to 120 BPM over 4 beats takes 4 * ln(2) seconds.
+    let ramp_seconds = 4.0 * 2f64.ln();
+    assert!((tempo_map.beat_to_seconds(4.0) - ramp_seconds).abs() < 1e-12);
+    assert_eq!(tempo_map.beat_to_frame(4.0, 48000), 133084);
+    assert_eq!(tempo_map.beat_to_frame(5.0, 48000), 157084);
+
+    // Seconds and beats convert back and forth inside the ramp.
+    for beat in [0.5, 1.0, 2.25, 3.999] {
+        let seconds = tempo_map.beat_to_seconds(beat);
+        assert!((tempo_map.seconds_to_beat(seconds) - beat).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_tempo_map_round_trips_on_boundaries() {
+    let mut tempo_map = TempoMap::new(137.5);
+    tempo_map.set_tempo(3.333, 97.3, TempoCurve::Linear);
+    tempo_map.set_tempo(7.1, 181.25, TempoCurve::Constant);
+    tempo_map.set_tempo(9.0, 61.7, TempoCurve::Linear);
+    tempo_map.set_tempo(13.77, 140.0, TempoCurve::Linear);
+
+    for sample_rate in [22050, 44100, 48000, 96000] {
+        for beat in [0.0, 3.333, 7.1, 9.0, 13.77] {
+            let frame = tempo_map.beat_to_frame(beat, sample_rate);
+
+            assert_eq!(tempo_map.frame_to_beat(frame, sample_rate), beat);
+            assert_eq!(
+                tempo_map.beat_to_frame(tempo_map.frame_to_beat(frame, sample_rate), sample_rate),
+                frame
+            );
+        }
+
+        // Conversions stay monotonic across every segment.
+        let frames: Vec<u64> = (0..2000)
+            .map(|tick| tempo_map.beat_to_frame(tick as f64 / 100.0, sample_rate))
+            .collect();
+        assert!(frames.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
+
+#[test]
+fn test_tempo_map_ticks_round_trip() {
+    let tempo_map = TempoMap::with_ppq(120.0, 96);
+
+    // A tick is exactly 250 frames, so every tick lands on a frame.
+    for tick in 0..1000 {
+        let frame = tempo_map.tick_to_frame(tick, 48000);
+
+        assert_eq!(frame, tick * 250);
+        assert_eq!(tempo_map.frame_to_tick(frame, 48000), tick);
+    }
+}
+
+#[test]
+fn test_tempo_map_time_signatures() {
+    let mut tempo_map = TempoMap::new(120.0);
+    tempo_map.set_time_signature(
+        2,
+        TimeSignature {
+            numerator: 6,
+            denominator: 8,
+        },
+    );
+
+    assert_eq!(tempo_map.bar_to_beat(1, 2.0), 6.0);
+    assert_eq!(tempo_map.bar_to_beat(2, 0.0), 8.0);
+    assert_eq!(tempo_map.bar_to_beat(3, 1.5), 11.75);
+    assert_eq!(tempo_map.beat_to_bar(11.75), (3, 1.5));
+    assert_eq!(tempo_map.beat_to_bar(7.0), (1, 3.0));
+    assert_eq!(tempo_map.time_signature_at(7.0), TimeSignature::default());
+    assert_eq!(tempo_map.time_signature_at(9.0).numerator, 6);
+
+    // Changing an earlier time signature moves the later ones.
+    tempo_map.set_time_signature(
+        1,
+        TimeSignature {
+            numerator: 3,
+            denominator: 4,
+        },
+    );
+    assert_eq!(tempo_map.bar_to_beat(2, 0.0), 7.0);
+    assert_eq!(tempo_map.bar_to_beat(3, 1.5), 10.75);
+}
+
 #[cfg(feature = "simd")]
 mod simd_tests {
     use rodio_scheduler::simd_utils::{SimdIter, SimdOps, gather_select_or_checked_u64};
@@ -178,3 +554,50 @@ mod simd_tests {
         assert_eq!(horizontal_add_result, 10.5 + 0.5 - 10.0 + 1.0);
     }
 }
+
+#[test]
+fn test_automation_lane_curves() {
+    let mut lane = AutomationLane::new();
+    assert_eq!(lane.value_at(0), None);
+
+    lane.set_point(100, 1.0, AutomationCurve::Linear);
+    lane.set_point(200, 0.0, AutomationCurve::Step);
+    lane.set_point(300, 0.25, AutomationCurve::Exponential);
+    lane.set_point(400, 1.0, AutomationCurve::Exponential);
+    lane.set_point(500, 0.0, AutomationCurve::Step);
+
+    // The first value is used before the first point, and the last one is held after the last.
+    assert_eq!(lane.value_at(0), Some(1.0));
+    assert_eq!(lane.value_at(150), Some(0.5));
+    assert_eq!(lane.value_at(250), Some(0.0));
+    assert_eq!(lane.value_at(300), Some(0.25));
+    assert!((lane.value_at(350).unwrap() - 0.5).abs() < 1e-6);
+    assert_eq!(lane.value_at(10000), Some(0.0));
+
+    // Exponential ramps to silence use the floor, and reach silence on the point.
+    assert!((lane.value_at(450).unwrap() - EXPONENTIAL_FLOOR.sqrt()).abs() < 1e-6);
+    assert_eq!(lane.value_at(500), Some(0.0));
+}
+
+#[test]
+fn test_automation_lane_points() {
+    let mut lane = AutomationLane::new();
+    lane.set_point(200, 0.5, AutomationCurve::Step);
+    lane.set_point(100, 1.0, AutomationCurve::Step);
+    assert!(!lane.is_empty());
+
+    // Points are kept sorted, and replaced on the same frame.
+    lane.set_point(200, 0.75, AutomationCurve::Linear);
+    lane.set_point(300, f32::NAN, AutomationCurve::Step);
+    let frames: Vec<u64> = lane.points().iter().map(|point| point.frame).collect();
+    assert_eq!(frames, [100, 200]);
+    assert_eq!(lane.value_at(250), Some(0.75));
+    assert_eq!(lane.points()[1].curve, AutomationCurve::Linear);
+
+    assert!(lane.remove_point(100));
+    assert!(!lane.remove_point(100));
+    assert_eq!(lane.value_at(0), Some(0.75));
+
+    lane.clear();
+    assert!(lane.is_empty());
+}